@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use crate::{
+    api::{
+        check_error,
+        client::{send_with_retry, HttpClient, RetryPolicy, Timeouts},
+        multipart::MultipartBody,
+        provider::Provider,
+        request_headers,
+    },
+    config::ProxyConfig,
+    error::{AppError, Result},
+};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 接口允许上传的音频大小上限
+const MAX_AUDIO_BYTES: u64 = 25 * 1024 * 1024;
+
+/// 转写为原语言文本，或翻译为英文
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioTask {
+    #[default]
+    Transcription,
+    Translation,
+}
+
+impl AudioTask {
+    fn api(&self) -> &'static str {
+        match self {
+            AudioTask::Transcription => "/audio/transcriptions",
+            AudioTask::Translation => "/audio/translations",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioResponseFormat {
+    #[default]
+    Json,
+    Text,
+    Srt,
+    VerboseJson,
+    Vtt,
+}
+
+impl AudioResponseFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AudioResponseFormat::Json => "json",
+            AudioResponseFormat::Text => "text",
+            AudioResponseFormat::Srt => "srt",
+            AudioResponseFormat::VerboseJson => "verbose_json",
+            AudioResponseFormat::Vtt => "vtt",
+        }
+    }
+
+    fn is_json(&self) -> bool {
+        matches!(
+            self,
+            AudioResponseFormat::Json | AudioResponseFormat::VerboseJson
+        )
+    }
+}
+
+fn default_audio_model() -> String {
+    "whisper-1".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AudioRequest {
+    /// 本地音频文件路径
+    pub file: String,
+    #[serde(default = "default_audio_model")]
+    pub model: String,
+    /// ISO-639-1 语言代码，只用于转写
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// 引导转写风格或提供上文的提示
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub response_format: AudioResponseFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>, // [0, 1], default: 0
+}
+
+/// 转写结果
+///
+/// `text`、`srt`、`vtt` 格式时 `text` 为接口返回的原始内容，
+/// `verbose_json` 格式时 `raw` 中包含分段等详细信息。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AudioResponse {
+    pub text: String,
+    pub language: Option<String>,
+    pub duration: Option<f64>,
+    pub raw: Option<Value>,
+}
+
+fn audio_mime(path: &Path) -> Result<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "mp3" | "mpga" | "mpeg" => Ok("audio/mpeg"),
+        "mp4" | "m4a" => Ok("audio/mp4"),
+        "wav" => Ok("audio/wav"),
+        "webm" => Ok("audio/webm"),
+        "ogg" | "oga" => Ok("audio/ogg"),
+        "flac" => Ok("audio/flac"),
+        _ => Err(AppError::Other(format!(
+            "不支持的音频格式：{}",
+            path.display()
+        ))),
+    }
+}
+
+async fn multipart_body(task: AudioTask, request: &AudioRequest) -> Result<(String, Vec<u8>)> {
+    let path = Path::new(&request.file);
+    let mime = audio_mime(path)?;
+
+    let size = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| AppError::Io(format!("无法读取音频 {}：{}", path.display(), e)))?
+        .len();
+    if size > MAX_AUDIO_BYTES {
+        return Err(AppError::Other(format!(
+            "音频超过 {} MB：{}",
+            MAX_AUDIO_BYTES / 1024 / 1024,
+            path.display()
+        )));
+    }
+
+    let data = tokio::fs::read(path).await?;
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+
+    let language = match task {
+        AudioTask::Transcription => request.language.as_deref(),
+        // 翻译的结果总是英文
+        AudioTask::Translation => None,
+    };
+    let temperature = request.temperature.map(|t| t.to_string());
+
+    Ok(MultipartBody::new()
+        .file("file", &filename, mime, &data)
+        .text("model", &request.model)
+        .optional("language", language)
+        .optional("prompt", request.prompt.as_deref())
+        .text("response_format", request.response_format.as_str())
+        .optional("temperature", temperature.as_deref())
+        .finish())
+}
+
+pub async fn audio_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+    task: AudioTask,
+    request: &AudioRequest,
+) -> Result<AudioResponse> {
+    let url = provider.url(proxy_config, task.api(), Some(&request.model))?;
+
+    let (content_type, body) = multipart_body(task, request).await?;
+
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
+    headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+
+    debug!(
+        "requesting: provider={} method=POST path={} file={}",
+        provider.name(),
+        url,
+        request.file
+    );
+
+    let rb = client
+        .post(&url)
+        .headers(headers)
+        .body(body)
+        .timeout(Timeouts::load()?.request);
+
+    let text = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
+        .text()
+        .await?;
+
+    if !request.response_format.is_json() {
+        return Ok(AudioResponse {
+            text,
+            language: None,
+            duration: None,
+            raw: None,
+        });
+    }
+
+    let response: Value = serde_json::from_str(&text)?;
+
+    debug!("response {:?}", response);
+
+    check_error(&response)?;
+
+    let verbose = request.response_format == AudioResponseFormat::VerboseJson;
+
+    Ok(AudioResponse {
+        text: response["text"].as_str().unwrap_or_default().to_string(),
+        language: response["language"].as_str().map(str::to_string),
+        duration: response["duration"].as_f64(),
+        raw: verbose.then_some(response),
+    })
+}
@@ -1,16 +1,219 @@
 use crate::{
-    api::{client::new_client, create_headers, url::api_url},
+    api::{
+        check_error,
+        client::{open_event_source, send_with_retry, HttpClient, RetryPolicy, Timeouts},
+        provider::Provider,
+        request_headers,
+    },
     config::ProxyConfig,
-    error::Result,
+    error::{AppError, Result},
 };
 use reqwest_eventsource::EventSource;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// 模型调用的函数，`arguments` 为 JSON 字符串，可能不完整或无效
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON Schema 描述的参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FunctionName {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NamedToolChoice {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionName,
+}
+
+/// `none`、`auto`、`required` 或指定调用某个函数
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Mode(String),
+    Named(NamedToolChoice),
+}
+
+/// 旧版 `functions` 接口的 `function_call`：`none`、`auto` 或指定调用某个函数
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum FunctionCallChoice {
+    Mode(String),
+    Named(FunctionName),
+}
+
+/// 图片地址，可以是 http(s) 链接或 `data:` URL；
+/// 前端传入的本地路径在发送前由后端转换为 base64 编码的 `data:` URL
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ImageUrl {
+    pub url: String,
+    /// `low`、`high` 或 `auto`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+/// 消息内容：纯文本或文本和图片组成的数组
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::Text(String::new())
+    }
+}
+
+impl Content {
+    /// 所有文本部分拼接的内容
+    pub fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    pub fn images(&self) -> Vec<&ImageUrl> {
+        match self {
+            Content::Text(_) => Vec::new(),
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::ImageUrl { image_url } => Some(image_url),
+                    ContentPart::Text { .. } => None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn images_mut(&mut self) -> Vec<&mut ImageUrl> {
+        match self {
+            Content::Text(_) => Vec::new(),
+            Content::Parts(parts) => parts
+                .iter_mut()
+                .filter_map(|p| match p {
+                    ContentPart::ImageUrl { image_url } => Some(image_url),
+                    ContentPart::Text { .. } => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 调用工具时 `content` 为 null，反序列化为空字符串
+fn null_as_empty<'de, D>(deserializer: D) -> std::result::Result<Content, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<Content>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub content: Content,
+    /// `tool` 消息对应的函数名
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// `tool` 消息回复的调用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// 旧版 `functions` 接口的调用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCall>,
+}
+
+impl Message {
+    pub fn new(role: &str, content: &str) -> Self {
+        Message {
+            role: role.to_string(),
+            content: Content::Text(content.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+            function_call: None,
+        }
+    }
+
+    /// 在文本之后附加图片，没有图片时保持纯文本
+    pub fn with_images(mut self, images: Vec<ImageUrl>) -> Self {
+        if images.is_empty() {
+            return self;
+        }
+
+        let mut parts = vec![ContentPart::Text {
+            text: self.content.text(),
+        }];
+        parts.extend(
+            images
+                .into_iter()
+                .map(|image_url| ContentPart::ImageUrl { image_url }),
+        );
+        self.content = Content::Parts(parts);
+        self
+    }
+
+    /// 回复中调用的工具，旧版 `function_call` 转换为 id 为空的调用
+    pub fn calls(&self) -> Vec<ToolCall> {
+        match (&self.tool_calls, &self.function_call) {
+            (Some(calls), _) => calls.clone(),
+            (None, Some(function)) => vec![ToolCall {
+                id: String::new(),
+                kind: "function".to_string(),
+                function: function.clone(),
+            }],
+            (None, None) => Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -42,20 +245,52 @@ pub struct ChatGPTRequest {
     // pub logit_bias: Option<HashMap<>> // TODO: 待处理
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// 旧版接口，新模型应使用 `tools`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub functions: Option<Vec<FunctionDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCallChoice>,
+}
+
+impl ChatGPTRequest {
+    pub fn new(model: &str, messages: Vec<Message>) -> Self {
+        ChatGPTRequest {
+            model: model.to_string(),
+            messages,
+            temperature: None,
+            top_p: None,
+            n: None,
+            stream: None,
+            stop: None,
+            max_tokens: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            user: None,
+            tools: None,
+            tool_choice: None,
+            functions: None,
+            function_call: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Choice {
     pub index: u32,
     pub message: Message,
-    pub finish_reason: String,
+    /// 部分兼容服务返回 `null`
+    pub finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Usage {
-    pub prompt_tokens: u16,
-    pub completion_tokens: u16,
-    pub total_tokens: u16,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
 }
 
 // ChatGPT API响应
@@ -66,66 +301,83 @@ pub struct ChatGPTResponse {
     pub model: String,
     pub created: u64,
     pub choices: Vec<Choice>,
-    pub usage: Usage,
+    /// 部分兼容服务不返回用量
+    pub usage: Option<Usage>,
+}
+
+impl ChatGPTResponse {
+    /// 第一个回复，部分服务可能返回空的 `choices`
+    pub fn choice(&self) -> Result<&Choice> {
+        self.choices
+            .first()
+            .ok_or_else(|| AppError::Parse("响应中没有回复".to_string()))
+    }
 }
 
-const API: &str = "/v1/chat/completions";
+const API: &str = "/chat/completions";
 
 // ChatGPT API客户端
 pub async fn chat_gpt_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
     proxy_config: &ProxyConfig,
     api_key: &str,
-    request: ChatGPTRequest,
+    request: &ChatGPTRequest,
 ) -> Result<ChatGPTResponse> {
-    let client = new_client(proxy_config)?;
-
-    let url = api_url(proxy_config, API);
+    let url = provider.url(proxy_config, API, Some(&request.model))?;
 
-    let mut headers = create_headers(api_key);
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
     headers.append("Content-Type", "application/json".parse().unwrap());
 
-    let response = client
+    let rb = client
         .post(&url)
         .headers(headers)
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?
+        .json(request)
+        .timeout(Timeouts::load()?.request);
+
+    let response = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
         .json::<Value>()
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
     debug!("response {:?}", response);
 
-    let resp: ChatGPTResponse = serde_json::from_value(response).map_err(|e| e.to_string())?;
+    check_error(&response)?;
+
+    let resp: ChatGPTResponse = serde_json::from_value(response)?;
+    resp.choice()?;
 
     Ok(resp)
 }
 
 // ChatGPT API客户端
 pub async fn chat_gpt_steam_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
     proxy_config: &ProxyConfig,
     api_key: &str,
-    request: ChatGPTRequest,
+    request: &ChatGPTRequest,
 ) -> Result<EventSource> {
-    let client = new_client(proxy_config)?;
-
-    let url = api_url(proxy_config, API);
+    let url = provider.url(proxy_config, API, Some(&request.model))?;
 
-    let mut headers = create_headers(api_key);
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
     headers.append("Content-Type", "application/json".parse().unwrap());
 
-    debug!("requesting: method=POST path={}", url);
+    debug!(
+        "requesting: provider={} method=POST path={}",
+        provider.name(),
+        url
+    );
 
-    let rb = client.post(url).headers(headers).json(&request);
+    let rb = client.post(&url).headers(headers).json(request);
 
-    match EventSource::new(rb) {
-        Ok(es) => Ok(es),
-        Err(e) => {
+    // 流式响应持续时间不确定，不设置整体超时，由首个 chunk 和 chunk 间隔的超时控制
+    open_event_source(&RetryPolicy::load()?, rb, Timeouts::load()?.first_token)
+        .await
+        .map_err(|e| {
             error!("{}", e);
-            return Err(e.to_string());
-        }
-    }
+            e
+        })
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -134,6 +386,105 @@ pub struct MessageChunkChoiceDelta {
     pub role: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCallDelta>,
+}
+
+/// 流式响应中函数名和参数分多个 chunk 返回
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FunctionCallDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+/// 同一个调用的 delta 以 `index` 关联，只有第一个 delta 包含 `id`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ToolCallDelta {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<FunctionCallDelta>,
+}
+
+/// 一次回复中工具调用的最大数量，超过的 index 视为无效
+const MAX_TOOL_CALLS: usize = 128;
+
+/// 合并流式响应中的工具调用 delta
+#[derive(Debug, Default)]
+pub struct ToolCallMerger {
+    calls: Vec<ToolCall>,
+}
+
+fn merge_function(function: &mut FunctionCall, delta: &FunctionCallDelta) {
+    if let Some(name) = &delta.name {
+        function.name.push_str(name);
+    }
+
+    if let Some(arguments) = &delta.arguments {
+        function.arguments.push_str(arguments);
+    }
+}
+
+impl ToolCallMerger {
+    pub fn push(&mut self, delta: &MessageChunkChoiceDelta) {
+        for call in delta.tool_calls.iter().flatten() {
+            // index 从 0 开始连续递增，跳过的 index 来自有问题的服务端
+            if call.index > self.calls.len() || call.index >= MAX_TOOL_CALLS {
+                warn!(
+                    "忽略无效的工具调用 index：{}，已有 {} 个调用",
+                    call.index,
+                    self.calls.len()
+                );
+                continue;
+            }
+
+            if call.index == self.calls.len() {
+                self.calls.push(ToolCall {
+                    id: String::new(),
+                    kind: "function".to_string(),
+                    function: FunctionCall::default(),
+                });
+            }
+
+            let merged = &mut self.calls[call.index];
+
+            if let Some(id) = &call.id {
+                merged.id.push_str(id);
+            }
+
+            if let Some(kind) = &call.kind {
+                merged.kind.clone_from(kind);
+            }
+
+            if let Some(function) = &call.function {
+                merge_function(&mut merged.function, function);
+            }
+        }
+
+        // 旧版 function_call 只有一个调用
+        if let Some(function) = &delta.function_call {
+            if self.calls.is_empty() {
+                self.calls.push(ToolCall {
+                    id: String::new(),
+                    kind: "function".to_string(),
+                    function: FunctionCall::default(),
+                });
+            }
+
+            merge_function(&mut self.calls[0].function, function);
+        }
+    }
+
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.calls
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -151,3 +502,102 @@ pub struct MessageChunk {
     pub model: String,
     pub choices: Vec<MessageChunkChoice>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(json: Value) -> MessageChunkChoiceDelta {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn merger_joins_tool_call_deltas_by_index() {
+        let mut merger = ToolCallMerger::default();
+
+        merger.push(&delta(serde_json::json!({
+            "role": "assistant",
+            "tool_calls": [{
+                "index": 0,
+                "id": "call_1",
+                "type": "function",
+                "function": {"name": "calculator", "arguments": ""}
+            }]
+        })));
+        merger.push(&delta(serde_json::json!({
+            "tool_calls": [{"index": 0, "function": {"arguments": "{\"expression\":"}}]
+        })));
+        merger.push(&delta(serde_json::json!({
+            "tool_calls": [{
+                "index": 1,
+                "id": "call_2",
+                "type": "function",
+                "function": {"name": "current_time", "arguments": "{}"}
+            }]
+        })));
+        merger.push(&delta(serde_json::json!({
+            "tool_calls": [{"index": 0, "function": {"arguments": "\"1+2\"}"}}]
+        })));
+        merger.push(&delta(serde_json::json!({ "content": "忽略的文本" })));
+
+        let calls = merger.finish();
+
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.name, "calculator");
+        assert_eq!(calls[0].function.arguments, r#"{"expression":"1+2"}"#);
+        assert_eq!(calls[1].id, "call_2");
+        assert_eq!(calls[1].function.name, "current_time");
+        assert_eq!(calls[1].function.arguments, "{}");
+    }
+
+    #[test]
+    fn merger_joins_legacy_function_call() {
+        let mut merger = ToolCallMerger::default();
+
+        merger.push(&delta(serde_json::json!({
+            "function_call": {"name": "calculator", "arguments": "{\"expr"}
+        })));
+        merger.push(&delta(serde_json::json!({
+            "function_call": {"arguments": "ession\":\"2\"}"}
+        })));
+
+        let calls = merger.finish();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].kind, "function");
+        assert_eq!(calls[0].function.name, "calculator");
+        assert_eq!(calls[0].function.arguments, r#"{"expression":"2"}"#);
+    }
+
+    #[test]
+    fn merger_ignores_invalid_index() {
+        let mut merger = ToolCallMerger::default();
+
+        merger.push(&delta(serde_json::json!({
+            "tool_calls": [{"index": 4000000000u64, "function": {"name": "calculator"}}]
+        })));
+        merger.push(&delta(serde_json::json!({
+            "tool_calls": [{"index": 1, "function": {"name": "calculator"}}]
+        })));
+        assert!(merger.calls.is_empty());
+
+        merger.push(&delta(serde_json::json!({
+            "tool_calls": [{"index": 0, "function": {"name": "current_time"}}]
+        })));
+
+        let calls = merger.finish();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "current_time");
+    }
+
+    #[test]
+    fn merger_without_tool_calls() {
+        let mut merger = ToolCallMerger::default();
+
+        merger.push(&delta(serde_json::json!({ "content": "你好" })));
+
+        assert!(merger.finish().is_empty());
+    }
+}
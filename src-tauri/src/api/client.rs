@@ -1,28 +1,561 @@
-use crate::{config::ProxyConfig, error::Result};
-use reqwest::Client;
+use std::{sync::Mutex, time::Duration};
 
-pub(crate) fn new_http_client_with_proxy(proxy: &str) -> Result<Client> {
-    debug!("使用的代理：{}", proxy);
+use crate::{
+    api::parse_error,
+    config::{self, HttpConfig, ProxyConfig, RetryConfig, TimeoutConfig, TlsConfig},
+    error::{AppError, Result},
+    keys,
+};
+use futures_util::StreamExt;
+use rand::Rng;
+use reqwest::{header::HeaderMap, Certificate, Client, ClientBuilder, RequestBuilder, Response};
+use reqwest_eventsource::{retry::Never, Event, EventSource};
+use url::{Origin, Url};
 
-    let proxy = reqwest::Proxy::all(proxy).map_err(|e| e.to_string())?;
-    let client = reqwest::Client::builder()
-        .proxy(proxy)
-        .build()
-        .map_err(|e| e.to_string())?;
+fn client_builder(timeouts: &Timeouts, http: &HttpConfig) -> ClientBuilder {
+    let keepalive = match http.tcp_keepalive_ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
 
-    Ok(client)
+    reqwest::Client::builder()
+        .connect_timeout(timeouts.connect)
+        .pool_max_idle_per_host(http.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_millis(http.pool_idle_timeout_ms))
+        .tcp_keepalive(keepalive)
+        .user_agent(&http.user_agent)
 }
 
-pub(crate) fn new_http_client() -> Result<Client> {
-    let client = reqwest::Client::new();
+/// 从环境变量中读取代理，同时支持大写和小写的变量名
+fn env_proxy(name: &str) -> Option<String> {
+    [name.to_uppercase(), name.to_string()]
+        .into_iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.trim().is_empty()))
+}
+
+/// `system` 方式使用的代理，`NO_PROXY` 与配置中的 `no_proxy` 合并
+fn system_proxies(no_proxy: &[String]) -> Result<Vec<reqwest::Proxy>> {
+    let mut bypass: Vec<String> = env_proxy("no_proxy")
+        .map(|v| v.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default();
+    bypass.extend(no_proxy.iter().cloned());
+
+    let mut proxies = Vec::new();
+
+    for name in ["https_proxy", "http_proxy", "all_proxy"] {
+        let url = match env_proxy(name) {
+            Some(url) => url,
+            None => continue,
+        };
+
+        debug!("使用环境变量中的代理：{}", name);
+
+        let proxy = match name {
+            "https_proxy" => reqwest::Proxy::https(&url),
+            "http_proxy" => reqwest::Proxy::http(&url),
+            _ => reqwest::Proxy::all(&url),
+        }
+        .map_err(|e| AppError::Proxy(format!("{} 无效：{}", name, e)))?;
+
+        proxies.push(proxy.no_proxy(reqwest::NoProxy::from_string(&bypass.join(","))));
+    }
+
+    if proxies.is_empty() {
+        warn!("未在环境变量中找到代理，将直接连接");
+    }
+
+    Ok(proxies)
+}
+
+/// 根据代理配置创建代理，`reverse-proxy` 方式不设置代理
+fn proxies(proxy_config: &ProxyConfig) -> Result<Vec<reqwest::Proxy>> {
+    match proxy_config.method.as_str() {
+        "proxy" => {
+            let proxy = proxy_config
+                .proxy
+                .as_ref()
+                .ok_or_else(|| AppError::Proxy("未配置代理".to_string()))?;
+
+            let url = proxy.url()?;
+            debug!(
+                "使用的代理：{}://{}:{}",
+                url.scheme(),
+                proxy.host,
+                proxy.port
+            );
+
+            // 错误信息中不包含代理地址，避免认证信息出现在日志中
+            let proxy = reqwest::Proxy::all(url)
+                .map_err(|e| AppError::Proxy(e.without_url().to_string()))?
+                .no_proxy(reqwest::NoProxy::from_string(
+                    &proxy_config.no_proxy.join(","),
+                ));
+
+            Ok(vec![proxy])
+        }
+        "system" => system_proxies(&proxy_config.no_proxy),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn read_certificates(path: &str) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)
+        .map_err(|e| AppError::Config(format!("读取证书文件 {} 失败：{}", path, e)))?;
+
+    Certificate::from_pem_bundle(&pem)
+        .map_err(|e| AppError::Config(format!("解析证书文件 {} 失败：{}", path, e)))
+}
+
+fn add_ca_files(mut builder: ClientBuilder, tls: &TlsConfig) -> Result<ClientBuilder> {
+    for path in &tls.ca_files {
+        for cert in read_certificates(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+
+        debug!("已添加 CA 证书：{}", path);
+    }
+
+    Ok(builder)
+}
+
+/// 反向代理单独使用的客户端，没有为反向代理配置证书时返回 `None`
+///
+/// 只信任指定证书或不校验证书的设置只对这个客户端生效，访问其他主机的客户端保持默认的校验。
+fn reverse_proxy_client(
+    timeouts: &Timeouts,
+    http: &HttpConfig,
+    proxy_config: &ProxyConfig,
+    tls: &TlsConfig,
+) -> Result<Option<(Origin, Client)>> {
+    if proxy_config.method != "reverse-proxy" {
+        return Ok(None);
+    }
+
+    let url = match proxy_config.reverse_proxy_url() {
+        Ok(url) => url,
+        Err(_) => return Ok(None),
+    };
+    let host = url.host_str().unwrap_or_default();
+
+    let accept_invalid_certs = tls.accept_invalid_certs_hosts.iter().any(|h| h == host);
+    if tls.reverse_proxy_cert.is_none() && !accept_invalid_certs {
+        return Ok(None);
+    }
+
+    let mut builder = add_ca_files(client_builder(timeouts, http), tls)?;
+
+    if let Some(path) = &tls.reverse_proxy_cert {
+        // 只信任反向代理的证书，其他证书（包括系统根证书）都会被拒绝
+        builder = builder.tls_built_in_root_certs(false);
+
+        for cert in read_certificates(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+
+        info!("反向代理只信任证书：{}", path);
+    }
+
+    if accept_invalid_certs {
+        warn!(
+            "！！！已关闭 {} 的证书校验，连接可能被窃听或篡改，只应在本地测试时使用！！！",
+            host
+        );
+
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    Ok(Some((url.origin(), builder.build()?)))
+}
+
+/// HTTP 客户端，发往反向代理的请求使用单独的客户端
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    reverse_proxy: Option<(Origin, Client)>,
+}
+
+impl HttpClient {
+    fn client_for(&self, url: &str) -> &Client {
+        match &self.reverse_proxy {
+            Some((origin, client)) if Url::parse(url).is_ok_and(|u| &u.origin() == origin) => {
+                client
+            }
+            _ => &self.client,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client_for(url).get(url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client_for(url).post(url)
+    }
+}
+
+pub fn new_client(proxy_config: &ProxyConfig) -> Result<HttpClient> {
+    let (timeout, http, tls) = match config::read_config()? {
+        Some(c) => (
+            c.timeout.unwrap_or_default(),
+            c.http.unwrap_or_default(),
+            c.tls.unwrap_or_default(),
+        ),
+        None => Default::default(),
+    };
+
+    let timeouts = Timeouts::from(&timeout);
+
+    let mut builder = add_ca_files(client_builder(&timeouts, &http), &tls)?;
+
+    for proxy in proxies(proxy_config)? {
+        builder = builder.proxy(proxy);
+    }
+
+    Ok(HttpClient {
+        client: builder.build()?,
+        reverse_proxy: reverse_proxy_client(&timeouts, &http, proxy_config, &tls)?,
+    })
+}
+
+/// 共享的 HTTP 客户端，以代理配置区分
+///
+/// 代理配置不变时复用同一个客户端及其连接池，避免每次请求重新建立连接和 TLS 握手。
+#[derive(Default)]
+pub struct SharedClient {
+    cached: Mutex<Option<(ProxyConfig, HttpClient)>>,
+}
+
+impl SharedClient {
+    /// 获取与代理配置对应的客户端，代理配置改变时重新创建
+    pub fn get(&self, proxy_config: &ProxyConfig) -> Result<HttpClient> {
+        let mut cached = self.cached.lock().unwrap();
+
+        if let Some((key, client)) = cached.as_ref() {
+            if key == proxy_config {
+                return Ok(client.clone());
+            }
+        }
+
+        debug!("创建 HTTP 客户端：{:?}", proxy_config);
+
+        let client = new_client(proxy_config)?;
+        *cached = Some((proxy_config.clone(), client.clone()));
+
+        Ok(client)
+    }
+
+    /// 丢弃当前客户端，下次请求时按新配置创建
+    pub fn reset(&self) {
+        let mut cached = self.cached.lock().unwrap();
+
+        if cached.take().is_some() {
+            debug!("已重置 HTTP 客户端");
+        }
+    }
+}
+
+/// 各阶段的超时时间
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub request: Duration,
+    pub model: Duration,
+    pub first_token: Duration,
+    pub chunk: Duration,
+}
+
+impl From<&TimeoutConfig> for Timeouts {
+    fn from(config: &TimeoutConfig) -> Self {
+        Timeouts {
+            connect: Duration::from_millis(config.connect_ms),
+            request: Duration::from_millis(config.request_ms),
+            model: Duration::from_millis(config.model_ms),
+            first_token: Duration::from_millis(config.first_token_ms),
+            chunk: Duration::from_millis(config.chunk_ms),
+        }
+    }
+}
+
+impl Timeouts {
+    /// 从配置文件中读取超时时间
+    pub fn load() -> Result<Self> {
+        let config = config::read_config()?;
+
+        let timeout = config.and_then(|c| c.timeout).unwrap_or_default();
+
+        Ok((&timeout).into())
+    }
+}
+
+/// 请求失败后的重试策略：指数退避 + 随机抖动
+#[derive(Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(config: &RetryConfig) -> Self {
+        RetryPolicy {
+            max_attempts: config.max_attempts.max(1),
+            base_delay: Duration::from_millis(config.base_delay_ms),
+            max_delay: Duration::from_millis(config.max_delay_ms),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 从配置文件中读取重试策略
+    pub fn load() -> Result<Self> {
+        let config = config::read_config()?;
+
+        let retry = config.and_then(|c| c.retry).unwrap_or_default();
+
+        Ok((&retry).into())
+    }
+
+    /// 第 `attempt` 次（从 1 开始）失败后的等待时间，服务端给出的等待时间优先
+    fn delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+        if let Some(delay) = headers.and_then(server_delay) {
+            return delay.min(self.max_delay);
+        }
+
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+
+        // 在 [exp / 2, exp] 内随机，避免多个请求同时重试
+        let half = exp / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// 解析 OpenAI 限流重置时间，如 `20ms`、`1s`、`6m0s`、`1h2m3.5s`
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0f64;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let n: f64 = number.parse().ok()?;
+        number.clear();
+
+        total += match c {
+            'h' => n * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                n / 1000.0
+            }
+            'm' => n * 60.0,
+            's' => n,
+            _ => return None,
+        };
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    // 数值过大时无法表示为 Duration，视为无效
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// 从 `Retry-After` 或 `x-ratelimit-reset-*` 响应头中获取等待时间
+fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(seconds) = header("retry-after").and_then(|v| v.trim().parse::<u64>().ok()) {
+        return Some(Duration::from_secs(seconds));
+    }
 
-    Ok(client)
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .iter()
+        .filter_map(|name| header(name).and_then(parse_reset_duration))
+        .max()
 }
 
-pub fn new_client(proxy_config: &ProxyConfig) -> Result<Client> {
-    if proxy_config.method == "proxy" {
-        new_http_client_with_proxy(&proxy_config.to_string())
-    } else {
-        new_http_client()
+pub(crate) async fn status_error(response: Response) -> AppError {
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+
+    parse_error(status, &body)
+}
+
+/// 被限流且还有其他 key 可用时不重试，由 `KeyStore::run` 换用下一个 key
+fn rotate_key(err: &AppError) -> bool {
+    err.kind() == "rate_limit" && keys::has_next_key()
+}
+
+/// 发送请求，遇到 429 / 5xx 或连接错误时按策略重试
+///
+/// 额度不足（insufficient_quota）的 429 不会重试。
+pub async fn send_with_retry(policy: &RetryPolicy, rb: RequestBuilder) -> Result<Response> {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let request = rb
+            .try_clone()
+            .ok_or_else(|| AppError::Other("请求无法重试：请求体不可复制".to_string()))?;
+
+        let (delay, err) = match request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                // 响应头在读取响应体后不可用，需先计算等待时间
+                let delay = policy.delay(attempt, Some(response.headers()));
+                (delay, status_error(response).await)
+            }
+            Err(e) => (policy.delay(attempt, None), AppError::from(e)),
+        };
+
+        if attempt >= policy.max_attempts || !err.retryable() || rotate_key(&err) {
+            return Err(err);
+        }
+
+        warn!(
+            "请求失败：{}，{:?} 后进行第 {} 次重试",
+            err,
+            delay,
+            attempt + 1
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// 打开事件流，连接建立前的失败按策略重试
+///
+/// 连接建立后不再重试，避免重复接收已经发送给前端的内容。
+/// 超过 `timeout` 仍未收到响应头视为超时。
+pub async fn open_event_source(
+    policy: &RetryPolicy,
+    rb: RequestBuilder,
+    timeout: Duration,
+) -> Result<EventSource> {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let request = rb
+            .try_clone()
+            .ok_or_else(|| AppError::Other("请求无法重试：请求体不可复制".to_string()))?;
+
+        let mut es = EventSource::new(request)
+            .map_err(|e| AppError::Other(format!("无法创建事件流：{}", e)))?;
+        // 由本策略控制重试，EventSource 自身的重连会在中途重复请求
+        es.set_retry_policy(Box::new(Never));
+
+        let (delay, err) = match tokio::time::timeout(timeout, es.next()).await {
+            Ok(Some(Ok(Event::Open))) => return Ok(es),
+            Ok(Some(Ok(Event::Message(_)))) => {
+                es.close();
+                return Err(AppError::Other("事件流在连接建立前收到消息".to_string()));
+            }
+            Ok(Some(Err(reqwest_eventsource::Error::InvalidStatusCode(_, response)))) => {
+                let delay = policy.delay(attempt, Some(response.headers()));
+                (delay, status_error(response).await)
+            }
+            Ok(Some(Err(e))) => (policy.delay(attempt, None), AppError::from(e)),
+            Ok(None) => return Err(AppError::Network("事件流已关闭".to_string())),
+            Err(_) => (
+                policy.delay(attempt, None),
+                AppError::Timeout(format!("{:?} 内未收到响应", timeout)),
+            ),
+        };
+
+        es.close();
+
+        if attempt >= policy.max_attempts || !err.retryable() || rotate_key(&err) {
+            return Err(err);
+        }
+
+        warn!(
+            "打开事件流失败：{}，{:?} 后进行第 {} 次重试",
+            err,
+            delay,
+            attempt + 1
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn parse_reset_duration_formats() {
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(
+            parse_reset_duration("500ms"),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(parse_reset_duration("1m30s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(
+            parse_reset_duration(" 20ms "),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[test]
+    fn parse_reset_duration_rejects_invalid() {
+        assert_eq!(parse_reset_duration("30"), None);
+        assert_eq!(parse_reset_duration("1x"), None);
+        assert_eq!(parse_reset_duration("s"), None);
+        assert_eq!(parse_reset_duration(&format!("{}h", "9".repeat(400))), None);
+        assert_eq!(parse_reset_duration(&format!("{}h", "9".repeat(30))), None);
+    }
+
+    #[test]
+    fn delay_caps_server_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("2h"));
+        assert_eq!(policy.delay(1, Some(&headers)), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn server_delay_prefers_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("1m30s"),
+        );
+        assert_eq!(server_delay(&headers), Some(Duration::from_secs(90)));
+
+        headers.insert("retry-after", HeaderValue::from_static("5"));
+        assert_eq!(server_delay(&headers), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn server_delay_uses_longest_reset() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("500ms"),
+        );
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("2s"));
+        assert_eq!(server_delay(&headers), Some(Duration::from_secs(2)));
+
+        assert_eq!(server_delay(&HeaderMap::new()), None);
     }
 }
@@ -0,0 +1,115 @@
+use crate::{
+    api::{
+        check_error,
+        client::{send_with_retry, HttpClient, RetryPolicy, Timeouts},
+        provider::Provider,
+        request_headers,
+    },
+    config::ProxyConfig,
+    error::Result,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const API: &str = "/embeddings";
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingRequest<'a> {
+    pub model: &'a str,
+    pub input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingData {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingResponse {
+    pub model: String,
+    pub data: Vec<EmbeddingData>,
+    /// 部分兼容 OpenAI 的服务不返回用量
+    pub usage: Option<EmbeddingUsage>,
+}
+
+impl EmbeddingResponse {
+    /// 按输入的顺序排列的向量
+    pub fn into_vectors(mut self) -> Vec<Vec<f32>> {
+        self.data.sort_by_key(|d| d.index);
+        self.data.into_iter().map(|d| d.embedding).collect()
+    }
+}
+
+pub async fn embeddings_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+    request: &EmbeddingRequest<'_>,
+) -> Result<EmbeddingResponse> {
+    let url = provider.url(proxy_config, API, Some(request.model))?;
+
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
+    headers.append("Content-Type", "application/json".parse().unwrap());
+
+    debug!(
+        "requesting: provider={} method=POST path={} inputs={}",
+        provider.name(),
+        url,
+        request.input.len()
+    );
+
+    let rb = client
+        .post(&url)
+        .headers(headers)
+        .json(request)
+        .timeout(Timeouts::load()?.request);
+
+    let response = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
+        .json::<Value>()
+        .await?;
+
+    check_error(&response)?;
+
+    let response: EmbeddingResponse = serde_json::from_value(response)?;
+
+    match &response.usage {
+        Some(usage) => debug!(
+            "embedding: model={} prompt_tokens={} total_tokens={}",
+            response.model, usage.prompt_tokens, usage.total_tokens
+        ),
+        None => debug!("embedding: model={}", response.model),
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_without_usage() {
+        let response: EmbeddingResponse = serde_json::from_str(
+            r#"{
+                "model": "text-embedding-3-small",
+                "data": [
+                    { "index": 1, "embedding": [0.5] },
+                    { "index": 0, "embedding": [0.25] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(response.usage.is_none());
+        assert_eq!(response.into_vectors(), vec![vec![0.25], vec![0.5]]);
+    }
+}
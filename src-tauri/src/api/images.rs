@@ -0,0 +1,220 @@
+use std::path::Path;
+
+use crate::{
+    api::{
+        check_error,
+        client::{send_with_retry, HttpClient, RetryPolicy, Timeouts},
+        multipart::MultipartBody,
+        provider::Provider,
+        request_headers,
+    },
+    config::ProxyConfig,
+    error::{AppError, Result},
+    vision::image_mime,
+};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// 生成新图片、按提示编辑图片或生成图片的变体
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImageTask {
+    Generation {
+        prompt: String,
+    },
+    Edit {
+        /// 本地图片路径
+        image: String,
+        /// 透明区域为需要编辑的部分，不指定时使用图片本身的透明区域
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mask: Option<String>,
+        prompt: String,
+    },
+    Variation {
+        image: String,
+    },
+}
+
+impl ImageTask {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ImageTask::Generation { .. } => "generation",
+            ImageTask::Edit { .. } => "edit",
+            ImageTask::Variation { .. } => "variation",
+        }
+    }
+
+    pub fn prompt(&self) -> Option<&str> {
+        match self {
+            ImageTask::Generation { prompt } | ImageTask::Edit { prompt, .. } => Some(prompt),
+            ImageTask::Variation { .. } => None,
+        }
+    }
+
+    fn api(&self) -> &'static str {
+        match self {
+            ImageTask::Generation { .. } => "/images/generations",
+            ImageTask::Edit { .. } => "/images/edits",
+            ImageTask::Variation { .. } => "/images/variations",
+        }
+    }
+
+    /// 编辑和变体只有 dall-e-2 支持
+    fn default_model(&self) -> &'static str {
+        match self {
+            ImageTask::Generation { .. } => "dall-e-3",
+            ImageTask::Edit { .. } | ImageTask::Variation { .. } => "dall-e-2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ImageOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 生成的数量，dall-e-3 只能为 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u8>,
+    /// `256x256`、`512x512`、`1024x1024`、`1792x1024`、`1024x1792` 等
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// `standard`、`hd`，或 gpt-image 模型的 `low`、`medium`、`high`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    /// dall-e-3 的 `vivid` 或 `natural`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+}
+
+impl ImageOptions {
+    pub fn model<'a>(&'a self, task: &ImageTask) -> &'a str {
+        self.model
+            .as_deref()
+            .unwrap_or_else(|| task.default_model())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImageData {
+    /// dall-e 模型默认返回一小时内有效的链接
+    pub url: Option<String>,
+    pub b64_json: Option<String>,
+    /// dall-e-3 改写后实际使用的提示
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImagesResponse {
+    pub created: u64,
+    pub data: Vec<ImageData>,
+}
+
+/// 只有 dall-e 模型接受 `response_format`，gpt-image 模型总是返回 base64
+fn response_format(model: &str) -> Option<&'static str> {
+    model.starts_with("dall-e").then_some("b64_json")
+}
+
+async fn read_image(path: &str) -> Result<(String, &'static str, Vec<u8>)> {
+    let path = Path::new(path);
+    let mime = image_mime(path)?;
+    let data = tokio::fs::read(path)
+        .await
+        .map_err(|e| AppError::Io(format!("无法读取图片 {}：{}", path.display(), e)))?;
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    Ok((filename, mime, data))
+}
+
+async fn multipart_body(task: &ImageTask, options: &ImageOptions) -> Result<(String, Vec<u8>)> {
+    let model = options.model(task);
+    let n = options.n.map(|n| n.to_string());
+
+    let (image, mask, prompt) = match task {
+        ImageTask::Edit {
+            image,
+            mask,
+            prompt,
+        } => (image, mask.as_ref(), Some(prompt.as_str())),
+        ImageTask::Variation { image } => (image, None, None),
+        ImageTask::Generation { .. } => unreachable!("生成图片不使用 multipart"),
+    };
+
+    let (filename, mime, data) = read_image(image).await?;
+    let mut body = MultipartBody::new()
+        .file("image", &filename, mime, &data)
+        .text("model", model)
+        .optional("prompt", prompt)
+        .optional("n", n.as_deref())
+        .optional("size", options.size.as_deref())
+        .optional("quality", options.quality.as_deref())
+        .optional("response_format", response_format(model));
+
+    if let Some(mask) = mask {
+        let (filename, mime, data) = read_image(mask).await?;
+        body = body.file("mask", &filename, mime, &data);
+    }
+
+    Ok(body.finish())
+}
+
+pub async fn images_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+    task: &ImageTask,
+    options: &ImageOptions,
+) -> Result<ImagesResponse> {
+    let model = options.model(task);
+    let url = provider.url(proxy_config, task.api(), Some(model))?;
+
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
+
+    let rb = match task {
+        ImageTask::Generation { prompt } => {
+            let mut request = json!({ "model": model, "prompt": prompt });
+            let fields = [
+                ("n", options.n.map(Value::from)),
+                ("size", options.size.as_deref().map(Value::from)),
+                ("quality", options.quality.as_deref().map(Value::from)),
+                ("style", options.style.as_deref().map(Value::from)),
+                ("response_format", response_format(model).map(Value::from)),
+            ];
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    request[name] = value;
+                }
+            }
+
+            headers.append("Content-Type", "application/json".parse().unwrap());
+            client.post(&url).headers(headers).json(&request)
+        }
+        _ => {
+            let (content_type, body) = multipart_body(task, options).await?;
+            headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+            client.post(&url).headers(headers).body(body)
+        }
+    };
+
+    debug!(
+        "requesting: provider={} method=POST path={} model={}",
+        provider.name(),
+        url,
+        model
+    );
+
+    let rb = rb.timeout(Timeouts::load()?.request);
+
+    let response = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
+        .json::<Value>()
+        .await?;
+
+    check_error(&response)?;
+
+    Ok(serde_json::from_value(response)?)
+}
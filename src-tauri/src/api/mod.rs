@@ -1,24 +1,116 @@
-use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+use serde_json::Value;
 
+use crate::{
+    api::provider::Provider,
+    config::ProxyConfig,
+    error::{AppError, Result},
+};
+
+pub mod audio;
 pub mod chat;
 pub mod client;
+pub mod embeddings;
+pub mod images;
 pub mod models;
+pub mod moderations;
+mod multipart;
+pub mod provider;
+pub mod speech;
 mod url;
 
 // ChatGPT API基础URL
 const API_BASE_URL: &str = "https://api.openai.com";
 
 // ChatGPT API请求头
-pub(crate) fn create_headers(api_key: &str) -> HeaderMap {
-    trace!("使用的 api key：{}", api_key);
+pub(crate) fn create_headers(api_key: &str) -> Result<HeaderMap> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", api_key))
+        .map_err(|_| AppError::Config("API key 中包含无效字符".to_string()))?;
+    // 标记为敏感后 Debug 输出中不包含 key
+    value.set_sensitive(true);
 
     let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        format!("Bearer {}", api_key).parse().unwrap(),
-    );
+    headers.insert(AUTHORIZATION, value);
 
     trace!("headers: {:?}", headers);
 
-    headers
+    Ok(headers)
+}
+
+/// 提供商的鉴权请求头，加上代理配置中的额外请求头
+pub(crate) fn request_headers(
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+) -> Result<HeaderMap> {
+    let mut headers = provider.headers(api_key)?;
+
+    for (name, value) in &proxy_config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| AppError::Config(format!("请求头名称无效：{}", name)))?;
+
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|_| AppError::Config(format!("请求头 {} 的值无效", name)))?;
+        // 额外请求头通常包含 token，不在日志中输出
+        value.set_sensitive(true);
+
+        headers.insert(name, value);
+    }
+
+    Ok(headers)
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    code: Option<Value>,
+}
+
+// OpenAI 错误响应：{"error": {"message": "", "type": "", "param": null, "code": ""}}
+#[derive(Debug, Deserialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+impl ApiErrorDetail {
+    fn into_app_error(self, status: u16) -> AppError {
+        let code = match self.code {
+            Some(Value::String(c)) => Some(c),
+            Some(Value::Number(n)) => Some(n.to_string()),
+            _ => self.error_type,
+        };
+
+        AppError::Api {
+            status,
+            message: self.message,
+            code,
+        }
+    }
+}
+
+/// 将失败响应的响应体解析为错误，无法解析时使用原始响应体
+pub(crate) fn parse_error(status: u16, body: &str) -> AppError {
+    match serde_json::from_str::<ApiErrorBody>(body) {
+        Ok(b) => b.error.into_app_error(status),
+        Err(_) => AppError::Api {
+            status,
+            message: body.to_string(),
+            code: None,
+        },
+    }
+}
+
+/// 部分反向代理在状态码为 200 时也会返回错误体
+pub(crate) fn check_error(response: &Value) -> Result<()> {
+    if response.get("error").is_none() {
+        return Ok(());
+    }
+
+    match ApiErrorBody::deserialize(response) {
+        Ok(b) => Err(b.error.into_app_error(200)),
+        Err(_) => Ok(()),
+    }
 }
@@ -1,7 +1,10 @@
-use std::time::Duration;
-
 use crate::{
-    api::{client::new_client, create_headers, url::api_url},
+    api::{
+        check_error,
+        client::{send_with_retry, status_error, HttpClient, RetryPolicy, Timeouts},
+        provider::Provider,
+        request_headers,
+    },
     config::ProxyConfig,
     error::Result,
 };
@@ -41,25 +44,30 @@ pub struct ModelsResponse {
     pub data: Vec<Model>,
 }
 
-const API: &str = "/v1/models";
-
-pub async fn get_chat_models(proxy_config: &ProxyConfig, api_key: &str) -> Result<ModelsResponse> {
-    let client = new_client(proxy_config)?;
+const API: &str = "/models";
 
-    let url = api_url(proxy_config, API);
+pub async fn get_chat_models(
+    client: &HttpClient,
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+) -> Result<ModelsResponse> {
+    let url = provider.url(proxy_config, API, None)?;
 
-    let response = client
+    let rb = client
         .get(&url)
-        .headers(create_headers(api_key))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?
+        .headers(request_headers(provider, proxy_config, api_key)?);
+
+    let response = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
         .json::<Value>()
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
     debug!("response {:?}", response);
-    Ok(serde_json::from_value(response).map_err(|e| e.to_string())?)
+
+    check_error(&response)?;
+
+    Ok(serde_json::from_value(response)?)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -70,25 +78,30 @@ pub struct ModelResponse {
 }
 
 pub async fn retrieve_model(
+    client: &HttpClient,
+    provider: &dyn Provider,
     proxy_config: &ProxyConfig,
     api_key: &str,
     model: &str,
 ) -> Result<Model> {
-    let client = new_client(proxy_config)?;
-
-    let url = api_url(proxy_config, API) + "/" + model;
+    let url = provider.url(proxy_config, &format!("{}/{}", API, model), None)?;
 
     let response = client
         .get(&url)
-        .headers(create_headers(api_key))
-        .timeout(Duration::from_secs(5)) // TODO: 超时时间写到配置文件中
+        .headers(request_headers(provider, proxy_config, api_key)?)
+        .timeout(Timeouts::load()?.model)
         .send()
-        .await
-        .map_err(|e| e.to_string())?
-        .json::<Value>()
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(status_error(response).await);
+    }
+
+    let response = response.json::<Value>().await?;
 
     debug!("response {:?}", response);
-    Ok(serde_json::from_value(response).map_err(|e| e.to_string())?)
+
+    check_error(&response)?;
+
+    Ok(serde_json::from_value(response)?)
 }
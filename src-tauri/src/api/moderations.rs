@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    api::{
+        check_error,
+        client::{send_with_retry, HttpClient, RetryPolicy, Timeouts},
+        provider::Provider,
+        request_headers,
+    },
+    config::ProxyConfig,
+    error::Result,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const API: &str = "/moderations";
+
+pub const DEFAULT_MODEL: &str = "omni-moderation-latest";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    /// 类别名如 `harassment`、`self-harm/intent`
+    pub categories: BTreeMap<String, bool>,
+    pub category_scores: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationResponse {
+    pub id: String,
+    pub model: String,
+    pub results: Vec<ModerationResult>,
+}
+
+pub async fn moderations_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+    model: &str,
+    input: &str,
+) -> Result<ModerationResponse> {
+    let url = provider.url(proxy_config, API, Some(model))?;
+
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
+    headers.append("Content-Type", "application/json".parse().unwrap());
+
+    debug!(
+        "requesting: provider={} method=POST path={} model={}",
+        provider.name(),
+        url,
+        model
+    );
+
+    let rb = client
+        .post(&url)
+        .headers(headers)
+        .json(&json!({ "model": model, "input": input }))
+        .timeout(Timeouts::load()?.request);
+
+    let response = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
+        .json::<Value>()
+        .await?;
+
+    check_error(&response)?;
+
+    let response: ModerationResponse = serde_json::from_value(response)?;
+
+    debug!("moderation: id={} model={}", response.id, response.model);
+
+    Ok(response)
+}
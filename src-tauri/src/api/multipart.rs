@@ -0,0 +1,71 @@
+use rand::{distributions::Alphanumeric, Rng};
+
+/// 手动组装的 `multipart/form-data` 请求体
+///
+/// reqwest 的 multipart 请求体是流，无法复制，`send_with_retry` 重试时需要复制请求，
+/// 因此在内存中组装完整的请求体。
+pub(crate) struct MultipartBody {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl MultipartBody {
+    pub fn new() -> Self {
+        let boundary: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+
+        MultipartBody {
+            boundary: format!("----chatgpt-client-{}", boundary),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                self.boundary, name, value
+            )
+            .as_bytes(),
+        );
+        self
+    }
+
+    /// 值为 `None` 时不添加字段
+    pub fn optional(self, name: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(value) => self.text(name, value),
+            None => self,
+        }
+    }
+
+    pub fn file(mut self, name: &str, filename: &str, mime: &str, data: &[u8]) -> Self {
+        // 文件名中的引号和换行会破坏请求头
+        let filename = filename.replace(['"', '\r', '\n'], "_");
+
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                self.boundary, name, filename, mime
+            )
+            .as_bytes(),
+        );
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// 返回 `Content-Type` 请求头和请求体
+    pub fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.body,
+        )
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use url::Url;
+
+use crate::{
+    api::{
+        create_headers,
+        url::{api_url, join_path},
+    },
+    config::{self, Config, ProviderConfig, ProxyConfig},
+    error::{AppError, Result},
+};
+
+/// 接口服务提供商
+///
+/// 各接口模块只使用 OpenAI 风格的接口路径（如 `/chat/completions`），
+/// 完整的地址与鉴权请求头由提供商生成。
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 生成接口地址，`model` 用于需要按模型区分部署的提供商
+    fn url(&self, proxy_config: &ProxyConfig, api: &str, model: Option<&str>) -> Result<String>;
+
+    fn headers(&self, api_key: &str) -> Result<HeaderMap>;
+}
+
+/// api.openai.com，支持反向代理
+pub struct OpenAI;
+
+impl Provider for OpenAI {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn url(&self, proxy_config: &ProxyConfig, api: &str, _model: Option<&str>) -> Result<String> {
+        api_url(proxy_config, &format!("/v1{}", api))
+    }
+
+    fn headers(&self, api_key: &str) -> Result<HeaderMap> {
+        create_headers(api_key)
+    }
+}
+
+/// Azure OpenAI，模型通过部署名访问
+pub struct AzureOpenAI {
+    endpoint: String,
+    api_version: String,
+    deployments: HashMap<String, String>,
+}
+
+impl AzureOpenAI {
+    /// 未配置部署名时，直接将模型名作为部署名
+    fn deployment<'a>(&'a self, model: &'a str) -> &'a str {
+        self.deployments
+            .get(model)
+            .map(|d| d.as_str())
+            .unwrap_or(model)
+    }
+}
+
+impl Provider for AzureOpenAI {
+    fn name(&self) -> &str {
+        "azure"
+    }
+
+    fn url(&self, _proxy_config: &ProxyConfig, api: &str, model: Option<&str>) -> Result<String> {
+        let endpoint = self.endpoint.trim_end_matches('/');
+
+        let url = match model {
+            Some(model) => format!(
+                "{}/openai/deployments/{}{}?api-version={}",
+                endpoint,
+                self.deployment(model),
+                api,
+                self.api_version
+            ),
+            None => format!(
+                "{}/openai{}?api-version={}",
+                endpoint, api, self.api_version
+            ),
+        };
+
+        Ok(url)
+    }
+
+    fn headers(&self, api_key: &str) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let mut value = HeaderValue::from_str(api_key)
+            .map_err(|_| AppError::Config("API key 中包含无效字符".to_string()))?;
+        value.set_sensitive(true);
+        headers.insert("api-key", value);
+
+        Ok(headers)
+    }
+}
+
+/// 兼容 OpenAI 接口的服务，如本地部署的模型服务
+pub struct Compatible {
+    base_url: String,
+}
+
+impl Provider for Compatible {
+    fn name(&self) -> &str {
+        "compatible"
+    }
+
+    fn url(&self, _proxy_config: &ProxyConfig, api: &str, _model: Option<&str>) -> Result<String> {
+        let base_url = Url::parse(&self.base_url)
+            .map_err(|e| AppError::Config(format!("服务地址无效：{}，{}", self.base_url, e)))?;
+
+        Ok(join_path(&base_url, api).to_string())
+    }
+
+    fn headers(&self, api_key: &str) -> Result<HeaderMap> {
+        // 本地服务通常不需要鉴权
+        if api_key.is_empty() {
+            return Ok(HeaderMap::new());
+        }
+
+        create_headers(api_key)
+    }
+}
+
+impl From<&ProviderConfig> for Box<dyn Provider> {
+    fn from(config: &ProviderConfig) -> Self {
+        match config {
+            ProviderConfig::OpenAI => Box::new(OpenAI),
+            ProviderConfig::Azure {
+                endpoint,
+                api_version,
+                deployments,
+            } => Box::new(AzureOpenAI {
+                endpoint: endpoint.clone(),
+                api_version: api_version.clone(),
+                deployments: deployments.clone(),
+            }),
+            ProviderConfig::Compatible { base_url } => Box::new(Compatible {
+                base_url: base_url.clone(),
+            }),
+        }
+    }
+}
+
+fn find_provider(config: Option<&Config>, name: Option<&str>) -> Result<Box<dyn Provider>> {
+    let name = match name {
+        Some(n) => n,
+        None => return Ok(Box::new(OpenAI)),
+    };
+
+    let provider = config
+        .and_then(|c| c.providers.as_ref())
+        .and_then(|p| p.get(name))
+        .ok_or_else(|| AppError::Config(format!("未配置服务提供商：{}", name)))?;
+
+    debug!("使用的服务提供商：{}", name);
+
+    Ok(provider.into())
+}
+
+/// 按名称从配置文件中获取提供商，未指定名称时使用 OpenAI
+pub fn get_provider(name: Option<&str>) -> Result<Box<dyn Provider>> {
+    let config = config::read_config()?;
+
+    find_provider(config.as_ref(), name)
+}
+
+/// 获取主题使用的提供商
+pub fn get_topic_provider(topic_id: u32) -> Result<Box<dyn Provider>> {
+    let config = config::read_config()?;
+
+    let name = config
+        .as_ref()
+        .and_then(|c| c.topics.as_ref())
+        .and_then(|t| t.get(&topic_id.to_string()))
+        .and_then(|t| t.provider.as_deref());
+
+    find_provider(config.as_ref(), name)
+}
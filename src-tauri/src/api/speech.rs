@@ -0,0 +1,239 @@
+use crate::{
+    api::{
+        client::{send_with_retry, HttpClient, RetryPolicy, Timeouts},
+        provider::Provider,
+        request_headers,
+    },
+    config::{self, ProxyConfig},
+    error::{AppError, Result},
+};
+use serde::{Deserialize, Serialize};
+
+const API: &str = "/audio/speech";
+
+/// 每次请求的最大字符数，接口限制为 4096，按句子分段时取较小的值以便尽快开始播放
+pub const MAX_CHUNK_CHARS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeechFormat {
+    #[default]
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+    Wav,
+    Pcm,
+}
+
+impl SpeechFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SpeechFormat::Mp3 => "mp3",
+            SpeechFormat::Opus => "opus",
+            SpeechFormat::Aac => "aac",
+            SpeechFormat::Flac => "flac",
+            SpeechFormat::Wav => "wav",
+            SpeechFormat::Pcm => "pcm",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            SpeechFormat::Mp3 => "audio/mpeg",
+            SpeechFormat::Opus => "audio/ogg",
+            SpeechFormat::Aac => "audio/aac",
+            SpeechFormat::Flac => "audio/flac",
+            SpeechFormat::Wav => "audio/wav",
+            SpeechFormat::Pcm => "audio/pcm",
+        }
+    }
+
+    /// 多段音频能否直接首尾相接：mp3 帧、ADTS、ogg 链式流和裸 PCM 可以，
+    /// wav 和 flac 的文件头记录了长度，拼接后只能播放第一段
+    pub fn concatenable(&self) -> bool {
+        !matches!(self, SpeechFormat::Flac | SpeechFormat::Wav)
+    }
+}
+
+fn default_speech_model() -> String {
+    "tts-1".to_string()
+}
+
+fn default_voice() -> String {
+    "alloy".to_string()
+}
+
+/// 语音合成选项，不包含文本
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpeechOptions {
+    #[serde(default = "default_speech_model")]
+    pub model: String,
+    /// `alloy`、`echo`、`fable`、`onyx`、`nova`、`shimmer` 等
+    #[serde(default = "default_voice")]
+    pub voice: String,
+    #[serde(default)]
+    pub response_format: SpeechFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>, // [0.25, 4.0], default: 1
+}
+
+impl Default for SpeechOptions {
+    fn default() -> Self {
+        SpeechOptions {
+            model: default_speech_model(),
+            voice: default_voice(),
+            response_format: SpeechFormat::default(),
+            speed: None,
+        }
+    }
+}
+
+impl SpeechOptions {
+    /// 未指定时使用配置文件中的选项
+    pub fn or_config(options: Option<SpeechOptions>) -> Result<SpeechOptions> {
+        match options {
+            Some(options) => Ok(options),
+            None => Ok(config::read_config()?
+                .and_then(|c| c.speech)
+                .unwrap_or_default()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpeechRequest<'a> {
+    pub model: &'a str,
+    pub input: &'a str,
+    pub voice: &'a str,
+    pub response_format: SpeechFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+}
+
+impl<'a> SpeechRequest<'a> {
+    pub fn new(options: &'a SpeechOptions, input: &'a str) -> Self {
+        SpeechRequest {
+            model: &options.model,
+            input,
+            voice: &options.voice,
+            response_format: options.response_format,
+            speed: options.speed,
+        }
+    }
+}
+
+/// 去掉 markdown 中的代码块，代码朗读出来没有意义
+fn strip_code_blocks(text: &str) -> String {
+    let mut in_code = false;
+
+    text.lines()
+        .filter(|line| {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                return false;
+            }
+            !in_code
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+
+        // 英文句点后需要有空白，避免切开小数和缩写中的点
+        let end = match c {
+            '。' | '！' | '？' | '；' | '!' | '?' | ';' | '\n' => true,
+            '.' => !matches!(chars.peek(), Some(n) if !n.is_whitespace()),
+            _ => false,
+        };
+
+        if end {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    sentences.push(current);
+
+    sentences
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// 将回复按句子分段，每段不超过 `max_chars` 个字符，超长的句子按字符截断
+pub fn split_chunks(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for sentence in split_sentences(&strip_code_blocks(text)) {
+        let len = sentence.chars().count();
+
+        if !current.is_empty() && current.chars().count() + 1 + len > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+
+        if len > max_chars {
+            let chars: Vec<char> = sentence.chars().collect();
+            chunks.extend(chars.chunks(max_chars).map(|c| c.iter().collect()));
+            continue;
+        }
+
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&sentence);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// 合成一段文本，返回音频数据
+pub async fn speech_client(
+    client: &HttpClient,
+    provider: &dyn Provider,
+    proxy_config: &ProxyConfig,
+    api_key: &str,
+    request: &SpeechRequest<'_>,
+) -> Result<Vec<u8>> {
+    if request.input.chars().count() > 4096 {
+        return Err(AppError::Other(
+            "合成语音的文本超过 4096 个字符".to_string(),
+        ));
+    }
+
+    let url = provider.url(proxy_config, API, Some(request.model))?;
+
+    let mut headers = request_headers(provider, proxy_config, api_key)?;
+    headers.append("Content-Type", "application/json".parse().unwrap());
+
+    debug!(
+        "requesting: provider={} method=POST path={} voice={}",
+        provider.name(),
+        url,
+        request.voice
+    );
+
+    let rb = client
+        .post(&url)
+        .headers(headers)
+        .json(request)
+        .timeout(Timeouts::load()?.request);
+
+    let bytes = send_with_retry(&RetryPolicy::load()?, rb)
+        .await?
+        .bytes()
+        .await?;
+
+    Ok(bytes.to_vec())
+}
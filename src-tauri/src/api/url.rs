@@ -1,13 +1,31 @@
-use crate::{api::API_BASE_URL, config::ProxyConfig};
+use url::Url;
 
-pub fn base_url(proxy_config: &ProxyConfig) -> String {
-    if proxy_config.method == "proxy" {
-        API_BASE_URL.to_owned()
+use crate::{api::API_BASE_URL, config::ProxyConfig, error::Result};
+
+pub fn base_url(proxy_config: &ProxyConfig) -> Result<Url> {
+    if proxy_config.method == "reverse-proxy" {
+        proxy_config.reverse_proxy_url()
     } else {
-        proxy_config.to_string()
+        Ok(Url::parse(API_BASE_URL).unwrap())
     }
 }
 
-pub fn api_url(proxy_config: &ProxyConfig, api: &str) -> String {
-    format!("{}{}", base_url(proxy_config), api)
+/// 将接口路径拼接到基础地址的路径之后，保留基础地址的路径前缀和查询参数
+///
+/// `https://example.com/openai/` 与 `/v1/models` 拼接为 `https://example.com/openai/v1/models`。
+pub fn join_path(base: &Url, api: &str) -> Url {
+    let mut url = base.clone();
+
+    let path = format!(
+        "{}/{}",
+        base.path().trim_end_matches('/'),
+        api.trim_start_matches('/')
+    );
+    url.set_path(&path);
+
+    url
+}
+
+pub fn api_url(proxy_config: &ProxyConfig, api: &str) -> Result<String> {
+    Ok(join_path(&base_url(proxy_config)?, api).to_string())
 }
@@ -1,7 +1,9 @@
-use crate::error::Result;
+use crate::api::speech::SpeechOptions;
+use crate::error::{AppError, Result};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{collections::HashMap, fmt, fs, path::PathBuf};
+use url::Url;
 
 lazy_static! {
     pub static ref APP_CONFIG_DIR: PathBuf = {
@@ -22,33 +24,395 @@ lazy_static! {
     static ref CONFIG_FILE: PathBuf = APP_CONFIG_DIR.join("config.toml");
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Proxy {
     pub protocol: String,
     pub host: String,
     pub port: u16,
+    /// 代理认证，http 代理使用 Basic 认证，socks5 代理使用用户名密码认证
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl Proxy {
+    /// 带认证信息的代理地址
+    pub fn url(&self) -> Result<Url> {
+        let mut url = Url::parse(&format!("{}{}:{}", self.protocol, self.host, self.port))
+            .map_err(|e| AppError::Proxy(format!("代理地址无效：{}", e)))?;
+
+        if let Some(username) = self.username.as_deref().filter(|u| !u.is_empty()) {
+            url.set_username(username)
+                .and_then(|_| url.set_password(self.password.as_deref()))
+                .map_err(|_| AppError::Proxy("代理地址不支持认证信息".to_string()))?;
+        }
+
+        Ok(url)
+    }
+}
+
+// 配置会输出到日志中，不输出代理密码
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Proxy")
+            .field("protocol", &self.protocol)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// 代理方式：
+///
+/// - `proxy`：使用 `proxy` 中配置的代理
+/// - `reverse-proxy`：请求发送到 `reverse_proxy`
+/// - `system`：使用环境变量 `HTTP_PROXY`、`HTTPS_PROXY`、`ALL_PROXY` 和 `NO_PROXY` 中的代理
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct ProxyConfig {
     pub method: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy: Option<Proxy>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reverse_proxy: Option<String>,
+    /// 不使用代理的主机，格式同 `NO_PROXY`，如 `localhost`、`.example.com`、`192.168.0.0/16`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+    /// 每个接口请求附加的请求头，如 `OpenAI-Organization`、`OpenAI-Project` 或网关的鉴权 token
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+}
+
+// 额外请求头通常包含 token，日志中只输出请求头名称
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: HashMap<&str, &str> = self
+            .headers
+            .keys()
+            .map(|name| (name.as_str(), "***"))
+            .collect();
+
+        f.debug_struct("ProxyConfig")
+            .field("method", &self.method)
+            .field("proxy", &self.proxy)
+            .field("reverse_proxy", &self.reverse_proxy)
+            .field("no_proxy", &self.no_proxy)
+            .field("headers", &headers)
+            .finish()
+    }
 }
 
 impl ProxyConfig {
-    pub fn to_string(&self) -> String {
-        if self.method == "proxy" {
-            let proxy = self.proxy.as_ref().unwrap();
-            return format!("{}{}:{}", proxy.protocol, proxy.host, proxy.port);
+    /// 反向代理地址，可以包含路径前缀，如 `https://example.com/openai`
+    pub fn reverse_proxy_url(&self) -> Result<Url> {
+        let reverse_proxy = self
+            .reverse_proxy
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| AppError::Config("未配置反向代理地址".to_string()))?;
+
+        let url = Url::parse(reverse_proxy)
+            .map_err(|e| AppError::Config(format!("反向代理地址无效：{}，{}", reverse_proxy, e)))?;
+
+        if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+            return Err(AppError::Config(format!(
+                "反向代理地址应以 http:// 或 https:// 开头：{}",
+                reverse_proxy
+            )));
         }
 
-        self.reverse_proxy.clone().unwrap()
+        Ok(url)
     }
 }
 
+/// 接口服务提供商配置，以 `kind` 区分类型
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ProviderConfig {
+    #[serde(rename = "openai")]
+    OpenAI,
+    Azure {
+        endpoint: String,
+        api_version: String,
+        /// 模型名 => 部署名
+        #[serde(default)]
+        deployments: HashMap<String, String>,
+    },
+    Compatible {
+        base_url: String,
+    },
+}
+
+/// 命名的 API key
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    /// key 或 `secret://名字` 形式的密钥引用
+    pub key: String,
+    /// 所属的服务提供商名，为空时为 OpenAI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
+/// 多个 key 的使用方式
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyRotation {
+    /// 按顺序使用，前一个 key 被限流或额度不足时使用下一个
+    #[default]
+    Failover,
+    /// 轮流使用每个 key
+    RoundRobin,
+}
+
+/// API key 的保存位置
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SecretBackend {
+    /// 用密码加密的 secrets.json
+    #[default]
+    File,
+    /// 系统的密钥服务，需要启用 `keyring` 特性
+    Keyring,
+}
+
+/// 模型价格，单位为美元 / 1K tokens
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl ModelPrice {
+    const fn new(prompt: f64, completion: f64) -> Self {
+        ModelPrice { prompt, completion }
+    }
+
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion) / 1000.0
+    }
+}
+
+/// 内置的模型价格，键为模型名前缀，匹配时使用最长的前缀
+///
+/// `gpt-4o`、`gpt-4.1` 等同样以 `gpt-4` 开头，需要单独列出。
+const DEFAULT_PRICING: [(&str, ModelPrice); 11] = [
+    ("gpt-3.5-turbo", ModelPrice::new(0.0015, 0.002)),
+    ("gpt-3.5-turbo-16k", ModelPrice::new(0.003, 0.004)),
+    ("gpt-4", ModelPrice::new(0.03, 0.06)),
+    ("gpt-4-32k", ModelPrice::new(0.06, 0.12)),
+    ("gpt-4-1106", ModelPrice::new(0.01, 0.03)),
+    ("gpt-4-turbo", ModelPrice::new(0.01, 0.03)),
+    ("gpt-4o", ModelPrice::new(0.0025, 0.01)),
+    ("gpt-4o-mini", ModelPrice::new(0.00015, 0.0006)),
+    ("gpt-4.1", ModelPrice::new(0.002, 0.008)),
+    ("gpt-4.1-mini", ModelPrice::new(0.0004, 0.0016)),
+    ("gpt-4.1-nano", ModelPrice::new(0.0001, 0.0004)),
+];
+
+/// 请求失败后的重试配置
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RetryConfig {
+    /// 最大尝试次数，包含第一次请求
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: default_max_attempts(),
+            base_delay_ms: default_base_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+        }
+    }
+}
+
+/// 超时配置，单位为毫秒
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct TimeoutConfig {
+    /// 建立连接的超时时间
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_ms: u64,
+    /// 普通请求从发送到接收完响应的超时时间
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_ms: u64,
+    /// 获取模型信息等简单请求的超时时间
+    #[serde(default = "default_model_timeout_ms")]
+    pub model_ms: u64,
+    /// 流式请求从发送到收到第一个 chunk 的超时时间
+    #[serde(default = "default_first_token_timeout_ms")]
+    pub first_token_ms: u64,
+    /// 流式响应两个 chunk 之间的超时时间
+    #[serde(default = "default_chunk_timeout_ms")]
+    pub chunk_ms: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            connect_ms: default_connect_timeout_ms(),
+            request_ms: default_request_timeout_ms(),
+            model_ms: default_model_timeout_ms(),
+            first_token_ms: default_first_token_timeout_ms(),
+            chunk_ms: default_chunk_timeout_ms(),
+        }
+    }
+}
+
+/// 共享 HTTP 客户端的连接配置
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct HttpConfig {
+    /// 每个主机保留的最大空闲连接数
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// 空闲连接的保留时间，单位为毫秒
+    #[serde(default = "default_pool_idle_timeout_ms")]
+    pub pool_idle_timeout_ms: u64,
+    /// TCP keep-alive 间隔，单位为毫秒，为 0 时不启用
+    #[serde(default = "default_tcp_keepalive_ms")]
+    pub tcp_keepalive_ms: u64,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            pool_idle_timeout_ms: default_pool_idle_timeout_ms(),
+            tcp_keepalive_ms: default_tcp_keepalive_ms(),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+/// TLS 配置，用于使用自签名证书或 TLS 检查网关的网络
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+pub struct TlsConfig {
+    /// 额外信任的 CA 证书文件（PEM），一个文件可包含多个证书
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_files: Vec<String>,
+    /// 反向代理的证书文件（PEM），设置后访问反向代理时只信任此证书
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reverse_proxy_cert: Option<String>,
+    /// 不校验证书的反向代理主机，只应用于本地测试服务器
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accept_invalid_certs_hosts: Vec<String>,
+}
+
+/// 本地工具的配置
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ToolsConfig {
+    /// 允许 `read_file` 读取的目录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_dirs: Vec<String>,
+    /// `read_file` 一次最多读取的字节数
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// 一次回复中最多调用工具的轮数，超过后不再执行工具
+    #[serde(default = "default_max_tool_rounds")]
+    pub max_rounds: u32,
+}
+
+/// 历史对话索引和知识库使用的向量模型
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EmbeddingConfig {
+    /// 在后台定期为新的对话建立索引，关闭时只在搜索前建立索引
+    #[serde(default)]
+    pub background: bool,
+    #[serde(default = "default_embedding_model")]
+    pub model: String,
+    /// 使用的服务提供商名，为空时使用 OpenAI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// 每次请求的对话数
+    #[serde(default = "default_embedding_batch_size")]
+    pub batch_size: usize,
+    /// 后台索引的间隔，单位为秒
+    #[serde(default = "default_embedding_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        EmbeddingConfig {
+            background: false,
+            model: default_embedding_model(),
+            provider: None,
+            batch_size: default_embedding_batch_size(),
+            interval_secs: default_embedding_interval_secs(),
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    pub fn load() -> Result<Self> {
+        Ok(read_config()?.and_then(|c| c.embedding).unwrap_or_default())
+    }
+}
+
+/// 主题知识库的分段和检索
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct KnowledgeConfig {
+    /// 每次发送时加入上下文的分段数
+    #[serde(default = "default_knowledge_top_k")]
+    pub top_k: usize,
+    /// 每个分段的最大字符数
+    #[serde(default = "default_knowledge_chunk_chars")]
+    pub chunk_chars: usize,
+    /// 相邻分段重叠的字符数
+    #[serde(default = "default_knowledge_overlap_chars")]
+    pub overlap_chars: usize,
+    /// 相似度低于此值的分段不加入上下文
+    #[serde(default = "default_knowledge_min_score")]
+    pub min_score: f32,
+    /// 导入文件的大小上限
+    #[serde(default = "default_knowledge_max_file_bytes")]
+    pub max_file_bytes: u64,
+}
+
+impl Default for KnowledgeConfig {
+    fn default() -> Self {
+        KnowledgeConfig {
+            top_k: default_knowledge_top_k(),
+            chunk_chars: default_knowledge_chunk_chars(),
+            overlap_chars: default_knowledge_overlap_chars(),
+            min_score: default_knowledge_min_score(),
+            max_file_bytes: default_knowledge_max_file_bytes(),
+        }
+    }
+}
+
+impl KnowledgeConfig {
+    pub fn load() -> Result<Self> {
+        Ok(read_config()?.and_then(|c| c.knowledge).unwrap_or_default())
+    }
+}
+
+/// 通过标准输入输出通信的 MCP 服务
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct McpServerConfig {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// 工作目录，为空时使用当前目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// 等待请求响应的超时时间，单位为毫秒
+    #[serde(default = "default_mcp_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PromptConfig {
     pub in_chinese: Option<bool>,
@@ -78,6 +442,52 @@ pub struct Config {
     pub show_line_numbers: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub topics: Option<HashMap<String, TopicConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub providers: Option<HashMap<String, ProviderConfig>>,
+    /// 覆盖或补充内置的模型价格
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<HashMap<String, ModelPrice>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<TimeoutConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// `open_api_key` 之外的 key，`open_api_key` 以 `default` 为名参与轮换
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_keys: Option<Vec<ApiKeyConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_rotation: Option<KeyRotation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_backend: Option<SecretBackend>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<ToolsConfig>,
+    /// MCP 服务，以名字区分，主题通过 `TopicConfig.mcp_servers` 启用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mcp_servers: Option<HashMap<String, McpServerConfig>>,
+    /// 朗读回复时默认使用的模型、声音和格式
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speech: Option<SpeechOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<EmbeddingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub knowledge: Option<KnowledgeConfig>,
+}
+
+/// 内置价格与配置中的价格合并后的价格表
+pub fn pricing_table(config: Option<&Config>) -> HashMap<String, ModelPrice> {
+    let mut pricing: HashMap<String, ModelPrice> = DEFAULT_PRICING
+        .iter()
+        .map(|(model, price)| (model.to_string(), price.clone()))
+        .collect();
+
+    if let Some(custom) = config.and_then(|c| c.pricing.as_ref()) {
+        pricing.extend(custom.iter().map(|(m, p)| (m.clone(), p.clone())));
+    }
+
+    pricing
 }
 
 pub fn read_config() -> Result<Option<Config>> {
@@ -87,23 +497,25 @@ pub fn read_config() -> Result<Option<Config>> {
 
     let config_str = fs::read_to_string(CONFIG_FILE.to_owned()).map_err(|e| {
         error!("读取配置文件时出错：{}", e);
-        e.to_string()
+        AppError::Config(e.to_string())
     })?;
 
     toml::from_str(&config_str).map_err(|e| {
         error!("解析配置文件时出错：{}", e);
-        e.to_string()
+        AppError::Config(e.to_string())
     })
 }
 
 pub fn write_config(config: &Config) -> Result<()> {
     trace!("保存的配置：{:?}", config);
 
-    let config_str = toml::to_string(config).map_err(|e| e.to_string())?;
+    let config_str = toml::to_string(config)?;
 
     trace!("序列化后的配置：{}", config_str);
 
-    fs::write(CONFIG_FILE.to_owned(), config_str).map_err(|e| e.to_string())
+    fs::write(CONFIG_FILE.to_owned(), config_str)?;
+
+    Ok(())
 }
 
 fn default_temperature() -> f64 {
@@ -114,6 +526,102 @@ fn default_is_on_top() -> bool {
     true
 }
 
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    30_000
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_request_timeout_ms() -> u64 {
+    120_000
+}
+
+fn default_model_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_first_token_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_chunk_timeout_ms() -> u64 {
+    15_000
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    8
+}
+
+fn default_pool_idle_timeout_ms() -> u64 {
+    90_000
+}
+
+fn default_tcp_keepalive_ms() -> u64 {
+    60_000
+}
+
+fn default_user_agent() -> String {
+    format!("chatgpt-client/{}", env!("CARGO_PKG_VERSION"))
+}
+
+fn default_max_file_bytes() -> u64 {
+    64 * 1024
+}
+
+fn default_max_tool_rounds() -> u32 {
+    5
+}
+
+fn default_mcp_timeout_ms() -> u64 {
+    30000
+}
+
+fn default_embedding_model() -> String {
+    "text-embedding-3-small".to_string()
+}
+
+fn default_embedding_batch_size() -> usize {
+    64
+}
+
+fn default_embedding_interval_secs() -> u64 {
+    300
+}
+
+fn default_knowledge_top_k() -> usize {
+    4
+}
+
+fn default_knowledge_chunk_chars() -> usize {
+    1500
+}
+
+fn default_knowledge_overlap_chars() -> usize {
+    200
+}
+
+fn default_knowledge_min_score() -> f32 {
+    0.25
+}
+
+fn default_knowledge_max_file_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_moderation_action() -> ModerationAction {
+    ModerationAction::Log
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TopicConfig {
     pub use_context: bool,
@@ -122,4 +630,80 @@ pub struct TopicConfig {
     pub system_role: String,
     #[serde(default = "default_temperature")]
     pub temperature: f64,
+    /// 使用的模型，为空时使用 gpt-3.5-turbo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 使用的服务提供商名，为空时使用 OpenAI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// 固定使用的 API key 名，为空时按轮换策略选择
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// 可以调用的本地工具名，为空时不使用工具
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    /// 启用的 MCP 服务名，其中的工具都可以调用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mcp_servers: Option<Vec<String>>,
+    /// 发送前的内容审核策略，为空时不审核
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<ModerationPolicy>,
+}
+
+/// 审核命中某个类别后的处理，按 `log`、`warn`、`block` 依次加严
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    /// 只记录审核结果
+    Log,
+    /// 记录并提示用户，仍然发送
+    Warn,
+    /// 拒绝发送
+    Block,
+}
+
+impl ModerationAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationAction::Log => "log",
+            ModerationAction::Warn => "warn",
+            ModerationAction::Block => "block",
+        }
+    }
+}
+
+/// 主题的内容审核策略
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ModerationPolicy {
+    /// 审核模型，为空时使用 omni-moderation-latest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 没有单独配置的类别命中时的处理
+    #[serde(default = "default_moderation_action")]
+    pub action: ModerationAction,
+    /// 按类别配置的处理，如 `{"self-harm": "block", "violence": "warn"}`，
+    /// 父类别同时作用于 `self-harm/intent` 等子类别
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub categories: HashMap<String, ModerationAction>,
+    /// 类别分数达到此值时也视为命中，为空时只使用接口的判断
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    /// 审核请求失败时拒绝发送，为 false 时只记录错误
+    #[serde(default)]
+    pub fail_closed: bool,
+}
+
+impl ModerationPolicy {
+    /// 类别命中时的处理，先查找类别本身，再查找父类别
+    pub fn action_for(&self, category: &str) -> ModerationAction {
+        self.categories
+            .get(category)
+            .or_else(|| {
+                category
+                    .split_once('/')
+                    .and_then(|(parent, _)| self.categories.get(parent))
+            })
+            .copied()
+            .unwrap_or(self.action)
+    }
 }
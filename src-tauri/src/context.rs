@@ -0,0 +1,338 @@
+use crate::{
+    api::chat::{ChatGPTRequest, ImageUrl, Message},
+    config::{Config, TopicConfig},
+    db::message::Conversation,
+    db::topic::PROMPT_TOPIC_ID,
+    tokenizer::{prompt_budget, trim_conversations},
+};
+
+/// 未在主题中配置模型时使用的模型
+pub const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+
+const PROMPT_ROLE_MESSAGE: &str = r#"I want you to become my Expert Prompt Creator. Your goal is to help me craft the best possible prompt for my needs. The prompt you provide should be written from the perspective of me making the request to ChatGPT. Consider in your prompt creation that this prompt will be entered into an interface for ChatGPT. The process is as follows:
+
+1. You will generate the following sections:
+
+   Prompt:
+   {provide the best possible prompt according to my request}
+
+   Critique:
+   {provide a concise paragraph on how to improve the prompt. Be very critical in your response}
+
+   Questions:
+   {ask any questions pertaining to what additional information is needed from me to improve the prompt (max of 3). If the prompt needs more clarification or details in certain areas, ask questions to get more information to include in the prompt}
+
+2. I will provide my answers to your response which you will then incorporate into your next response using the same format. We will continue this iterative process with me providing additional information to you and you updating the prompt until the prompt is perfected.
+
+   Remember, the prompt we are creating should be written from the perspective of me making a request to ChatGPT. Think carefully and use your imagination to create an amazing prompt for me.
+
+   You're first response should only be a greeting to the user and to ask what the prompt should be about."#;
+
+const PROMPT_ASSISTANT_RESPONSE: &str = "Hello there! I'm excited to help create the perfect prompt for your needs. Can you please let me know what the prompt should be about?";
+
+const PROMPT_ASSISTANT_RESPONSE_IN_CHINESE: &str =
+    "你好，我想知道你需要的提示是关于什么的？请告诉我更多的细节，让我更好地为您生成最好的提示。";
+
+fn conversation_messages<'a>(
+    conversations: impl IntoIterator<Item = &'a Conversation>,
+) -> Vec<Message> {
+    conversations
+        .into_iter()
+        .flat_map(|c| c.messages())
+        .collect()
+}
+
+/// 按主题配置选取作为上下文的历史对话
+///
+/// 对话数量超过 `conversation_count` 时只保留最近的对话，
+/// 若 `use_first_conversation` 为真，第一组对话占用其中一个名额。
+pub fn select_conversations<'a>(
+    topic_config: &TopicConfig,
+    history: &'a [Conversation],
+) -> Vec<&'a Conversation> {
+    if !topic_config.use_context {
+        return Vec::new();
+    }
+
+    let count = topic_config.conversation_count as usize;
+
+    if history.len() <= count {
+        return history.iter().collect();
+    }
+
+    if topic_config.use_first_conversation && count > 0 {
+        let mut selected = vec![&history[0]];
+        selected.extend(&history[history.len() - (count - 1)..]);
+        return selected;
+    }
+
+    history[history.len() - count..].iter().collect()
+}
+
+fn prompt_messages(in_chinese: bool) -> [Message; 2] {
+    if in_chinese {
+        [
+            Message::new(
+                "user",
+                &format!("{}\n\nResponse me in Chinese.", PROMPT_ROLE_MESSAGE),
+            ),
+            Message::new("assistant", PROMPT_ASSISTANT_RESPONSE_IN_CHINESE),
+        ]
+    } else {
+        [
+            Message::new("user", PROMPT_ROLE_MESSAGE),
+            Message::new("assistant", PROMPT_ASSISTANT_RESPONSE),
+        ]
+    }
+}
+
+/// 根据主题配置和历史对话组装请求
+///
+/// 超出模型 token 预算时从最早的对话开始删除。
+pub fn build_request(
+    config: Option<&Config>,
+    topic_id: u32,
+    history: &[Conversation],
+    content: &str,
+    images: Vec<ImageUrl>,
+    stream: bool,
+) -> ChatGPTRequest {
+    let topic_config = config
+        .and_then(|c| c.topics.as_ref())
+        .and_then(|t| t.get(&topic_id.to_string()));
+
+    let model = topic_config
+        .and_then(|t| t.model.as_deref())
+        .unwrap_or(DEFAULT_MODEL);
+
+    let mut head = Vec::new();
+    let mut conversations = Vec::new();
+    let mut keep_first = false;
+
+    if topic_id == PROMPT_TOPIC_ID {
+        // 完善提示的主题始终使用全部对话
+        let in_chinese = config
+            .and_then(|c| c.prompt.as_ref())
+            .and_then(|p| p.in_chinese)
+            .unwrap_or(true);
+
+        head.extend(prompt_messages(in_chinese));
+        conversations.extend(history);
+    } else if let Some(topic_config) = topic_config {
+        if !topic_config.system_role.is_empty() {
+            head.push(Message::new("system", &topic_config.system_role));
+        }
+
+        conversations = select_conversations(topic_config, history);
+        keep_first = topic_config.use_first_conversation;
+    }
+
+    let user_message = Message::new("user", content).with_images(images);
+
+    let mut fixed = head;
+    fixed.push(user_message);
+
+    trim_conversations(
+        model,
+        prompt_budget(model, None),
+        &fixed,
+        &mut conversations,
+        keep_first,
+    );
+
+    let user_message = fixed.pop().unwrap();
+
+    let mut messages = fixed;
+    messages.extend(conversation_messages(conversations));
+    messages.push(user_message);
+
+    let mut request = ChatGPTRequest::new(model, messages);
+
+    // 内置主题使用默认温度
+    if topic_id > PROMPT_TOPIC_ID {
+        request.temperature = topic_config.map(|t| t.temperature as f32);
+    }
+    request.stream = Some(stream);
+
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::message::{AssistantMessage, MessageStatus, UserMessage};
+
+    const TOPIC_ID: u32 = 3;
+
+    fn conversation(index: u64) -> Conversation {
+        Conversation {
+            user: UserMessage::new(&format!("问题 {}", index), index, TOPIC_ID),
+            attachments: Vec::new(),
+            moderation: None,
+            tools: Vec::new(),
+            assistant: AssistantMessage::new(
+                format!("回答 {}", index),
+                index,
+                0,
+                MessageStatus::Complete,
+                None,
+            ),
+        }
+    }
+
+    fn history(len: u64) -> Vec<Conversation> {
+        (1..=len).map(conversation).collect()
+    }
+
+    fn topic_config(count: u8, use_first: bool, system_role: &str) -> TopicConfig {
+        toml::from_str(&format!(
+            r#"
+            use_context = true
+            conversation_count = {}
+            use_first_conversation = {}
+            system_role = "{}"
+            "#,
+            count, use_first, system_role
+        ))
+        .unwrap()
+    }
+
+    fn config(topic: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            open_api_key = ""
+            image_scale = 1
+
+            [proxy]
+            method = "none"
+
+            [topics.{}]
+            {}
+            "#,
+            TOPIC_ID, topic
+        ))
+        .unwrap()
+    }
+
+    fn questions(selected: &[&Conversation]) -> Vec<String> {
+        selected.iter().map(|c| c.user.message.clone()).collect()
+    }
+
+    #[test]
+    fn select_keeps_latest_conversations() {
+        let history = history(5);
+        let selected = select_conversations(&topic_config(2, false, ""), &history);
+
+        assert_eq!(questions(&selected), ["问题 4", "问题 5"]);
+    }
+
+    #[test]
+    fn select_keeps_first_conversation_within_count() {
+        let history = history(5);
+        let selected = select_conversations(&topic_config(3, true, ""), &history);
+
+        assert_eq!(questions(&selected), ["问题 1", "问题 4", "问题 5"]);
+    }
+
+    #[test]
+    fn select_returns_all_when_under_count() {
+        let history = history(2);
+        let selected = select_conversations(&topic_config(5, true, ""), &history);
+
+        assert_eq!(questions(&selected), ["问题 1", "问题 2"]);
+    }
+
+    #[test]
+    fn select_without_context_or_history() {
+        let mut no_context = topic_config(5, false, "");
+        no_context.use_context = false;
+        assert!(select_conversations(&no_context, &history(3)).is_empty());
+
+        assert!(select_conversations(&topic_config(5, true, ""), &[]).is_empty());
+        assert!(select_conversations(&topic_config(0, false, ""), &history(3)).is_empty());
+    }
+
+    #[test]
+    fn build_places_system_prompt_first() {
+        let config = config(
+            r#"
+            use_context = true
+            conversation_count = 1
+            use_first_conversation = false
+            system_role = "你是翻译"
+            "#,
+        );
+
+        let request = build_request(
+            Some(&config),
+            TOPIC_ID,
+            &history(3),
+            "新问题",
+            Vec::new(),
+            true,
+        );
+
+        let messages: Vec<(&str, String)> = request
+            .messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.text()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("system", "你是翻译".to_string()),
+                ("user", "问题 3".to_string()),
+                ("assistant", "回答 3".to_string()),
+                ("user", "新问题".to_string()),
+            ]
+        );
+        assert_eq!(request.model, DEFAULT_MODEL);
+        assert_eq!(request.stream, Some(true));
+    }
+
+    #[test]
+    fn build_without_system_prompt_or_history() {
+        let config = config(
+            r#"
+            use_context = true
+            conversation_count = 3
+            use_first_conversation = true
+            system_role = ""
+            "#,
+        );
+
+        let request = build_request(Some(&config), TOPIC_ID, &[], "新问题", Vec::new(), false);
+
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].role, "user");
+        assert_eq!(request.messages[0].content.text(), "新问题");
+    }
+
+    #[test]
+    fn build_without_topic_config() {
+        let request = build_request(None, TOPIC_ID, &history(3), "新问题", Vec::new(), false);
+
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.temperature, None);
+    }
+
+    #[test]
+    fn build_prompt_topic_uses_whole_history() {
+        let request = build_request(
+            None,
+            PROMPT_TOPIC_ID,
+            &history(2),
+            "新问题",
+            Vec::new(),
+            false,
+        );
+
+        // 提示词的两条消息、两组对话和新的用户消息
+        assert_eq!(request.messages.len(), 7);
+        assert_eq!(
+            request.messages[1].content.text(),
+            PROMPT_ASSISTANT_RESPONSE_IN_CHINESE
+        );
+        assert_eq!(request.messages[6].content.text(), "新问题");
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Ok, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+const ATTACHMENT_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS attachment (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_message_id INTEGER NOT NULL,
+        kind TEXT NOT NULL DEFAULT 'image',
+        mime TEXT,
+        path TEXT NOT NULL,
+        detail TEXT,
+        created_at INTEGER NOT NULL,
+        CONSTRAINT fk_user_message
+        FOREIGN KEY (user_message_id)
+        REFERENCES user_message (id)
+    )
+"#;
+
+const ATTACHMENT_INSERT: &str = r#"
+    INSERT INTO attachment (user_message_id, kind, mime, path, detail, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6);
+"#;
+
+const SELECT_ATTACHMENTS: &str = r#"
+    SELECT a.id, a.user_message_id, a.kind, a.mime, a.path, a.detail, a.created_at
+    FROM attachment a
+    INNER JOIN user_message um ON um.id = a.user_message_id
+    WHERE um.topic_id = ?
+    ORDER BY a.id;
+"#;
+
+const SELECT_TOPIC_ATTACHMENT_PATHS: &str = r#"
+    SELECT a.path
+    FROM attachment a
+    INNER JOIN user_message um ON um.id = a.user_message_id
+    WHERE um.topic_id = ?;
+"#;
+
+const SELECT_ATTACHMENT_PATHS_BY_TIME: &str = r#"
+    SELECT a.path
+    FROM attachment a
+    INNER JOIN user_message um ON um.id = a.user_message_id
+    WHERE um.created_at = ?;
+"#;
+
+/// 用户消息附带的图片
+///
+/// `path` 为复制到应用目录中的本地文件，或者远程图片的 http(s) 链接。
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Attachment {
+    pub id: u32,
+    pub user_message_id: u32,
+    pub kind: String,
+    pub mime: Option<String>,
+    pub path: String,
+    pub detail: Option<String>,
+    pub created_at: u64,
+}
+
+impl Attachment {
+    pub fn image(
+        user_message_id: u32,
+        mime: Option<&str>,
+        path: &str,
+        detail: Option<&str>,
+        created_at: u64,
+    ) -> Self {
+        Attachment {
+            id: 0,
+            user_message_id,
+            kind: "image".into(),
+            mime: mime.map(Into::into),
+            path: path.into(),
+            detail: detail.map(Into::into),
+            created_at,
+        }
+    }
+
+    pub fn insert(&self, conn: &Connection) -> Result<usize> {
+        conn.execute(
+            ATTACHMENT_INSERT,
+            (
+                self.user_message_id,
+                &self.kind,
+                &self.mime,
+                &self.path,
+                &self.detail,
+                self.created_at,
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 attachment 失败：user_message_id={}, path={}",
+                self.user_message_id, self.path
+            )
+        })
+    }
+}
+
+pub fn init_attachment(conn: &Connection) -> Result<()> {
+    conn.execute(ATTACHMENT_TABLE, ())
+        .with_context(|| "创建 attachment 表失败")?;
+
+    Ok(())
+}
+
+/// 主题中所有用户消息的附件，按 id 排序
+pub fn get_attachments(conn: &Connection, topic_id: u32) -> Result<Vec<Attachment>> {
+    let mut stmt = conn
+        .prepare(SELECT_ATTACHMENTS)
+        .with_context(|| "准备附件查询语句时出错")?;
+
+    let attachments = stmt
+        .query_map([topic_id], |row| {
+            std::result::Result::Ok(Attachment {
+                id: row.get(0)?,
+                user_message_id: row.get(1)?,
+                kind: row.get(2)?,
+                mime: row.get(3)?,
+                path: row.get(4)?,
+                detail: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })
+        .with_context(|| "获取附件时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集附件时出错")?;
+
+    Ok(attachments)
+}
+
+fn get_paths(conn: &Connection, sql: &str, param: u64) -> Result<Vec<String>> {
+    let mut stmt = conn
+        .prepare(sql)
+        .with_context(|| "准备附件路径查询语句时出错")?;
+
+    let paths = stmt
+        .query_map([param], |row| row.get(0))
+        .with_context(|| "获取附件路径时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集附件路径时出错")?;
+
+    Ok(paths)
+}
+
+/// 主题中所有附件的路径，删除主题或清空消息后用来删除文件
+pub fn get_topic_attachment_paths(conn: &Connection, topic_id: u32) -> Result<Vec<String>> {
+    get_paths(conn, SELECT_TOPIC_ATTACHMENT_PATHS, topic_id as u64)
+}
+
+/// 用户消息附件的路径，前端以用户消息的创建时间标识消息
+pub fn get_attachment_paths_by_time(conn: &Connection, created_at: u64) -> Result<Vec<String>> {
+    get_paths(conn, SELECT_ATTACHMENT_PATHS_BY_TIME, created_at)
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Ok, Result};
+use rusqlite::Connection;
+
+/// 每组对话（用户消息和最终回复）的向量，同一条消息可以保存不同模型的向量
+const MESSAGE_EMBEDDING_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS message_embedding (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_message_id INTEGER NOT NULL,
+        model TEXT NOT NULL,
+        dimensions INTEGER NOT NULL,
+        embedding BLOB NOT NULL,
+        created_at INTEGER NOT NULL,
+        UNIQUE (user_message_id, model),
+        CONSTRAINT fk_user_message
+        FOREIGN KEY (user_message_id)
+        REFERENCES user_message (id)
+    )
+"#;
+
+const MESSAGE_EMBEDDING_INSERT: &str = r#"
+    INSERT OR REPLACE INTO message_embedding (user_message_id, model, dimensions, embedding, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5);
+"#;
+
+/// 还没有建立索引的对话，失败和超时的回复不建立索引
+const SELECT_PENDING: &str = r#"
+    SELECT um.id, um.message, am.message
+    FROM user_message um
+    INNER JOIN assistant_message am ON um.id = am.user_message_id
+    WHERE am.status NOT IN ('error', 'timeout')
+        AND NOT EXISTS (
+            SELECT 1 FROM message_embedding me
+            WHERE me.user_message_id = um.id AND me.model = ?1
+        )
+    ORDER BY um.id
+    LIMIT ?2;
+"#;
+
+const SELECT_EMBEDDINGS: &str = r#"
+    SELECT me.user_message_id, um.topic_id, me.embedding
+    FROM message_embedding me
+    INNER JOIN user_message um ON um.id = me.user_message_id
+    WHERE me.model = ?1 AND (?2 IS NULL OR um.topic_id = ?2);
+"#;
+
+/// 等待建立索引的对话
+#[derive(Debug)]
+pub struct PendingConversation {
+    pub user_message_id: u32,
+    pub user: String,
+    pub assistant: String,
+}
+
+/// 保存的对话向量
+#[derive(Debug)]
+pub struct MessageEmbedding {
+    pub user_message_id: u32,
+    pub topic_id: u32,
+    pub embedding: Vec<f32>,
+}
+
+/// 向量以小端序的 f32 数组保存
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn decode_vector(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+pub fn init_embedding(conn: &Connection) -> Result<()> {
+    conn.execute(MESSAGE_EMBEDDING_TABLE, ())
+        .with_context(|| "创建 message_embedding 表失败")?;
+
+    Ok(())
+}
+
+pub fn get_pending_conversations(
+    conn: &Connection,
+    model: &str,
+    limit: usize,
+) -> Result<Vec<PendingConversation>> {
+    let mut stmt = conn
+        .prepare(SELECT_PENDING)
+        .with_context(|| "准备待索引对话查询语句时出错")?;
+
+    let pending = stmt
+        .query_map((model, limit), |row| {
+            std::result::Result::Ok(PendingConversation {
+                user_message_id: row.get(0)?,
+                user: row.get(1)?,
+                assistant: row.get(2)?,
+            })
+        })
+        .with_context(|| "获取待索引对话时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集待索引对话时出错")?;
+
+    Ok(pending)
+}
+
+pub fn insert_embedding(
+    conn: &Connection,
+    user_message_id: u32,
+    model: &str,
+    embedding: &[f32],
+    created_at: u64,
+) -> Result<()> {
+    conn.execute(
+        MESSAGE_EMBEDDING_INSERT,
+        (
+            user_message_id,
+            model,
+            embedding.len(),
+            encode_vector(embedding),
+            created_at,
+        ),
+    )
+    .with_context(|| {
+        format!(
+            "插入 message_embedding 失败：user_message_id={}, model={}",
+            user_message_id, model
+        )
+    })?;
+
+    Ok(())
+}
+
+/// 读取模型的所有向量，`topic_id` 为空时读取所有主题
+pub fn get_embeddings(
+    conn: &Connection,
+    model: &str,
+    topic_id: Option<u32>,
+) -> Result<Vec<MessageEmbedding>> {
+    let mut stmt = conn
+        .prepare(SELECT_EMBEDDINGS)
+        .with_context(|| "准备向量查询语句时出错")?;
+
+    let embeddings = stmt
+        .query_map((model, topic_id), |row| {
+            let data: Vec<u8> = row.get(2)?;
+            std::result::Result::Ok(MessageEmbedding {
+                user_message_id: row.get(0)?,
+                topic_id: row.get(1)?,
+                embedding: decode_vector(&data),
+            })
+        })
+        .with_context(|| "获取向量时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集向量时出错")?;
+
+    Ok(embeddings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector_roundtrip() {
+        let vector = vec![0.0, -1.5, 3.25, f32::MAX, f32::MIN_POSITIVE];
+
+        let data = encode_vector(&vector);
+        assert_eq!(data.len(), vector.len() * 4);
+        assert_eq!(&data[4..8], &(-1.5f32).to_le_bytes());
+        assert_eq!(decode_vector(&data), vector);
+    }
+
+    #[test]
+    fn decode_vector_ignores_trailing_bytes() {
+        let mut data = encode_vector(&[1.0, 2.0]);
+        data.push(0xFF);
+
+        assert_eq!(decode_vector(&data), vec![1.0, 2.0]);
+        assert!(decode_vector(&[]).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Ok, Result};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+const IMAGE_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS image (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        topic_id INTEGER NOT NULL,
+        kind TEXT NOT NULL DEFAULT 'generation',
+        prompt TEXT,
+        revised_prompt TEXT,
+        model TEXT NOT NULL,
+        size TEXT,
+        quality TEXT,
+        path TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        CONSTRAINT fk_topic
+        FOREIGN KEY (topic_id)
+        REFERENCES topic (id)
+    )
+"#;
+
+const IMAGE_INSERT: &str = r#"
+    INSERT INTO image (topic_id, kind, prompt, revised_prompt, model, size, quality, path, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);
+"#;
+
+const SELECT_IMAGES: &str = r#"
+    SELECT id, topic_id, kind, prompt, revised_prompt, model, size, quality, path, created_at
+    FROM image
+    WHERE topic_id = ?
+    ORDER BY id;
+"#;
+
+/// 生成的图片，`path` 为画廊目录中的文件
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImageRecord {
+    pub id: u32,
+    pub topic_id: u32,
+    /// `generation`、`edit` 或 `variation`
+    pub kind: String,
+    pub prompt: Option<String>,
+    /// dall-e-3 改写后实际使用的提示
+    pub revised_prompt: Option<String>,
+    pub model: String,
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    pub path: String,
+    pub created_at: u64,
+}
+
+impl ImageRecord {
+    pub fn insert(&mut self, conn: &Connection) -> Result<()> {
+        conn.execute(
+            IMAGE_INSERT,
+            (
+                self.topic_id,
+                &self.kind,
+                &self.prompt,
+                &self.revised_prompt,
+                &self.model,
+                &self.size,
+                &self.quality,
+                &self.path,
+                self.created_at,
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 image 失败：topic_id={}, path={}",
+                self.topic_id, self.path
+            )
+        })?;
+
+        self.id = conn.last_insert_rowid() as u32;
+
+        Ok(())
+    }
+}
+
+pub fn init_image(conn: &Connection) -> Result<()> {
+    conn.execute(IMAGE_TABLE, ())
+        .with_context(|| "创建 image 表失败")?;
+
+    Ok(())
+}
+
+pub fn get_images(conn: &Connection, topic_id: u32) -> Result<Vec<ImageRecord>> {
+    let mut stmt = conn
+        .prepare(SELECT_IMAGES)
+        .with_context(|| "准备图片查询语句时出错")?;
+
+    let images = stmt
+        .query_map([topic_id], |row| {
+            std::result::Result::Ok(ImageRecord {
+                id: row.get(0)?,
+                topic_id: row.get(1)?,
+                kind: row.get(2)?,
+                prompt: row.get(3)?,
+                revised_prompt: row.get(4)?,
+                model: row.get(5)?,
+                size: row.get(6)?,
+                quality: row.get(7)?,
+                path: row.get(8)?,
+                created_at: row.get(9)?,
+            })
+        })
+        .with_context(|| "获取图片时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集图片时出错")?;
+
+    Ok(images)
+}
+
+/// 删除图片记录，返回图片文件的路径
+pub fn delete_image(conn: &Connection, id: u32) -> Result<Option<String>> {
+    let path = conn
+        .query_row("SELECT path FROM image WHERE id = ?", [id], |row| {
+            row.get(0)
+        })
+        .optional()
+        .with_context(|| format!("查询图片失败：id={}", id))?;
+
+    conn.execute("DELETE FROM image WHERE id = ?", [id])
+        .with_context(|| format!("删除图片失败：id={}", id))?;
+
+    Ok(path)
+}
@@ -0,0 +1,276 @@
+use anyhow::{Context, Ok, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::embedding::{decode_vector, encode_vector};
+
+/// 导入到主题知识库的文件，只保存路径，内容保存在分段中
+const KNOWLEDGE_DOCUMENT_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS knowledge_document (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        topic_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        model TEXT NOT NULL,
+        chunks INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        CONSTRAINT fk_topic
+        FOREIGN KEY (topic_id)
+        REFERENCES topic (id)
+    )
+"#;
+
+const KNOWLEDGE_CHUNK_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS knowledge_chunk (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        document_id INTEGER NOT NULL,
+        topic_id INTEGER NOT NULL,
+        seq INTEGER NOT NULL,
+        content TEXT NOT NULL,
+        start_line INTEGER NOT NULL,
+        end_line INTEGER NOT NULL,
+        embedding BLOB NOT NULL,
+        CONSTRAINT fk_knowledge_document
+        FOREIGN KEY (document_id)
+        REFERENCES knowledge_document (id)
+    )
+"#;
+
+const KNOWLEDGE_DOCUMENT_INSERT: &str = r#"
+    INSERT INTO knowledge_document (topic_id, name, path, kind, model, chunks, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);
+"#;
+
+const KNOWLEDGE_CHUNK_INSERT: &str = r#"
+    INSERT INTO knowledge_chunk (document_id, topic_id, seq, content, start_line, end_line, embedding)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);
+"#;
+
+const SELECT_DOCUMENTS: &str = r#"
+    SELECT id, topic_id, name, path, kind, model, chunks, created_at
+    FROM knowledge_document
+    WHERE topic_id = ?
+    ORDER BY id;
+"#;
+
+const SELECT_CHUNKS: &str = r#"
+    SELECT kc.id, kc.document_id, kd.name, kd.path, kc.content, kc.start_line, kc.end_line,
+        kc.embedding
+    FROM knowledge_chunk kc
+    INNER JOIN knowledge_document kd ON kd.id = kc.document_id
+    WHERE kc.topic_id = ?1 AND kd.model = ?2;
+"#;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KnowledgeDocument {
+    pub id: u32,
+    pub topic_id: u32,
+    /// 文件名
+    pub name: String,
+    pub path: String,
+    /// `markdown`、`text`、`code` 或 `pdf`
+    pub kind: String,
+    /// 计算分段向量使用的模型，检索时只使用同一模型的分段
+    pub model: String,
+    pub chunks: usize,
+    pub created_at: u64,
+}
+
+impl KnowledgeDocument {
+    pub fn insert(&mut self, conn: &Connection) -> Result<()> {
+        conn.execute(
+            KNOWLEDGE_DOCUMENT_INSERT,
+            (
+                self.topic_id,
+                &self.name,
+                &self.path,
+                &self.kind,
+                &self.model,
+                self.chunks,
+                self.created_at,
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 knowledge_document 失败：topic_id={}, path={}",
+                self.topic_id, self.path
+            )
+        })?;
+
+        self.id = conn.last_insert_rowid() as u32;
+
+        Ok(())
+    }
+}
+
+/// 文件中的一段文本，行号从 1 开始
+#[derive(Debug)]
+pub struct KnowledgeChunk {
+    pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// 检索时读取的分段
+#[derive(Debug)]
+pub struct StoredChunk {
+    pub id: u32,
+    pub document_id: u32,
+    pub name: String,
+    pub path: String,
+    pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// 回复引用的分段，随回复一起保存，文件删除或重新导入后仍可查看当时的内容
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Citation {
+    /// 上下文中的编号，回复中以 `[n]` 引用
+    pub index: usize,
+    pub document_id: u32,
+    pub chunk_id: u32,
+    pub name: String,
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f32,
+    pub content: String,
+}
+
+pub fn init_knowledge(conn: &Connection) -> Result<()> {
+    conn.execute(KNOWLEDGE_DOCUMENT_TABLE, ())
+        .with_context(|| "创建 knowledge_document 表失败")?;
+    conn.execute(KNOWLEDGE_CHUNK_TABLE, ())
+        .with_context(|| "创建 knowledge_chunk 表失败")?;
+
+    Ok(())
+}
+
+/// 在一个事务中保存文件和所有分段，同一主题中路径相同的旧文件会被替换
+pub fn insert_document(
+    conn: &Connection,
+    document: &mut KnowledgeDocument,
+    chunks: &[KnowledgeChunk],
+) -> Result<()> {
+    let tx = conn
+        .unchecked_transaction()
+        .with_context(|| "开始导入文件的事务失败")?;
+
+    let old: Vec<u32> = {
+        let mut stmt =
+            tx.prepare("SELECT id FROM knowledge_document WHERE topic_id = ? AND path = ?")?;
+        let ids = stmt
+            .query_map((document.topic_id, &document.path), |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        ids
+    };
+    for id in old {
+        delete_document(&tx, id)?;
+    }
+
+    document.insert(&tx)?;
+
+    for (seq, chunk) in chunks.iter().enumerate() {
+        tx.execute(
+            KNOWLEDGE_CHUNK_INSERT,
+            (
+                document.id,
+                document.topic_id,
+                seq,
+                &chunk.content,
+                chunk.start_line,
+                chunk.end_line,
+                encode_vector(&chunk.embedding),
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 knowledge_chunk 失败：document_id={}, seq={}",
+                document.id, seq
+            )
+        })?;
+    }
+
+    tx.commit().with_context(|| "提交导入文件的事务失败")?;
+
+    Ok(())
+}
+
+pub fn get_documents(conn: &Connection, topic_id: u32) -> Result<Vec<KnowledgeDocument>> {
+    let mut stmt = conn
+        .prepare(SELECT_DOCUMENTS)
+        .with_context(|| "准备知识库文件查询语句时出错")?;
+
+    let documents = stmt
+        .query_map([topic_id], |row| {
+            std::result::Result::Ok(KnowledgeDocument {
+                id: row.get(0)?,
+                topic_id: row.get(1)?,
+                name: row.get(2)?,
+                path: row.get(3)?,
+                kind: row.get(4)?,
+                model: row.get(5)?,
+                chunks: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })
+        .with_context(|| "获取知识库文件时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集知识库文件时出错")?;
+
+    Ok(documents)
+}
+
+/// 主题中使用 `model` 计算向量的所有分段
+pub fn get_chunks(conn: &Connection, topic_id: u32, model: &str) -> Result<Vec<StoredChunk>> {
+    let mut stmt = conn
+        .prepare(SELECT_CHUNKS)
+        .with_context(|| "准备知识库分段查询语句时出错")?;
+
+    let chunks = stmt
+        .query_map((topic_id, model), |row| {
+            let data: Vec<u8> = row.get(7)?;
+            std::result::Result::Ok(StoredChunk {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                name: row.get(2)?,
+                path: row.get(3)?,
+                content: row.get(4)?,
+                start_line: row.get(5)?,
+                end_line: row.get(6)?,
+                embedding: decode_vector(&data),
+            })
+        })
+        .with_context(|| "获取知识库分段时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集知识库分段时出错")?;
+
+    Ok(chunks)
+}
+
+/// 主题的知识库中是否有文件，没有时发送消息不需要计算向量
+pub fn has_documents(conn: &Connection, topic_id: u32) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM knowledge_document WHERE topic_id = ? LIMIT 1)",
+        [topic_id],
+        |row| row.get(0),
+    )
+    .with_context(|| format!("查询知识库文件失败：topic_id={}", topic_id))
+}
+
+pub fn delete_document(conn: &Connection, id: u32) -> Result<()> {
+    let sql = format!(
+        r#"
+        DELETE FROM knowledge_chunk WHERE document_id = {};
+        DELETE FROM knowledge_document WHERE id = {};
+        "#,
+        id, id
+    );
+
+    conn.execute_batch(&sql)
+        .with_context(|| format!("删除知识库文件失败：id={}", id))
+}
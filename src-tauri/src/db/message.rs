@@ -1,6 +1,14 @@
 use anyhow::{Context, Ok, Result};
-use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
+use rusqlite::{
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+    Connection, ToSql,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::attachment::{get_attachments, Attachment};
+use super::knowledge::Citation;
+use super::moderation::{get_moderations, ModerationRecord};
+use crate::api::chat::{ImageUrl, Message, ToolCall};
 
 const USER_MESSAGE_TABLE: &str = r#"
     CREATE TABLE IF NOT EXISTS user_message (
@@ -24,6 +32,10 @@ const ASSISTANT_MESSAGE_TABLE: &str = r#"
         message TEXT NOT NULL,
         created_at INTEGER NOT NULL,
         user_message_id INTEGER NOT NULL,
+        status TEXT NOT NULL DEFAULT 'complete',
+        error TEXT,
+        tool_calls TEXT,
+        citations TEXT,
         CONSTRAINT fk_user_message
         FOREIGN KEY (user_message_id)
         REFERENCES user_message (id)
@@ -31,21 +43,86 @@ const ASSISTANT_MESSAGE_TABLE: &str = r#"
         "#;
 
 const ASSISTANT_MESSAGE_INSERT: &str = r#"
-        INSERT INTO assistant_message (message, created_at, user_message_id)
-        VALUES (?1, ?2, ?3);
+        INSERT INTO assistant_message (message, created_at, user_message_id, status, error, tool_calls, citations)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);
         "#;
 
+/// 一次回复中间调用工具的过程：模型请求调用工具的回复（assistant）和工具的执行结果（tool），
+/// 按 id 顺序排列在用户消息和最终回复之间
+const TOOL_MESSAGE_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS tool_message (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_message_id INTEGER NOT NULL,
+        role TEXT NOT NULL,
+        message TEXT NOT NULL,
+        tool_calls TEXT,
+        tool_call_id TEXT,
+        name TEXT,
+        created_at INTEGER NOT NULL,
+        CONSTRAINT fk_user_message
+        FOREIGN KEY (user_message_id)
+        REFERENCES user_message (id)
+    )
+"#;
+
+const TOOL_MESSAGE_INSERT: &str = r#"
+    INSERT INTO tool_message (user_message_id, role, message, tool_calls, tool_call_id, name, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);
+    "#;
+
+const SELECT_TOOL_MESSAGES: &str = r#"
+    SELECT tm.id, tm.user_message_id, tm.role, tm.message, tm.tool_calls, tm.tool_call_id, tm.name,
+        tm.created_at
+    FROM tool_message tm
+    INNER JOIN user_message um ON um.id = tm.user_message_id
+    WHERE um.topic_id = ?
+    ORDER BY tm.id;
+"#;
+
+/// 旧版本数据库中 assistant_message 缺少的列
+const ASSISTANT_MESSAGE_COLUMNS: [(&str, &str); 4] = [
+    ("status", "TEXT NOT NULL DEFAULT 'complete'"),
+    ("error", "TEXT"),
+    ("tool_calls", "TEXT"),
+    ("citations", "TEXT"),
+];
+
 const SELECT_ALL_MESSAGES: &str = r#"
-    SELECT um.id, um.message, um.created_at, am.id, am.message, am.created_at, am.user_message_id
+    SELECT um.id, um.message, um.created_at, am.id, am.message, am.created_at, am.user_message_id,
+        am.status, am.error, am.tool_calls, am.citations
     FROM user_message um
     INNER JOIN assistant_message am ON um.id = am.user_message_id
     WHERE um.topic_id = ?;
 "#;
 
+const SELECT_ASSISTANT_MESSAGE_BY_TIME: &str = r#"
+    SELECT am.id, am.message, am.created_at, am.user_message_id, am.status, am.error, am.tool_calls,
+        am.citations
+    FROM assistant_message am
+    INNER JOIN user_message um ON um.id = am.user_message_id
+    WHERE um.created_at = ?
+    ORDER BY am.id DESC
+    LIMIT 1;
+"#;
+
+const SELECT_TOPIC_ASSISTANT_MESSAGE_IDS: &str = r#"
+    SELECT am.id
+    FROM assistant_message am
+    INNER JOIN user_message um ON um.id = am.user_message_id
+    WHERE um.topic_id = ?;
+"#;
+
+const SELECT_ASSISTANT_MESSAGE_IDS_BY_TIME: &str = r#"
+    SELECT am.id
+    FROM assistant_message am
+    INNER JOIN user_message um ON um.id = am.user_message_id
+    WHERE um.created_at = ?;
+"#;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UserMessage {
     id: u32,
-    message: String,
+    pub message: String,
     created_at: u64,
     topic_id: u32,
 }
@@ -60,6 +137,10 @@ impl UserMessage {
         };
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
     pub fn insert(&self, conn: &Connection) -> Result<usize> {
         conn.execute(
             USER_MESSAGE_INSERT,
@@ -80,28 +161,145 @@ impl UserMessage {
 //         .with_context(|| format!("查询 user_message 失败：id={}", user_message_id))
 // }
 
+/// 助手回复的状态
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageStatus {
+    /// 正常结束
+    #[default]
+    Complete,
+    /// 用户中断，只保存了部分回复
+    Aborted,
+    /// 请求失败，可能保存了部分回复
+    Error,
+    /// 连接或等待 chunk 超时，可能保存了部分回复
+    Timeout,
+    /// 达到 max_tokens 或上下文长度被截断
+    Length,
+}
+
+impl MessageStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageStatus::Complete => "complete",
+            MessageStatus::Aborted => "aborted",
+            MessageStatus::Error => "error",
+            MessageStatus::Timeout => "timeout",
+            MessageStatus::Length => "length",
+        }
+    }
+
+    /// 根据接口返回的 finish_reason 判断状态
+    pub fn from_finish_reason(reason: Option<&str>) -> Self {
+        match reason {
+            Some("length") => MessageStatus::Length,
+            _ => MessageStatus::Complete,
+        }
+    }
+}
+
+impl ToSql for MessageStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        std::result::Result::Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for MessageStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "complete" => std::result::Result::Ok(MessageStatus::Complete),
+            "aborted" => std::result::Result::Ok(MessageStatus::Aborted),
+            "error" => std::result::Result::Ok(MessageStatus::Error),
+            "timeout" => std::result::Result::Ok(MessageStatus::Timeout),
+            "length" => std::result::Result::Ok(MessageStatus::Length),
+            s => Err(FromSqlError::Other(format!("未知的消息状态：{}", s).into())),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AssistantMessage {
     id: u32,
-    message: String,
+    pub message: String,
     created_at: u64,
     user_message_id: u32,
+    pub status: MessageStatus,
+    /// 请求失败时的错误信息
+    pub error: Option<String>,
+    /// 回复中请求调用但未执行的工具
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// 发送前从主题知识库中检索并加入上下文的片段
+    pub citations: Option<Vec<Citation>>,
+}
+
+/// 以 JSON 保存工具调用和引用
+fn json_to_sql<T: Serialize>(value: &Option<T>) -> Result<Option<String>> {
+    value
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .with_context(|| "序列化 JSON 列失败")
+}
+
+fn json_from_sql<T: DeserializeOwned>(value: Option<String>) -> rusqlite::Result<Option<T>> {
+    value
+        .map(|v| serde_json::from_str(&v))
+        .transpose()
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+        })
 }
 
 impl AssistantMessage {
-    pub fn new(message: String, created_at: u64, user_message_id: u32) -> Self {
+    pub fn new(
+        message: String,
+        created_at: u64,
+        user_message_id: u32,
+        status: MessageStatus,
+        error: Option<String>,
+    ) -> Self {
         return AssistantMessage {
             id: 0,
             message,
             created_at,
             user_message_id,
+            status,
+            error,
+            tool_calls: None,
+            citations: None,
         };
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        if !tool_calls.is_empty() {
+            self.tool_calls = Some(tool_calls);
+        }
+        self
+    }
+
+    pub fn with_citations(mut self, citations: Vec<Citation>) -> Self {
+        if !citations.is_empty() {
+            self.citations = Some(citations);
+        }
+        self
+    }
+
     pub fn insert(&self, conn: &Connection) -> Result<usize> {
         conn.execute(
             ASSISTANT_MESSAGE_INSERT,
-            (&self.message, &self.created_at, &self.user_message_id),
+            (
+                &self.message,
+                &self.created_at,
+                &self.user_message_id,
+                &self.status,
+                &self.error,
+                &json_to_sql(&self.tool_calls)?,
+                &json_to_sql(&self.citations)?,
+            ),
         )
         .with_context(|| {
             format!(
@@ -123,14 +321,218 @@ pub fn init_messages(conn: &Connection) -> Result<()> {
         .with_context(|| format!("创建 user_message 表失败"))?;
     conn.execute(ASSISTANT_MESSAGE_TABLE, ())
         .with_context(|| format!("创建 assistant_message 表失败"))?;
+    conn.execute(TOOL_MESSAGE_TABLE, ())
+        .with_context(|| "创建 tool_message 表失败")?;
+
+    migrate_assistant_message(conn)?;
+
+    Ok(())
+}
+
+/// 为旧版本创建的 assistant_message 表添加缺少的列
+fn migrate_assistant_message(conn: &Connection) -> Result<()> {
+    let mut stmt = conn
+        .prepare("PRAGMA table_info(assistant_message)")
+        .with_context(|| "查询 assistant_message 表结构失败")?;
+
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .with_context(|| "查询 assistant_message 表结构失败")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集 assistant_message 列名失败")?;
+
+    for (name, definition) in ASSISTANT_MESSAGE_COLUMNS {
+        if columns.iter().any(|c| c == name) {
+            continue;
+        }
+
+        conn.execute(
+            &format!(
+                "ALTER TABLE assistant_message ADD COLUMN {} {}",
+                name, definition
+            ),
+            (),
+        )
+        .with_context(|| format!("为 assistant_message 添加 {} 列失败", name))?;
+
+        info!("已为 assistant_message 添加列：{}", name);
+    }
 
     Ok(())
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ToolMessage {
+    id: u32,
+    user_message_id: u32,
+    /// `assistant` 为请求调用工具的回复，`tool` 为执行结果
+    pub role: String,
+    pub message: String,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    pub tool_call_id: Option<String>,
+    pub name: Option<String>,
+    created_at: u64,
+}
+
+impl ToolMessage {
+    pub fn new(user_message_id: u32, message: &Message, created_at: u64) -> Self {
+        let tool_calls = message.calls();
+
+        ToolMessage {
+            id: 0,
+            user_message_id,
+            role: message.role.clone(),
+            message: message.content.text(),
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
+            },
+            tool_call_id: message.tool_call_id.clone(),
+            name: message.name.clone(),
+            created_at,
+        }
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut message = Message::new(&self.role, &self.message);
+        match &self.tool_calls {
+            // 旧版 functions 接口的调用没有 id
+            Some(calls) if calls.iter().all(|c| c.id.is_empty()) => {
+                message.function_call = calls.first().map(|c| c.function.clone());
+            }
+            calls => message.tool_calls = calls.clone(),
+        }
+        message.tool_call_id = self.tool_call_id.clone();
+        message.name = self.name.clone();
+        message
+    }
+
+    pub fn insert(&self, conn: &Connection) -> Result<usize> {
+        conn.execute(
+            TOOL_MESSAGE_INSERT,
+            (
+                &self.user_message_id,
+                &self.role,
+                &self.message,
+                &json_to_sql(&self.tool_calls)?,
+                &self.tool_call_id,
+                &self.name,
+                &self.created_at,
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 tool_message 失败：user_message_id={}, role={}",
+                self.user_message_id, self.role
+            )
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Conversation {
-    user: UserMessage,
-    assistant: AssistantMessage,
+    pub user: UserMessage,
+    /// 用户消息附带的图片
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// 发送前的审核结果，主题没有配置审核策略时为空
+    #[serde(default)]
+    pub moderation: Option<ModerationRecord>,
+    /// 最终回复之前调用工具的过程
+    pub tools: Vec<ToolMessage>,
+    pub assistant: AssistantMessage,
+}
+
+impl Conversation {
+    /// 作为上下文的消息，最终回复中未执行的工具调用不发送，否则接口会要求对应的执行结果
+    ///
+    /// 附带的图片使用保存的路径，发送前由 `vision::inline_images` 转换为 `data:` URL。
+    pub fn messages(&self) -> Vec<Message> {
+        let images = self
+            .attachments
+            .iter()
+            .filter(|a| a.kind == "image")
+            .map(|a| ImageUrl {
+                url: a.path.clone(),
+                detail: a.detail.clone(),
+            })
+            .collect();
+
+        let mut messages = vec![Message::new("user", &self.user.message).with_images(images)];
+        messages.extend(self.tools.iter().map(|t| t.to_message()));
+        messages.push(Message::new("assistant", &self.assistant.message));
+        messages
+    }
+}
+
+/// 根据用户消息的创建时间查询对应的回复，前端以用户消息的时间标识一组对话
+pub fn get_assistant_message_by_time(
+    conn: &Connection,
+    created_at: u64,
+) -> Result<AssistantMessage> {
+    conn.query_row(SELECT_ASSISTANT_MESSAGE_BY_TIME, [created_at], |row| {
+        std::result::Result::Ok(AssistantMessage {
+            id: row.get(0)?,
+            message: row.get(1)?,
+            created_at: row.get(2)?,
+            user_message_id: row.get(3)?,
+            status: row.get(4)?,
+            error: row.get(5)?,
+            tool_calls: json_from_sql(row.get(6)?)?,
+            citations: json_from_sql(row.get(7)?)?,
+        })
+    })
+    .with_context(|| format!("查询回复失败：created_at={}", created_at))
+}
+
+fn get_assistant_message_ids(conn: &Connection, sql: &str, param: u64) -> Result<Vec<u32>> {
+    let mut stmt = conn
+        .prepare(sql)
+        .with_context(|| "准备回复 id 查询语句时出错")?;
+
+    let ids = stmt
+        .query_map([param], |row| row.get(0))
+        .with_context(|| "获取回复 id 时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集回复 id 时出错")?;
+
+    Ok(ids)
+}
+
+/// 主题中所有回复的 id，删除主题或清空消息后用来删除语音缓存
+pub fn get_topic_assistant_message_ids(conn: &Connection, topic_id: u32) -> Result<Vec<u32>> {
+    get_assistant_message_ids(conn, SELECT_TOPIC_ASSISTANT_MESSAGE_IDS, topic_id as u64)
+}
+
+/// 用户消息对应的所有回复的 id
+pub fn get_assistant_message_ids_by_time(conn: &Connection, created_at: u64) -> Result<Vec<u32>> {
+    get_assistant_message_ids(conn, SELECT_ASSISTANT_MESSAGE_IDS_BY_TIME, created_at)
+}
+
+fn get_tool_messages(conn: &Connection, topic_id: u32) -> Result<Vec<ToolMessage>> {
+    let mut stmt = conn
+        .prepare(SELECT_TOOL_MESSAGES)
+        .with_context(|| "准备工具消息查询语句时出错")?;
+
+    let messages = stmt
+        .query_map([topic_id], |row| {
+            std::result::Result::Ok(ToolMessage {
+                id: row.get(0)?,
+                user_message_id: row.get(1)?,
+                role: row.get(2)?,
+                message: row.get(3)?,
+                tool_calls: json_from_sql(row.get(4)?)?,
+                tool_call_id: row.get(5)?,
+                name: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })
+        .with_context(|| "获取工具消息时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集工具消息时出错")?;
+
+    Ok(messages)
 }
 
 pub fn get_messages(conn: &Connection, topic_id: u32) -> Result<Vec<Conversation>> {
@@ -138,7 +540,11 @@ pub fn get_messages(conn: &Connection, topic_id: u32) -> Result<Vec<Conversation
         .prepare(SELECT_ALL_MESSAGES)
         .with_context(|| format!("准备所有消息查询语句时出错"))?;
 
-    let conversations = stmt
+    let mut tool_messages = get_tool_messages(conn, topic_id)?;
+    let mut attachments = get_attachments(conn, topic_id)?;
+    let mut moderations = get_moderations(conn, topic_id)?;
+
+    let mut conversations = stmt
         .query_map([topic_id], |row| {
             std::result::Result::Ok(Conversation {
                 user: UserMessage {
@@ -147,11 +553,18 @@ pub fn get_messages(conn: &Connection, topic_id: u32) -> Result<Vec<Conversation
                     created_at: row.get(2)?,
                     topic_id,
                 },
+                attachments: Vec::new(),
+                moderation: None,
+                tools: Vec::new(),
                 assistant: AssistantMessage {
                     id: row.get(3)?,
                     message: row.get(4)?,
                     created_at: row.get(5)?,
                     user_message_id: row.get(6)?,
+                    status: row.get(7)?,
+                    error: row.get(8)?,
+                    tool_calls: json_from_sql(row.get(9)?)?,
+                    citations: json_from_sql(row.get(10)?)?,
                 },
             })
         })
@@ -159,9 +572,86 @@ pub fn get_messages(conn: &Connection, topic_id: u32) -> Result<Vec<Conversation
         .collect::<Result<Vec<_>, _>>()
         .with_context(|| format!("收集所有消息时出错"))?;
 
+    for conversation in conversations.iter_mut() {
+        let (tools, rest) = tool_messages
+            .into_iter()
+            .partition(|t| t.user_message_id == conversation.user.id);
+
+        conversation.tools = tools;
+        tool_messages = rest;
+
+        let (images, rest) = attachments
+            .into_iter()
+            .partition(|a| a.user_message_id == conversation.user.id);
+
+        conversation.attachments = images;
+        attachments = rest;
+
+        let (records, rest): (Vec<_>, _) = moderations
+            .into_iter()
+            .partition(|m| m.user_message_id == conversation.user.id);
+
+        conversation.moderation = records.into_iter().last();
+        moderations = rest;
+    }
+
     Ok(conversations)
 }
 
+const SEARCH_MESSAGES: &str = r#"
+    SELECT um.topic_id, um.message, am.message, um.created_at
+    FROM user_message um
+    INNER JOIN assistant_message am ON um.id = am.user_message_id
+    WHERE (um.message LIKE ?1 ESCAPE '\' OR am.message LIKE ?1 ESCAPE '\')
+        AND (?2 IS NULL OR um.topic_id = ?2)
+    ORDER BY um.created_at DESC
+    LIMIT ?3;
+"#;
+
+/// 搜索历史对话的结果
+#[derive(Debug, Serialize)]
+pub struct MessageMatch {
+    pub topic_id: u32,
+    pub user: String,
+    pub assistant: String,
+    pub created_at: u64,
+}
+
+/// 在用户消息和回复中搜索关键词，`topic_id` 为空时搜索所有主题
+pub fn search_messages(
+    conn: &Connection,
+    query: &str,
+    topic_id: Option<u32>,
+    limit: u32,
+) -> Result<Vec<MessageMatch>> {
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    let mut stmt = conn
+        .prepare(SEARCH_MESSAGES)
+        .with_context(|| "准备搜索消息语句时出错")?;
+
+    let matches = stmt
+        .query_map((&pattern, &topic_id, &limit), |row| {
+            std::result::Result::Ok(MessageMatch {
+                topic_id: row.get(0)?,
+                user: row.get(1)?,
+                assistant: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })
+        .with_context(|| format!("搜索消息时出错：{}", query))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集搜索结果时出错")?;
+
+    Ok(matches)
+}
+
 pub fn delete_user_message_by_time(conn: &Connection, create_at: u64) -> Result<()> {
     let sql = format!(
         r#"
@@ -169,10 +659,22 @@ pub fn delete_user_message_by_time(conn: &Connection, create_at: u64) -> Result<
         DELETE FROM assistant_message WHERE user_message_id = (
             SELECT id FROM user_message WHERE created_at = {}
         );
+        DELETE FROM tool_message WHERE user_message_id = (
+            SELECT id FROM user_message WHERE created_at = {}
+        );
+        DELETE FROM attachment WHERE user_message_id = (
+            SELECT id FROM user_message WHERE created_at = {}
+        );
+        DELETE FROM message_embedding WHERE user_message_id = (
+            SELECT id FROM user_message WHERE created_at = {}
+        );
+        DELETE FROM moderation WHERE user_message_id = (
+            SELECT id FROM user_message WHERE created_at = {}
+        );
         DELETE FROM user_message WHERE created_at = {};
         COMMIT;
         "#,
-        create_at, create_at
+        create_at, create_at, create_at, create_at, create_at, create_at
     );
 
     conn.execute_batch(&sql)
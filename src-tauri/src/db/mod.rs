@@ -1,3 +1,9 @@
+pub mod attachment;
+pub mod embedding;
+pub mod image;
+pub mod knowledge;
 pub mod manager;
 pub mod message;
+pub mod moderation;
 pub mod topic;
+pub mod usage;
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Ok, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+/// 用户消息发送前的审核结果
+const MODERATION_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS moderation (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_message_id INTEGER NOT NULL,
+        model TEXT NOT NULL,
+        flagged INTEGER NOT NULL,
+        action TEXT,
+        categories TEXT NOT NULL,
+        scores TEXT NOT NULL,
+        error TEXT,
+        created_at INTEGER NOT NULL,
+        CONSTRAINT fk_user_message
+        FOREIGN KEY (user_message_id)
+        REFERENCES user_message (id)
+    )
+"#;
+
+const MODERATION_INSERT: &str = r#"
+    INSERT INTO moderation (user_message_id, model, flagged, action, categories, scores, error, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);
+"#;
+
+const SELECT_MODERATIONS: &str = r#"
+    SELECT m.id, m.user_message_id, m.model, m.flagged, m.action, m.categories, m.scores, m.error,
+        m.created_at
+    FROM moderation m
+    INNER JOIN user_message um ON um.id = m.user_message_id
+    WHERE um.topic_id = ?
+    ORDER BY m.id;
+"#;
+
+/// 审核结果，`categories` 为命中的类别及对应的处理
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ModerationRecord {
+    pub id: u32,
+    pub user_message_id: u32,
+    pub model: String,
+    pub flagged: bool,
+    /// 命中类别中最严格的处理，没有命中时为空
+    pub action: Option<String>,
+    pub categories: BTreeMap<String, String>,
+    pub scores: BTreeMap<String, f64>,
+    /// 审核请求失败时的错误信息
+    pub error: Option<String>,
+    pub created_at: u64,
+}
+
+impl ModerationRecord {
+    pub fn insert(&mut self, conn: &Connection) -> Result<()> {
+        conn.execute(
+            MODERATION_INSERT,
+            (
+                self.user_message_id,
+                &self.model,
+                self.flagged,
+                &self.action,
+                serde_json::to_string(&self.categories)?,
+                serde_json::to_string(&self.scores)?,
+                &self.error,
+                self.created_at,
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 moderation 失败：user_message_id={}",
+                self.user_message_id
+            )
+        })?;
+
+        self.id = conn.last_insert_rowid() as u32;
+
+        Ok(())
+    }
+}
+
+fn json_from_sql<T: serde::de::DeserializeOwned>(value: String) -> rusqlite::Result<T> {
+    serde_json::from_str(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+pub fn init_moderation(conn: &Connection) -> Result<()> {
+    conn.execute(MODERATION_TABLE, ())
+        .with_context(|| "创建 moderation 表失败")?;
+
+    Ok(())
+}
+
+pub fn get_moderations(conn: &Connection, topic_id: u32) -> Result<Vec<ModerationRecord>> {
+    let mut stmt = conn
+        .prepare(SELECT_MODERATIONS)
+        .with_context(|| "准备审核结果查询语句时出错")?;
+
+    let records = stmt
+        .query_map([topic_id], |row| {
+            std::result::Result::Ok(ModerationRecord {
+                id: row.get(0)?,
+                user_message_id: row.get(1)?,
+                model: row.get(2)?,
+                flagged: row.get(3)?,
+                action: row.get(4)?,
+                categories: json_from_sql(row.get(5)?)?,
+                scores: json_from_sql(row.get(6)?)?,
+                error: row.get(7)?,
+                created_at: row.get(8)?,
+            })
+        })
+        .with_context(|| "获取审核结果时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集审核结果时出错")?;
+
+    Ok(records)
+}
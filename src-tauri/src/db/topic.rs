@@ -87,6 +87,9 @@ pub fn topic_exists_by_name(conn: &Connection, name: &str) -> Result<bool> {
     Ok(exists)
 }
 
+pub const FREE_TOPIC_ID: u32 = 1;
+pub const PROMPT_TOPIC_ID: u32 = 2;
+
 const FREE_TOPIC_NAME: &str = "自由对话";
 const FREE_TOPIC_DESCRIPTION: &str =
     "不使用上下文的简单问答，可在此主题中提问一些常识或答案偏固定的问题。";
@@ -100,8 +103,13 @@ pub fn init_topic(conn: &Connection) -> Result<()> {
         .with_context(|| format!("创建主题表时出错"))?;
 
     let topics: [Topic; 2] = [
-        Topic::new_with_id(1, FREE_TOPIC_NAME, FREE_TOPIC_DESCRIPTION, 0)?,
-        Topic::new_with_id(2, PROMPT_TOPIC_NAME, PROMPT_TOPIC_DESCRIPTION, 0)?,
+        Topic::new_with_id(FREE_TOPIC_ID, FREE_TOPIC_NAME, FREE_TOPIC_DESCRIPTION, 0)?,
+        Topic::new_with_id(
+            PROMPT_TOPIC_ID,
+            PROMPT_TOPIC_NAME,
+            PROMPT_TOPIC_DESCRIPTION,
+            0,
+        )?,
     ];
 
     for topic in topics.iter() {
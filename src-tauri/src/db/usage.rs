@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Ok, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ModelPrice;
+
+const USAGE_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS usage (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        topic_id INTEGER NOT NULL,
+        model TEXT NOT NULL,
+        prompt_tokens INTEGER NOT NULL,
+        completion_tokens INTEGER NOT NULL,
+        estimated INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL,
+        CONSTRAINT fk_topic
+        FOREIGN KEY (topic_id)
+        REFERENCES topic (id)
+    )
+"#;
+
+const USAGE_INSERT: &str = r#"
+    INSERT INTO usage (topic_id, model, prompt_tokens, completion_tokens, estimated, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6);
+"#;
+
+/// 一次请求的 token 用量
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UsageRecord {
+    id: u32,
+    topic_id: u32,
+    model: String,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    /// 流式响应不返回用量，由本地分词器估算
+    estimated: bool,
+    created_at: u64,
+}
+
+impl UsageRecord {
+    pub fn new(
+        topic_id: u32,
+        model: &str,
+        prompt_tokens: u32,
+        completion_tokens: u32,
+        estimated: bool,
+        created_at: u64,
+    ) -> Self {
+        UsageRecord {
+            id: 0,
+            topic_id,
+            model: model.into(),
+            prompt_tokens,
+            completion_tokens,
+            estimated,
+            created_at,
+        }
+    }
+
+    pub fn insert(&self, conn: &Connection) -> Result<usize> {
+        conn.execute(
+            USAGE_INSERT,
+            (
+                self.topic_id,
+                &self.model,
+                self.prompt_tokens,
+                self.completion_tokens,
+                self.estimated,
+                self.created_at,
+            ),
+        )
+        .with_context(|| {
+            format!(
+                "插入 usage 失败：topic_id={}, model={}",
+                self.topic_id, self.model
+            )
+        })
+    }
+}
+
+pub fn init_usage(conn: &Connection) -> Result<()> {
+    conn.execute(USAGE_TABLE, ())
+        .with_context(|| "创建 usage 表失败")?;
+
+    Ok(())
+}
+
+/// 用量的统计维度
+#[derive(Debug, Clone, Copy)]
+pub enum UsageGroup {
+    Day,
+    Model,
+    Topic,
+}
+
+impl UsageGroup {
+    fn column(&self) -> &'static str {
+        match self {
+            UsageGroup::Day => "date(created_at, 'unixepoch', 'localtime')",
+            UsageGroup::Model => "model",
+            UsageGroup::Topic => "CAST(topic_id AS TEXT)",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct UsageSummary {
+    /// 日期（YYYY-MM-DD）、模型名或主题 id
+    pub key: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// 费用，单位为美元
+    pub cost: f64,
+}
+
+/// 按前缀匹配模型价格，如 `gpt-4-0613` 使用 `gpt-4` 的价格，取最长的匹配
+fn find_price<'a>(pricing: &'a HashMap<String, ModelPrice>, model: &str) -> Option<&'a ModelPrice> {
+    pricing
+        .iter()
+        .filter(|(name, _)| model.starts_with(name.as_str()))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, price)| price)
+}
+
+/// 统计 `[since, until)` 时间范围内的用量
+pub fn summarize_usage(
+    conn: &Connection,
+    group: UsageGroup,
+    since: Option<u64>,
+    until: Option<u64>,
+    pricing: &HashMap<String, ModelPrice>,
+) -> Result<Vec<UsageSummary>> {
+    let sql = format!(
+        r#"
+        SELECT {} AS key, model, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens)
+        FROM usage
+        WHERE created_at >= ?1 AND created_at < ?2
+        GROUP BY key, model
+        ORDER BY key
+        "#,
+        group.column()
+    );
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .with_context(|| "准备用量统计语句时出错")?;
+
+    let rows = stmt
+        .query_map(
+            (since.unwrap_or(0), until.unwrap_or(i64::MAX as u64)),
+            |row| {
+                std::result::Result::Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, u64>(4)?,
+                ))
+            },
+        )
+        .with_context(|| "统计用量时出错")?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "收集用量统计时出错")?;
+
+    // 费用按模型计算，再合并到统计维度
+    let mut summaries: Vec<UsageSummary> = Vec::new();
+
+    for (key, model, requests, prompt_tokens, completion_tokens) in rows {
+        let cost = find_price(pricing, &model)
+            .map(|p| p.cost(prompt_tokens, completion_tokens))
+            .unwrap_or_default();
+
+        let summary = match summaries.last_mut() {
+            Some(s) if s.key == key => s,
+            _ => {
+                summaries.push(UsageSummary {
+                    key,
+                    ..Default::default()
+                });
+                summaries.last_mut().unwrap()
+            }
+        };
+
+        summary.requests += requests;
+        summary.prompt_tokens += prompt_tokens;
+        summary.completion_tokens += completion_tokens;
+        summary.cost += cost;
+    }
+
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::pricing_table;
+
+    fn prompt_price(model: &str) -> Option<f64> {
+        find_price(&pricing_table(None), model).map(|p| p.prompt)
+    }
+
+    #[test]
+    fn find_price_uses_longest_prefix() {
+        assert_eq!(prompt_price("gpt-4"), Some(0.03));
+        assert_eq!(prompt_price("gpt-4-0613"), Some(0.03));
+        assert_eq!(prompt_price("gpt-4-turbo-2024-04-09"), Some(0.01));
+        assert_eq!(prompt_price("gpt-4o"), Some(0.0025));
+        assert_eq!(prompt_price("gpt-4o-2024-08-06"), Some(0.0025));
+        assert_eq!(prompt_price("gpt-4o-mini"), Some(0.00015));
+        assert_eq!(prompt_price("gpt-4o-mini-2024-07-18"), Some(0.00015));
+        assert_eq!(prompt_price("gpt-4.1-mini"), Some(0.0004));
+        assert_eq!(prompt_price("llama3"), None);
+    }
+}
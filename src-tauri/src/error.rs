@@ -1 +1,236 @@
-pub type Result<T, E = String> = std::result::Result<T, E>;
+use std::fmt;
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+pub type Result<T, E = AppError> = std::result::Result<T, E>;
+
+/// 返回给前端的错误，序列化为
+/// `{kind, message, retryable, http_status, provider_code}`，
+/// 前端可根据 `kind` 做不同的处理。
+#[derive(Debug)]
+pub enum AppError {
+    /// 接口返回的错误
+    Api {
+        status: u16,
+        message: String,
+        /// OpenAI 错误体中的 `code`，没有时使用 `type`
+        code: Option<String>,
+    },
+    /// 连接失败
+    Network(String),
+    /// 代理配置错误或无法连接代理
+    Proxy(String),
+    Timeout(String),
+    Database(String),
+    Config(String),
+    Io(String),
+    /// 响应无法解析
+    Parse(String),
+    /// 内容审核未通过，拒绝发送
+    Moderation(String),
+    Other(String),
+}
+
+impl AppError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Api { status, code, .. } => match (status, code.as_deref()) {
+                (_, Some("insufficient_quota")) => "insufficient_quota",
+                (_, Some("context_length_exceeded")) => "context_length_exceeded",
+                (401, _) | (_, Some("invalid_api_key")) => "invalid_api_key",
+                (429, _) => "rate_limit",
+                _ => "api",
+            },
+            AppError::Network(_) => "network",
+            AppError::Proxy(_) => "proxy",
+            AppError::Timeout(_) => "timeout",
+            AppError::Database(_) => "database",
+            AppError::Config(_) => "config",
+            AppError::Io(_) => "io",
+            AppError::Parse(_) => "parse",
+            AppError::Moderation(_) => "moderation",
+            AppError::Other(_) => "other",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Api { message, .. } => message,
+            AppError::Network(m)
+            | AppError::Proxy(m)
+            | AppError::Timeout(m)
+            | AppError::Database(m)
+            | AppError::Config(m)
+            | AppError::Io(m)
+            | AppError::Parse(m)
+            | AppError::Moderation(m)
+            | AppError::Other(m) => m,
+        }
+    }
+
+    /// 稍后重试是否可能成功
+    pub fn retryable(&self) -> bool {
+        match self {
+            AppError::Api { status, .. } => {
+                self.kind() != "insufficient_quota"
+                    && matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+            }
+            AppError::Network(_) | AppError::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            AppError::Api { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn provider_code(&self) -> Option<&str> {
+        match self {
+            AppError::Api { code, .. } => code.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.http_status() {
+            Some(status) => write!(f, "[{}] {}: {}", self.kind(), status, self.message()),
+            None => write!(f, "[{}] {}", self.kind(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 5)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("retryable", &self.retryable())?;
+        state.serialize_field("http_status", &self.http_status())?;
+        state.serialize_field("provider_code", &self.provider_code())?;
+        state.end()
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Other(message.to_string())
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(err: reqwest::Error) -> Self {
+        // reqwest 没有单独的代理错误，只能从底层错误中判断。
+        // 错误本身的信息包含请求地址，不参与判断，避免地址中的 proxy 被误认为代理错误
+        let mut causes = Vec::new();
+        let mut source = std::error::Error::source(&err);
+        while let Some(e) = source {
+            causes.push(e.to_string());
+            source = e.source();
+        }
+
+        let lower = causes.join(": ").to_lowercase();
+        let from_proxy = ["proxy", "socks", "tunnel"]
+            .iter()
+            .any(|marker| lower.contains(marker));
+
+        let mut chain = err.to_string();
+        for cause in &causes {
+            chain.push_str(": ");
+            chain.push_str(cause);
+        }
+
+        if err.is_timeout() {
+            AppError::Timeout(chain)
+        } else if from_proxy {
+            AppError::Proxy(chain)
+        } else if err.is_connect() || err.is_request() {
+            AppError::Network(chain)
+        } else if err.is_decode() {
+            AppError::Parse(chain)
+        } else {
+            AppError::Other(chain)
+        }
+    }
+}
+
+impl From<reqwest_eventsource::Error> for AppError {
+    fn from(err: reqwest_eventsource::Error) -> Self {
+        match err {
+            reqwest_eventsource::Error::Transport(e) => e.into(),
+            reqwest_eventsource::Error::InvalidStatusCode(status, _) => AppError::Api {
+                status: status.as_u16(),
+                message: status.to_string(),
+                code: None,
+            },
+            e => AppError::Parse(e.to_string()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        AppError::Database(err.to_string())
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(err: r2d2::Error) -> Self {
+        AppError::Database(err.to_string())
+    }
+}
+
+/// db 模块返回带上下文的 anyhow 错误，根错误为 rusqlite 错误时视为数据库错误
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        let message = format!("{:#}", err);
+
+        if err.root_cause().is::<rusqlite::Error>() || err.root_cause().is::<r2d2::Error>() {
+            AppError::Database(message)
+        } else {
+            AppError::Other(message)
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Parse(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
+
+impl From<toml::de::Error> for AppError {
+    fn from(err: toml::de::Error) -> Self {
+        AppError::Config(err.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for AppError {
+    fn from(err: toml::ser::Error) -> Self {
+        AppError::Config(err.to_string())
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(err: tauri::Error) -> Self {
+        AppError::Other(err.to_string())
+    }
+}
@@ -0,0 +1,107 @@
+//! 生成的图片保存在应用目录下的 `gallery` 中，记录保存在 `image` 表
+
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lazy_static::lazy_static;
+use rand::Rng;
+
+use crate::{
+    api::{
+        client::{send_with_retry, HttpClient, RetryPolicy},
+        images::{ImageOptions, ImageTask, ImagesResponse},
+    },
+    config::APP_CONFIG_DIR,
+    db::image::ImageRecord,
+    error::{AppError, Result},
+    SQLitePool,
+};
+
+lazy_static! {
+    static ref GALLERY_DIR: PathBuf = APP_CONFIG_DIR.join("gallery");
+}
+
+/// 根据文件头判断图片格式
+fn image_extension(data: &[u8]) -> &'static str {
+    match data {
+        [0xFF, 0xD8, ..] => "jpg",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        _ => "png",
+    }
+}
+
+/// 图片数据，链接形式的结果需要在过期前下载
+async fn image_bytes(client: &HttpClient, url: Option<&str>, b64: Option<&str>) -> Result<Vec<u8>> {
+    if let Some(b64) = b64 {
+        return STANDARD
+            .decode(b64)
+            .map_err(|e| AppError::Parse(format!("图片 base64 解码失败：{}", e)));
+    }
+
+    let url = url.ok_or_else(|| AppError::Parse("响应中没有图片".to_string()))?;
+    let response = send_with_retry(&RetryPolicy::load()?, client.get(url)).await?;
+
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// 将生成的图片写入画廊目录并记录到主题中
+pub async fn save_images(
+    pool: &SQLitePool,
+    client: &HttpClient,
+    topic_id: u32,
+    task: &ImageTask,
+    options: &ImageOptions,
+    response: ImagesResponse,
+) -> Result<Vec<ImageRecord>> {
+    tokio::fs::create_dir_all(&*GALLERY_DIR).await?;
+
+    let mut records = Vec::new();
+
+    for (index, image) in response.data.into_iter().enumerate() {
+        let data = image_bytes(client, image.url.as_deref(), image.b64_json.as_deref()).await?;
+
+        // 同一秒内可能多次生成，加上随机后缀避免覆盖其他记录的文件
+        let path = GALLERY_DIR.join(format!(
+            "{}-{}-{}-{:08x}.{}",
+            topic_id,
+            response.created,
+            index,
+            rand::thread_rng().gen::<u32>(),
+            image_extension(&data)
+        ));
+        tokio::fs::write(&path, data).await?;
+
+        records.push(ImageRecord {
+            id: 0,
+            topic_id,
+            kind: task.kind().to_string(),
+            prompt: task.prompt().map(str::to_string),
+            revised_prompt: image.revised_prompt,
+            model: options.model(task).to_string(),
+            size: options.size.clone(),
+            quality: options.quality.clone(),
+            path: path.to_string_lossy().to_string(),
+            created_at: response.created,
+        });
+    }
+
+    let conn = pool.get()?;
+    for record in records.iter_mut() {
+        record.insert(&conn)?;
+    }
+
+    info!("已保存 {} 张图片到主题 {}", records.len(), topic_id);
+
+    Ok(records)
+}
+
+/// 删除主题后删除画廊中的图片文件
+pub fn remove_images(images: &[ImageRecord]) {
+    for image in images {
+        match std::fs::remove_file(&image.path) {
+            Ok(()) => debug!("已删除图片文件：{}", image.path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => warn!("删除图片文件 {} 失败：{}", image.path, e),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{self, Config, KeyRotation},
+    error::{AppError, Result},
+    secrets::SecretStore,
+};
+
+/// `open_api_key` 在 key 列表中的名字
+pub const DEFAULT_KEY_NAME: &str = "default";
+
+/// 被限流的 key 暂停使用的时间
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(60);
+/// 额度不足或无效的 key 暂停使用的时间
+const EXHAUSTED_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+tokio::task_local! {
+    /// 当前请求失败后是否还有下一个 key 可用
+    static HAS_NEXT_KEY: bool;
+}
+
+/// 在 [`KeyStore::run`] 中且还有下一个 key 时为真，此时被限流的请求不必等待重试，
+/// 直接换用下一个 key
+pub fn has_next_key() -> bool {
+    HAS_NEXT_KEY.try_with(|has_next| *has_next).unwrap_or(false)
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+}
+
+/// 后端保存的 API key，按配置的策略轮换
+///
+/// 某个 key 被限流（429）、额度不足或无效时，暂停使用一段时间并改用下一个 key。
+#[derive(Default)]
+pub struct KeyStore {
+    next: AtomicUsize,
+    cooldowns: Mutex<HashMap<String, Instant>>,
+    secrets: SecretStore,
+}
+
+/// 出现这些错误时换一个 key 重试
+fn should_rotate(err: &AppError) -> bool {
+    matches!(
+        err.kind(),
+        "rate_limit" | "insufficient_quota" | "invalid_api_key"
+    )
+}
+
+impl KeyStore {
+    pub fn secrets(&self) -> &SecretStore {
+        &self.secrets
+    }
+
+    /// 服务提供商可用的 key，`provider` 为空时为 OpenAI
+    fn provider_keys(&self, config: &Config, provider: Option<&str>) -> Result<Vec<ApiKey>> {
+        let mut keys = Vec::new();
+
+        if provider.is_none() && !config.open_api_key.is_empty() {
+            keys.push((DEFAULT_KEY_NAME, config.open_api_key.as_str()));
+        }
+
+        if let Some(api_keys) = &config.api_keys {
+            keys.extend(
+                api_keys
+                    .iter()
+                    .filter(|k| k.provider.as_deref() == provider)
+                    .map(|k| (k.name.as_str(), k.key.as_str())),
+            );
+        }
+
+        keys.into_iter()
+            .map(|(name, key)| {
+                Ok(ApiKey {
+                    name: name.to_string(),
+                    key: self.secrets.resolve(Some(config), key)?,
+                })
+            })
+            .collect()
+    }
+
+    /// 按轮换策略排列 key，暂停使用的 key 排在最后
+    fn order(&self, rotation: KeyRotation, mut keys: Vec<ApiKey>) -> Vec<ApiKey> {
+        if rotation == KeyRotation::RoundRobin && !keys.is_empty() {
+            let start = self.next.fetch_add(1, Ordering::Relaxed) % keys.len();
+            keys.rotate_left(start);
+        }
+
+        let now = Instant::now();
+        let mut cooldowns = self.cooldowns.lock().unwrap();
+        cooldowns.retain(|_, until| *until > now);
+
+        // sort_by_key 是稳定排序，不改变同一组内的顺序
+        keys.sort_by_key(|k| cooldowns.contains_key(&k.name));
+
+        keys
+    }
+
+    /// 选择 key 的顺序
+    ///
+    /// `pinned` 为主题固定使用的 key 名，设置后只使用这个 key。
+    pub fn candidates(
+        &self,
+        config: Option<&Config>,
+        provider: Option<&str>,
+        pinned: Option<&str>,
+    ) -> Result<Vec<ApiKey>> {
+        let config = match config {
+            Some(c) => c,
+            None => return Err(AppError::Config("配置文件不存在".to_string())),
+        };
+
+        let keys = self.provider_keys(config, provider)?;
+
+        if let Some(name) = pinned {
+            return keys
+                .into_iter()
+                .find(|k| k.name == name)
+                .map(|k| vec![k])
+                .ok_or_else(|| AppError::Config(format!("未配置 API key：{}", name)));
+        }
+
+        if keys.is_empty() {
+            return match provider {
+                // 其他服务提供商可能不需要鉴权，如本地部署的模型服务
+                Some(_) => Ok(vec![ApiKey {
+                    name: String::new(),
+                    key: String::new(),
+                }]),
+                None => Err(AppError::Config("未配置 API key".to_string())),
+            };
+        }
+
+        Ok(self.order(config.key_rotation.unwrap_or_default(), keys))
+    }
+
+    /// 服务提供商的 key，`provider` 为空时为 OpenAI
+    pub fn provider_candidates(&self, provider: Option<&str>) -> Result<Vec<ApiKey>> {
+        let config = config::read_config()?;
+
+        self.candidates(config.as_ref(), provider, None)
+    }
+
+    /// 主题使用的 key，由主题配置的服务提供商和固定的 key 决定
+    pub fn topic_candidates(&self, topic_id: u32) -> Result<Vec<ApiKey>> {
+        let config = config::read_config()?;
+
+        let topic_config = config
+            .as_ref()
+            .and_then(|c| c.topics.as_ref())
+            .and_then(|t| t.get(&topic_id.to_string()));
+
+        self.candidates(
+            config.as_ref(),
+            topic_config.and_then(|t| t.provider.as_deref()),
+            topic_config.and_then(|t| t.api_key.as_deref()),
+        )
+    }
+
+    fn report(&self, key: &ApiKey, err: &AppError) {
+        let cooldown = match err.kind() {
+            "rate_limit" => RATE_LIMIT_COOLDOWN,
+            _ => EXHAUSTED_COOLDOWN,
+        };
+
+        warn!(
+            "API key {} 不可用：{}，{:?} 内优先使用其他 key",
+            key.name, err, cooldown
+        );
+
+        let mut cooldowns = self.cooldowns.lock().unwrap();
+        cooldowns.insert(key.name.clone(), Instant::now() + cooldown);
+    }
+
+    /// 依次使用 `keys` 发送请求，被限流、额度不足或 key 无效时换下一个 key
+    ///
+    /// 还有下一个 key 时，被限流的请求不在 `send_with_retry` 中等待重试。
+    pub async fn run<T, F, Fut>(&self, keys: Vec<ApiKey>, mut f: F) -> Result<T>
+    where
+        F: FnMut(ApiKey) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut last_err = None;
+        let count = keys.len();
+
+        for (i, key) in keys.into_iter().enumerate() {
+            debug!("使用的 API key：{}", key.name);
+
+            match HAS_NEXT_KEY.scope(i + 1 < count, f(key.clone())).await {
+                Ok(r) => return Ok(r),
+                Err(e) if should_rotate(&e) => {
+                    self.report(&key, &e);
+
+                    if i + 1 < count {
+                        info!("换用下一个 API key 重试");
+                    }
+
+                    last_err = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| AppError::Config("未配置 API key".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> ApiKey {
+        ApiKey {
+            name: name.to_string(),
+            key: String::new(),
+        }
+    }
+
+    fn rate_limit() -> AppError {
+        AppError::Api {
+            status: 429,
+            message: "Rate limit reached".to_string(),
+            code: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn run_rotates_on_rate_limit() {
+        let store = KeyStore::default();
+        let seen = Mutex::new(Vec::new());
+        let seen_ref = &seen;
+
+        let result = store
+            .run(vec![key("a"), key("b")], |key| async move {
+                seen_ref
+                    .lock()
+                    .unwrap()
+                    .push((key.name.clone(), has_next_key()));
+
+                match key.name.as_str() {
+                    "a" => Err(rate_limit()),
+                    _ => Ok(key.name),
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "b");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [("a".to_string(), true), ("b".to_string(), false)]
+        );
+        assert!(!has_next_key());
+    }
+
+    #[tokio::test]
+    async fn run_returns_other_errors() {
+        let store = KeyStore::default();
+
+        let result: Result<()> = store
+            .run(vec![key("a"), key("b")], |_| async {
+                Err(AppError::Network("连接失败".to_string()))
+            })
+            .await;
+
+        assert_eq!(result.unwrap_err().kind(), "network");
+    }
+}
@@ -0,0 +1,391 @@
+//! 主题知识库
+//!
+//! 导入的文件按行切分为分段，计算向量后保存在 `knowledge_chunk` 表。发送消息时根据
+//! 最后一条用户消息检索相似的分段，编号后作为系统消息加入上下文，回复以 `[n]` 引用；
+//! 检索到的分段随回复一起保存，便于核对回答的来源。
+
+use std::path::Path;
+
+use crate::{
+    api::{
+        chat::{ChatGPTRequest, Message},
+        client::HttpClient,
+    },
+    config::{EmbeddingConfig, KnowledgeConfig, ProxyConfig},
+    db::knowledge::{
+        get_chunks, has_documents, insert_document, Citation, KnowledgeChunk, KnowledgeDocument,
+    },
+    error::{AppError, Result},
+    keys::KeyStore,
+    semantic::{cosine_similarity, Embedder},
+    time::now,
+    SQLitePool,
+};
+
+const TEXT_EXTENSIONS: [&str; 6] = ["txt", "text", "rst", "log", "csv", "tsv"];
+
+const CODE_EXTENSIONS: [&str; 36] = [
+    "rs", "py", "js", "jsx", "ts", "tsx", "mjs", "go", "java", "kt", "scala", "c", "h", "cc",
+    "cpp", "hpp", "cs", "rb", "php", "swift", "lua", "sh", "bash", "ps1", "sql", "toml", "yaml",
+    "yml", "json", "xml", "html", "css", "scss", "vue", "svelte", "proto",
+];
+
+/// 根据扩展名判断文件类型
+fn document_kind(path: &Path) -> Result<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let extension = extension.as_str();
+
+    match extension {
+        "md" | "markdown" | "mdx" => Ok("markdown"),
+        "pdf" => Ok("pdf"),
+        _ if TEXT_EXTENSIONS.contains(&extension) => Ok("text"),
+        _ if CODE_EXTENSIONS.contains(&extension) => Ok("code"),
+        _ => Err(AppError::Other(format!(
+            "不支持导入的文件类型：{}",
+            path.display()
+        ))),
+    }
+}
+
+async fn read_text(path: &Path, kind: &str, max_bytes: u64) -> Result<String> {
+    let size = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| AppError::Io(format!("无法读取文件 {}：{}", path.display(), e)))?
+        .len();
+    if size > max_bytes {
+        return Err(AppError::Other(format!(
+            "文件超过 {} MB：{}",
+            max_bytes / 1024 / 1024,
+            path.display()
+        )));
+    }
+
+    // PDF 只提取文本层，扫描件需要先识别文字
+    if kind == "pdf" {
+        let path = path.to_path_buf();
+        return tokio::task::spawn_blocking(move || pdf_extract::extract_text(&path))
+            .await
+            .map_err(|e| AppError::Other(format!("提取 PDF 文本的任务失败：{}", e)))?
+            .map_err(|e| AppError::Parse(format!("提取 PDF 文本失败：{}", e)));
+    }
+
+    let data = tokio::fs::read(path).await?;
+
+    String::from_utf8(data)
+        .map_err(|_| AppError::Parse(format!("文件不是 UTF-8 编码：{}", path.display())))
+}
+
+/// 超长的行按字符切开
+fn split_long_line(line: &str, max_chars: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.len() <= max_chars {
+        return vec![line.to_string()];
+    }
+
+    chars
+        .chunks(max_chars)
+        .map(|c| c.iter().collect())
+        .collect()
+}
+
+fn join_lines(lines: &[(usize, String)]) -> KnowledgeChunk {
+    KnowledgeChunk {
+        content: lines
+            .iter()
+            .map(|(_, l)| l.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        start_line: lines.first().map(|(n, _)| *n).unwrap_or_default(),
+        end_line: lines.last().map(|(n, _)| *n).unwrap_or_default(),
+        embedding: Vec::new(),
+    }
+}
+
+/// 按行切分，每段不超过 `chunk_chars` 个字符，下一段以上一段末尾不超过
+/// `overlap_chars` 个字符的行开头，避免一段内容被切断后两边都检索不到
+fn split_chunks(text: &str, chunk_chars: usize, overlap_chars: usize) -> Vec<KnowledgeChunk> {
+    let chunk_chars = chunk_chars.max(100);
+    let overlap_chars = overlap_chars.min(chunk_chars / 2);
+
+    let mut chunks = Vec::new();
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut size = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        for piece in split_long_line(line, chunk_chars) {
+            let len = piece.chars().count() + 1;
+
+            if size + len > chunk_chars && !lines.is_empty() {
+                chunks.push(join_lines(&lines));
+
+                let mut start = lines.len();
+                let mut kept = 0;
+                while start > 0 {
+                    let len = lines[start - 1].1.chars().count() + 1;
+                    if kept + len > overlap_chars {
+                        break;
+                    }
+                    kept += len;
+                    start -= 1;
+                }
+
+                lines.drain(..start);
+                size = kept;
+            }
+
+            size += len;
+            lines.push((index + 1, piece));
+        }
+    }
+
+    if !lines.is_empty() {
+        chunks.push(join_lines(&lines));
+    }
+
+    chunks.retain(|c| !c.content.trim().is_empty());
+    chunks
+}
+
+/// 导入文件到主题的知识库，同一路径再次导入时替换旧的分段
+pub async fn import_document(
+    pool: &SQLitePool,
+    embedder: &Embedder<'_>,
+    topic_id: u32,
+    path: &str,
+) -> Result<KnowledgeDocument> {
+    let config = KnowledgeConfig::load()?;
+    let batch_size = EmbeddingConfig::load()?.batch_size.max(1);
+
+    let path = Path::new(path.strip_prefix("file://").unwrap_or(path));
+    let kind = document_kind(path)?;
+    let text = read_text(path, kind, config.max_file_bytes).await?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut chunks = split_chunks(&text, config.chunk_chars, config.overlap_chars);
+    if chunks.is_empty() {
+        return Err(AppError::Other(format!(
+            "文件中没有文本：{}",
+            path.display()
+        )));
+    }
+
+    // 文件名一起参与计算向量，按文件名提问时也能检索到
+    for batch in chunks.chunks_mut(batch_size) {
+        let inputs: Vec<String> = batch
+            .iter()
+            .map(|c| format!("{}\n\n{}", name, c.content))
+            .collect();
+        let vectors = embedder.embed(&inputs).await?;
+
+        for (chunk, vector) in batch.iter_mut().zip(vectors) {
+            chunk.embedding = vector;
+        }
+    }
+
+    let mut document = KnowledgeDocument {
+        id: 0,
+        topic_id,
+        name,
+        path: path.to_string_lossy().to_string(),
+        kind: kind.to_string(),
+        model: embedder.model.clone(),
+        chunks: chunks.len(),
+        created_at: now()?,
+    };
+    insert_document(&*pool.get()?, &mut document, &chunks)?;
+
+    info!(
+        "已导入文件到主题 {} 的知识库：{}，共 {} 段",
+        topic_id, document.path, document.chunks
+    );
+
+    Ok(document)
+}
+
+async fn retrieve(
+    pool: &SQLitePool,
+    embedder: &Embedder<'_>,
+    topic_id: u32,
+    query: &str,
+    config: &KnowledgeConfig,
+) -> Result<Vec<Citation>> {
+    let query_vector = embedder
+        .embed(&[query.to_string()])
+        .await?
+        .pop()
+        .unwrap_or_default();
+
+    let chunks = get_chunks(&*pool.get()?, topic_id, &embedder.model)?;
+
+    let mut scored: Vec<_> = chunks
+        .into_iter()
+        .map(|c| (cosine_similarity(&query_vector, &c.embedding), c))
+        .filter(|(score, _)| *score >= config.min_score)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(config.top_k);
+
+    let citations = scored
+        .into_iter()
+        .enumerate()
+        .map(|(index, (score, chunk))| Citation {
+            index: index + 1,
+            document_id: chunk.document_id,
+            chunk_id: chunk.id,
+            name: chunk.name,
+            path: chunk.path,
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            score,
+            content: chunk.content,
+        })
+        .collect();
+
+    Ok(citations)
+}
+
+fn context_message(citations: &[Citation]) -> Message {
+    let mut content = String::from(
+        "以下是从知识库中检索到的资料。回答时优先参考这些资料，并在引用处用 [n] 标注来源编号；资料与问题无关时忽略它们。",
+    );
+
+    for citation in citations {
+        content.push_str(&format!(
+            "\n\n[{}] {}（第 {}-{} 行）\n{}",
+            citation.index, citation.name, citation.start_line, citation.end_line, citation.content
+        ));
+    }
+
+    Message::new("system", &content)
+}
+
+async fn try_augment(
+    pool: &SQLitePool,
+    client: &HttpClient,
+    keys: &KeyStore,
+    proxy_config: &ProxyConfig,
+    topic_id: u32,
+    request: &mut ChatGPTRequest,
+) -> Result<Vec<Citation>> {
+    if !has_documents(&*pool.get()?, topic_id)? {
+        return Ok(Vec::new());
+    }
+
+    let position = match request.messages.iter().rposition(|m| m.role == "user") {
+        Some(p) => p,
+        None => return Ok(Vec::new()),
+    };
+    let query = request.messages[position].content.text();
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let config = KnowledgeConfig::load()?;
+    let embedder = Embedder::new(client, keys, proxy_config, &EmbeddingConfig::load()?)?;
+
+    let citations = retrieve(pool, &embedder, topic_id, &query, &config).await?;
+    if !citations.is_empty() {
+        debug!("从知识库中检索到 {} 段资料", citations.len());
+        request
+            .messages
+            .insert(position, context_message(&citations));
+    }
+
+    Ok(citations)
+}
+
+/// 检索主题知识库中与最后一条用户消息相关的分段，作为系统消息加入到这条消息之前
+///
+/// 主题没有导入文件时不请求接口；检索失败只记录日志，不影响发送。
+pub async fn augment_request(
+    pool: &SQLitePool,
+    client: &HttpClient,
+    keys: &KeyStore,
+    proxy_config: &ProxyConfig,
+    topic_id: u32,
+    request: &mut ChatGPTRequest,
+) -> Vec<Citation> {
+    match try_augment(pool, client, keys, proxy_config, topic_id, request).await {
+        Ok(citations) => citations,
+        Err(e) => {
+            warn!("检索知识库失败：{}", e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(chunks: &[KnowledgeChunk]) -> Vec<(usize, usize)> {
+        chunks.iter().map(|c| (c.start_line, c.end_line)).collect()
+    }
+
+    #[test]
+    fn document_kind_by_extension() {
+        assert_eq!(document_kind(Path::new("notes.MD")).unwrap(), "markdown");
+        assert_eq!(document_kind(Path::new("paper.pdf")).unwrap(), "pdf");
+        assert_eq!(document_kind(Path::new("data.csv")).unwrap(), "text");
+        assert_eq!(document_kind(Path::new("src/main.rs")).unwrap(), "code");
+        assert!(document_kind(Path::new("setup.exe")).is_err());
+        assert!(document_kind(Path::new("Makefile")).is_err());
+    }
+
+    #[test]
+    fn split_long_line_by_chars() {
+        assert_eq!(split_long_line("short", 10), vec!["short"]);
+        assert_eq!(split_long_line("你好世界", 3), vec!["你好世", "界"]);
+    }
+
+    #[test]
+    fn split_chunks_overlap() {
+        // 每行加换行符 40 个字符，每段放下两行，重叠部分放下一行
+        let text = ["a", "b", "c", "d", "e"].map(|c| c.repeat(39)).join("\n");
+
+        let chunks = split_chunks(&text, 100, 50);
+        assert_eq!(lines(&chunks), vec![(1, 2), (2, 3), (3, 4), (4, 5)]);
+        assert_eq!(
+            chunks[1].content,
+            format!("{}\n{}", "b".repeat(39), "c".repeat(39))
+        );
+
+        // 重叠放不下一整行时不重叠
+        let chunks = split_chunks(&text, 100, 30);
+        assert_eq!(lines(&chunks), vec![(1, 2), (3, 4), (5, 5)]);
+    }
+
+    #[test]
+    fn split_chunks_long_line() {
+        let text = format!("{}\nshort", "x".repeat(250));
+
+        let chunks = split_chunks(&text, 100, 0);
+        assert_eq!(lines(&chunks), vec![(1, 1), (1, 1), (1, 2)]);
+        assert_eq!(chunks[0].content, "x".repeat(100));
+        assert_eq!(chunks[2].content, format!("{}\nshort", "x".repeat(50)));
+    }
+
+    #[test]
+    fn split_chunks_line_numbers() {
+        let chunks = split_chunks("first\n\n   \nsecond", 100, 0);
+        assert_eq!(lines(&chunks), vec![(1, 4)]);
+        assert_eq!(chunks[0].content, "first\n\n   \nsecond");
+
+        assert!(split_chunks("", 100, 0).is_empty());
+    }
+
+    #[test]
+    fn split_chunks_skips_blank_chunks() {
+        let text = format!("{}\n{}\n{}", "a".repeat(60), " ".repeat(60), "b".repeat(60));
+
+        let chunks = split_chunks(&text, 100, 0);
+        assert_eq!(lines(&chunks), vec![(1, 1), (3, 3)]);
+    }
+}
@@ -5,37 +5,79 @@
 
 mod api;
 mod config;
+mod context;
 mod db;
 mod error;
 mod export;
+mod gallery;
+mod keys;
+mod knowledge;
 mod logger;
+mod mcp;
+mod moderation;
+mod secrets;
+mod semantic;
+mod stream;
 mod time;
+mod tokenizer;
+mod tools;
+mod tts;
+mod vision;
 
 #[macro_use]
 extern crate log;
 extern crate simplelog;
 
-use crate::api::chat::MessageChunk;
-use crate::db::message::{delete_user_message_by_time, AssistantMessage, UserMessage};
+use crate::api::chat::{MessageChunk, ToolCallMerger};
+use crate::db::message::{
+    delete_user_message_by_time, get_assistant_message_by_time, get_assistant_message_ids_by_time,
+    get_topic_assistant_message_ids, AssistantMessage, MessageStatus, ToolMessage, UserMessage,
+};
 use crate::db::topic::{insert_topic, update_topic_by_id};
-use crate::error::Result;
+use crate::error::{AppError, Result};
 use crate::logger::{log_level, logger_config};
-use api::chat::{chat_gpt_client, chat_gpt_steam_client, ChatGPTRequest, ChatGPTResponse, Message};
+use crate::time::now;
+use api::audio::{audio_client, AudioRequest, AudioResponse, AudioTask};
+use api::chat::{
+    chat_gpt_client, chat_gpt_steam_client, ChatGPTRequest, ChatGPTResponse, ImageUrl, Message,
+};
+use api::client::{HttpClient, SharedClient, Timeouts};
+use api::images::{images_client, ImageOptions, ImageTask};
 use api::models::{get_chat_models, retrieve_model, Model, ModelsResponse};
-use config::{Config, ProxyConfig, APP_CONFIG_DIR};
+use api::provider::{get_provider, get_topic_provider};
+use api::speech::SpeechOptions;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use config::{Config, EmbeddingConfig, ProxyConfig, SecretBackend, APP_CONFIG_DIR};
+use context::build_request;
+use db::attachment::{get_attachment_paths_by_time, get_topic_attachment_paths, init_attachment};
+use db::embedding::init_embedding;
+use db::image::{get_images, init_image, ImageRecord};
+use db::knowledge::{get_documents, init_knowledge, Citation, KnowledgeDocument};
 use db::manager::SqliteConnectionManager;
 use db::message::{get_messages, init_messages, Conversation};
+use db::moderation::init_moderation;
 use db::topic::{get_all_topics, init_topic, Topic};
+use db::usage::{init_usage, summarize_usage, UsageGroup, UsageRecord, UsageSummary};
 use export::markdown::{format_user_message, UserMessageMode};
 use futures_util::StreamExt;
-use reqwest_eventsource::Event;
+use keys::KeyStore;
+use mcp::{McpManager, McpServerStatus};
+use moderation::ModerationOutcome;
+use reqwest_eventsource::{Event, EventSource};
+use secrets::SecretsStatus;
+use semantic::{Embedder, EmbeddingIndexer, SemanticMatch};
+use serde::Serialize;
 use simplelog::{ColorChoice, CombinedLogger, TermLogger, TerminalMode, WriteLogger};
 use std::fs as SysFS;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::path::Path;
+use std::time::Instant;
+use stream::{ChatStream, StreamInfo, StreamRegistry, StreamStatus};
+use tauri::Manager;
+use tokenizer::{count_request, count_text};
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncWriteExt, BufWriter};
-// use tauri::Manager;
+use tools::{ToolRegistry, ToolSession};
+use tts::Speaker;
 // use window_shadows::set_shadow;
 
 type SQLitePool = r2d2::Pool<SqliteConnectionManager>;
@@ -56,18 +98,14 @@ fn set_gtk_scale_env() {
 #[tauri::command]
 async fn export_to_file(filepath: String, buf: Vec<u8>, offset: u32) -> Result<()> {
     let mut file = if offset == 0 {
-        File::create(filepath).await.map_err(|e| e.to_string())?
+        File::create(filepath).await?
     } else {
-        OpenOptions::new()
-            .append(true)
-            .open(filepath)
-            .await
-            .map_err(|e| e.to_string())?
+        OpenOptions::new().append(true).open(filepath).await?
     };
 
     let mut writer = BufWriter::new(&mut file);
-    writer.write_all(&buf).await.map_err(|e| e.to_string())?;
-    writer.flush().await.map_err(|e| e.to_string())
+    writer.write_all(&buf).await?;
+    writer.flush().await.map_err(AppError::from)
 }
 
 #[tauri::command]
@@ -84,50 +122,339 @@ async fn export_to_markdown(
         format!(
             "# {}\n\n{}\n",
             topic_name,
-            format_user_message(&message.content, user_message_mode)
+            format_user_message(&message.content.text(), user_message_mode)
         )
     } else if message.role == "user" {
         format!(
             "\n{}\n",
-            format_user_message(&message.content, user_message_mode)
+            format_user_message(&message.content.text(), user_message_mode)
         )
     } else {
-        message.content + "\n"
+        message.content.text() + "\n"
     };
 
     let mut file = if offset == 0 {
-        File::create(filepath).await.map_err(|e| e.to_string())?
+        File::create(filepath).await?
     } else {
-        OpenOptions::new()
-            .append(true)
-            .open(filepath)
-            .await
-            .map_err(|e| e.to_string())?
+        OpenOptions::new().append(true).open(filepath).await?
     };
 
     let mut writer = BufWriter::new(&mut file);
 
-    writer
-        .write_all(text.as_bytes())
-        .await
-        .map_err(|e| e.to_string())?;
-    writer.flush().await.map_err(|e| e.to_string())
+    writer.write_all(text.as_bytes()).await?;
+    writer.flush().await.map_err(AppError::from)
 }
 
 #[tauri::command]
-async fn get_models(proxy_config: ProxyConfig, api_key: String) -> Result<ModelsResponse> {
-    get_chat_models(&proxy_config, &api_key).await
+async fn get_models(
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    provider: Option<String>,
+) -> Result<ModelsResponse> {
+    let candidates = keys.provider_candidates(provider.as_deref())?;
+    let provider = get_provider(provider.as_deref())?;
+    let provider = provider.as_ref();
+    let client = clients.get(&proxy_config)?;
+    let client = &client;
+    let proxy_config = &proxy_config;
+
+    keys.run(candidates, |key| async move {
+        get_chat_models(client, provider, proxy_config, &key.key).await
+    })
+    .await
 }
 
 #[tauri::command]
-async fn get_model(proxy_config: ProxyConfig, api_key: String, model: String) -> Result<Model> {
-    match retrieve_model(&proxy_config, &api_key, &model).await {
+async fn get_model(
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    model: String,
+    provider: Option<String>,
+) -> Result<Model> {
+    let candidates = keys.provider_candidates(provider.as_deref())?;
+    let provider = get_provider(provider.as_deref())?;
+    let provider = provider.as_ref();
+    let client = clients.get(&proxy_config)?;
+    let client = &client;
+    let proxy_config = &proxy_config;
+    let model = &model;
+
+    let result = keys
+        .run(candidates, |key| async move {
+            retrieve_model(client, provider, proxy_config, &key.key, model).await
+        })
+        .await;
+
+    match result {
         Ok(r) => Ok(r),
         Err(e) => {
             error!("获取模型响应时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e);
+        }
+    }
+}
+
+/// 转写或翻译本地音频
+///
+/// 指定主题时使用主题的提供商和 key，前端可将结果作为用户消息发送到该主题。
+#[tauri::command]
+async fn transcribe_audio(
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    topic_id: Option<u32>,
+    task: AudioTask,
+    request: AudioRequest,
+) -> Result<AudioResponse> {
+    let (candidates, provider) = match topic_id {
+        Some(topic_id) => (
+            keys.topic_candidates(topic_id)?,
+            get_topic_provider(topic_id)?,
+        ),
+        None => (keys.provider_candidates(None)?, get_provider(None)?),
+    };
+    let provider = provider.as_ref();
+    let client = clients.get(&proxy_config)?;
+    let client = &client;
+    let proxy_config = &proxy_config;
+    let request = &request;
+
+    keys.run(candidates, |key| async move {
+        audio_client(client, provider, proxy_config, &key.key, task, request).await
+    })
+    .await
+    .map_err(|e| {
+        error!("转写音频时出错：{}", e);
+        e
+    })
+}
+
+/// 生成、编辑图片或生成变体，结果保存到画廊并记录到主题中
+#[tauri::command]
+async fn generate_images(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    task: ImageTask,
+    options: Option<ImageOptions>,
+) -> Result<Vec<ImageRecord>> {
+    let options = options.unwrap_or_default();
+
+    let candidates = keys.topic_candidates(topic_id)?;
+    let provider = get_topic_provider(topic_id)?;
+    let provider = provider.as_ref();
+    let client = clients.get(&proxy_config)?;
+    let client = &client;
+    let proxy_config = &proxy_config;
+    let (task, options) = (&task, &options);
+
+    let response = keys
+        .run(candidates, |key| async move {
+            images_client(client, provider, proxy_config, &key.key, task, options).await
+        })
+        .await
+        .map_err(|e| {
+            error!("生成图片时出错：{}", e);
+            e
+        })?;
+
+    gallery::save_images(&pool, client, topic_id, task, options, response).await
+}
+
+#[tauri::command]
+fn get_images_by_topic_id(
+    pool: tauri::State<'_, SQLitePool>,
+    topic_id: u32,
+) -> Result<Vec<ImageRecord>> {
+    let conn = pool.get()?;
+    get_images(&conn, topic_id).map_err(AppError::from)
+}
+
+/// 删除图片记录和画廊中的文件
+#[tauri::command]
+fn delete_image(pool: tauri::State<'_, SQLitePool>, id: u32) -> Result<()> {
+    let conn = pool.get()?;
+
+    if let Some(path) = db::image::delete_image(&conn, id)? {
+        if let Err(e) = SysFS::remove_file(&path) {
+            warn!("删除图片文件 {} 失败：{}", path, e);
         }
     }
+
+    Ok(())
+}
+
+/// 按语义搜索历史对话，搜索前先为一批还没有索引的对话建立索引
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn semantic_search(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    indexer: tauri::State<'_, EmbeddingIndexer>,
+    proxy_config: ProxyConfig,
+    query: String,
+    top_k: Option<usize>,
+    topic_filter: Option<u32>,
+) -> Result<Vec<SemanticMatch>> {
+    let config = EmbeddingConfig::load()?;
+    let client = clients.get(&proxy_config)?;
+    let embedder = Embedder::new(&client, &keys, &proxy_config, &config)?;
+
+    // 索引失败时仍然搜索已有的向量
+    if let Err(e) = indexer
+        .index_pending(
+            &pool,
+            &embedder,
+            config.batch_size,
+            Some(semantic::SEARCH_INDEX_BATCHES),
+        )
+        .await
+    {
+        warn!("搜索前建立索引失败：{}", e);
+    }
+
+    semantic::search(
+        &pool,
+        &embedder,
+        &query,
+        top_k.unwrap_or(semantic::DEFAULT_TOP_K),
+        topic_filter,
+    )
+    .await
+    .map_err(|e| {
+        error!("语义搜索时出错：{}", e);
+        e
+    })
+}
+
+/// 导入文件到主题的知识库
+///
+/// 逐个导入，某个文件失败时返回错误，之前导入的文件保留。
+#[tauri::command]
+async fn import_knowledge(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    paths: Vec<String>,
+) -> Result<Vec<KnowledgeDocument>> {
+    let client = clients.get(&proxy_config)?;
+    let embedder = Embedder::new(&client, &keys, &proxy_config, &EmbeddingConfig::load()?)?;
+
+    let mut documents = Vec::with_capacity(paths.len());
+    for path in paths {
+        let document = knowledge::import_document(&pool, &embedder, topic_id, &path)
+            .await
+            .map_err(|e| {
+                error!("导入文件 {} 时出错：{}", path, e);
+                e
+            })?;
+        documents.push(document);
+    }
+
+    Ok(documents)
+}
+
+#[tauri::command]
+fn get_knowledge_documents(
+    pool: tauri::State<'_, SQLitePool>,
+    topic_id: u32,
+) -> Result<Vec<KnowledgeDocument>> {
+    let conn = pool.get()?;
+    get_documents(&conn, topic_id).map_err(AppError::from)
+}
+
+#[tauri::command]
+fn delete_knowledge_document(pool: tauri::State<'_, SQLitePool>, id: u32) -> Result<()> {
+    let conn = pool.get()?;
+    db::knowledge::delete_document(&conn, id).map_err(AppError::from)
+}
+
+/// 回复的语音，前端用 `data` 播放
+#[derive(Serialize)]
+struct SpeechAudio {
+    path: String,
+    mime: &'static str,
+    /// base64 编码的音频
+    data: String,
+}
+
+/// 朗读一条回复，`created_at` 为对应用户消息的创建时间
+#[tauri::command]
+async fn speak_message(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    created_at: u64,
+    options: Option<SpeechOptions>,
+) -> Result<SpeechAudio> {
+    let options = SpeechOptions::or_config(options)?;
+    let message = get_assistant_message_by_time(&*pool.get()?, created_at)?;
+
+    let provider = get_topic_provider(topic_id)?;
+    let client = clients.get(&proxy_config)?;
+    let speaker = Speaker {
+        client: &client,
+        provider: provider.as_ref(),
+        proxy_config: &proxy_config,
+        keys: &keys,
+        candidates: keys.topic_candidates(topic_id)?,
+        options: &options,
+    };
+
+    let path = speaker.message_audio(&message).await.map_err(|e| {
+        error!("合成语音时出错：{}", e);
+        e
+    })?;
+    let data = tokio::fs::read(&path).await?;
+
+    Ok(SpeechAudio {
+        path: path.to_string_lossy().to_string(),
+        mime: options.response_format.mime(),
+        data: STANDARD.encode(data),
+    })
+}
+
+/// 将主题中的所有回复导出为一个音频文件
+#[tauri::command]
+async fn export_topic_speech(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    options: Option<SpeechOptions>,
+    filepath: String,
+) -> Result<()> {
+    let options = SpeechOptions::or_config(options)?;
+    let conversations = get_messages(&*pool.get()?, topic_id)?;
+
+    let provider = get_topic_provider(topic_id)?;
+    let client = clients.get(&proxy_config)?;
+    let speaker = Speaker {
+        client: &client,
+        provider: provider.as_ref(),
+        proxy_config: &proxy_config,
+        keys: &keys,
+        candidates: keys.topic_candidates(topic_id)?,
+        options: &options,
+    };
+
+    speaker
+        .export_topic(&conversations, Path::new(&filepath))
+        .await
+        .map_err(|e| {
+            error!("导出主题语音时出错：{}", e);
+            e
+        })
 }
 
 #[tauri::command]
@@ -140,14 +467,77 @@ async fn read_config() -> Result<Option<Config>> {
 }
 
 #[tauri::command]
-async fn write_config(config: Config) -> Result<()> {
+async fn write_config(
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    mcp: tauri::State<'_, McpManager>,
+    mut config: Config,
+) -> Result<()> {
+    let old = config::read_config()?;
+
+    // 启用密钥库后，明文 key 不写入配置文件
+    keys.secrets().protect(&mut config, old.as_ref())?;
+
     config::write_config(&config)?;
 
+    // 连接相关的配置改变后重新创建客户端
+    let changed = match old {
+        Some(old) => {
+            old.proxy != config.proxy
+                || old.timeout != config.timeout
+                || old.http != config.http
+                || old.tls != config.tls
+        }
+        None => true,
+    };
+
+    if changed {
+        clients.reset();
+    }
+
+    // 停止已删除或配置已改变的 MCP 服务，下次使用时重新启动
+    mcp.sync_config(Some(&config)).await;
+
     debug!("已保存配置 {:?}", config);
 
     Ok(())
 }
 
+#[tauri::command]
+async fn get_secrets_status(keys: tauri::State<'_, KeyStore>) -> Result<SecretsStatus> {
+    keys.secrets().status()
+}
+
+/// 解锁密钥文件，文件不存在时以此密码创建，并迁移配置文件中的明文 key
+#[tauri::command]
+async fn unlock_secrets(keys: tauri::State<'_, KeyStore>, passphrase: String) -> Result<()> {
+    let secrets = keys.secrets();
+
+    secrets.unlock(&passphrase)?;
+    secrets.migrate_config()
+}
+
+#[tauri::command]
+async fn lock_secrets(keys: tauri::State<'_, KeyStore>) -> Result<()> {
+    keys.secrets().lock();
+
+    Ok(())
+}
+
+/// 启动配置中的所有 MCP 服务，返回其状态和工具
+#[tauri::command]
+async fn list_mcp_servers(mcp: tauri::State<'_, McpManager>) -> Result<Vec<McpServerStatus>> {
+    let config = config::read_config()?;
+
+    Ok(mcp.status(config.as_ref()).await)
+}
+
+/// 停止 MCP 服务，下次使用时重新启动
+#[tauri::command]
+async fn stop_mcp_server(mcp: tauri::State<'_, McpManager>, name: String) -> Result<bool> {
+    Ok(mcp.stop(&name).await)
+}
+
 #[tauri::command]
 async fn restore_is_on_top() -> Result<()> {
     trace!("重置 is_on_top");
@@ -167,91 +557,266 @@ async fn restore_is_on_top() -> Result<()> {
     Ok(())
 }
 
-#[tauri::command]
-async fn chat_gpt(
-    pool: tauri::State<'_, SQLitePool>,
-    proxy_config: ProxyConfig,
-    api_key: String,
-    topic_id: u32,
-    request: ChatGPTRequest,
+/// 先保存用户消息，返回其 id
+///
+/// 请求中最后一条用户消息之后的消息（请求调用工具的回复和工具的执行结果）
+/// 作为这次回复调用工具的过程一起保存。
+fn save_user_message(
+    pool: &SQLitePool,
+    request: &ChatGPTRequest,
     created_at: u64,
-) -> Result<ChatGPTResponse> {
-    debug!("使用的代理：{:?}", proxy_config);
-    debug!("发送的消息：{:?}", request);
+    topic_id: u32,
+) -> Result<u32> {
+    let messages = &request.messages;
+    let start = messages
+        .iter()
+        .rposition(|m| m.role == "user")
+        .unwrap_or(messages.len().saturating_sub(1));
 
-    let messages_len = request.messages.len();
+    let content = messages.get(start).map(|m| &m.content);
+    let text = content.map(|c| c.text()).unwrap_or_default();
+    let user_message = UserMessage::new(&text, created_at, topic_id);
 
-    let user_message_content = &request.messages[messages_len - 1].content.clone();
+    let conn = pool.get()?;
+    user_message.insert(&conn)?;
 
-    let response = match chat_gpt_client(&proxy_config, &api_key, request).await {
-        Ok(r) => r,
-        Err(e) => {
-            error!("获取普通响应时出错：{}", e);
-            return Err(e.to_string());
-        }
+    let user_message_id = conn.last_insert_rowid() as u32;
+
+    if let Some(content) = content {
+        vision::save_images(&conn, user_message_id, &content.images(), created_at)?;
+    }
+
+    for message in messages.iter().skip(start + 1) {
+        ToolMessage::new(user_message_id, message, created_at).insert(&conn)?;
+    }
+
+    Ok(user_message_id)
+}
+
+/// 保存用户消息的审核结果，拒绝发送时返回审核错误
+fn save_moderation(
+    pool: &SQLitePool,
+    user_message_id: u32,
+    moderation: Option<ModerationOutcome>,
+) -> Result<Option<ModerationOutcome>> {
+    let mut outcome = match moderation {
+        Some(o) => o,
+        None => return Ok(None),
     };
 
-    let user_message = UserMessage::new(user_message_content, created_at, topic_id);
+    let conn = pool.get()?;
+    outcome.save(&conn, user_message_id)?;
 
-    let conn = pool.get().map_err(|e| e.to_string())?;
-    user_message.insert(&conn).map_err(|e| e.to_string())?;
+    if outcome.blocked() {
+        return Err(outcome.error());
+    }
 
-    let user_message_id = conn.last_insert_rowid() as u32;
+    Ok(Some(outcome))
+}
 
-    let chat_message = AssistantMessage::new(
-        response.choices[0].message.content.clone(),
-        response.created,
-        user_message_id,
-    );
+/// 保存用户消息后出错时保存的回复，使历史中的每条用户消息都有对应的回复
+fn save_error_reply(
+    pool: &SQLitePool,
+    user_message_id: u32,
+    error: &AppError,
+    citations: Vec<Citation>,
+) -> Result<()> {
+    let status = match error {
+        AppError::Timeout(_) => MessageStatus::Timeout,
+        _ => MessageStatus::Error,
+    };
 
-    chat_message.insert(&conn).map_err(|e| e.to_string())?;
+    AssistantMessage::new(
+        String::new(),
+        now()?,
+        user_message_id,
+        status,
+        Some(error.to_string()),
+    )
+    .with_citations(citations)
+    .insert(&*pool.get()?)?;
 
-    Ok(response)
+    Ok(())
 }
 
-#[tauri::command]
-async fn chat_gpt_stream(
-    pool: tauri::State<'_, SQLitePool>,
-    window: tauri::Window,
-    proxy_config: ProxyConfig,
-    api_key: String,
+/// 发送普通请求并保存对话
+///
+/// 用户消息在请求前保存，之后出错时保存状态为 `error` 或 `timeout` 的回复。
+#[allow(clippy::too_many_arguments)]
+async fn send_chat(
+    pool: &SQLitePool,
+    client: &HttpClient,
+    keys: &KeyStore,
+    tools: &ToolRegistry,
+    mcp: &McpManager,
+    proxy_config: &ProxyConfig,
     topic_id: u32,
-    request: ChatGPTRequest,
+    mut request: ChatGPTRequest,
     created_at: u64,
-) -> Result<u32> {
-    debug!("使用的代理：{:?}", proxy_config);
-    debug!("发送的消息：{:?}", request);
+) -> Result<ChatGPTResponse> {
+    // 本地图片编码后再保存，读取失败时不保存这次的用户消息
+    vision::inline_images(&mut request)?;
+
+    let user_message_id = save_user_message(pool, &request, created_at, topic_id)?;
+
+    let mut citations = Vec::new();
+
+    // 之后的错误都保存为这条用户消息的回复
+    let result = async {
+        let mut session = ToolSession::new(tools, mcp, topic_id).await?;
+        session.prepare(&mut request);
+
+        // 拒绝发送时返回错误，不需要检索知识库
+        let moderation = moderation::check(client, keys, proxy_config, topic_id, &request).await?;
+        save_moderation(pool, user_message_id, moderation)?;
+
+        citations =
+            knowledge::augment_request(pool, client, keys, proxy_config, topic_id, &mut request)
+                .await;
+
+        debug!("使用的代理：{:?}", proxy_config);
+        debug!("发送的消息：{:?}", request);
+
+        let provider = get_topic_provider(topic_id)?;
+        let provider = provider.as_ref();
+        let candidates = keys.topic_candidates(topic_id)?;
+
+        // 执行模型调用的工具后再次请求，直到得到最终回复
+        let response = loop {
+            debug!("prompt token 数：{}", count_request(&request));
+
+            let request_ref = &request;
+            let response = keys
+                .run(candidates.clone(), |key| async move {
+                    chat_gpt_client(client, provider, proxy_config, &key.key, request_ref).await
+                })
+                .await?;
+
+            let message = &response.choice()?.message;
+
+            // 没有返回用量时使用本地分词器估算
+            let usage = match &response.usage {
+                Some(u) => UsageRecord::new(
+                    topic_id,
+                    &response.model,
+                    u.prompt_tokens,
+                    u.completion_tokens,
+                    false,
+                    now()?,
+                ),
+                None => UsageRecord::new(
+                    topic_id,
+                    &request.model,
+                    count_request(&request) as u32,
+                    count_text(&request.model, &message.content.text()) as u32,
+                    true,
+                    now()?,
+                ),
+            };
+            usage.insert(&*pool.get()?)?;
+
+            let calls = message.calls();
+
+            if !session.should_run(&calls) {
+                break response;
+            }
 
-    let messages_len = request.messages.len();
+            let content = message.content.text();
+            session
+                .run(pool, user_message_id, &mut request, &content, calls)
+                .await?;
+        };
 
-    let user_message_content = &request.messages[messages_len - 1].content.clone();
+        let choice = response.choice()?;
 
-    let mut es = match chat_gpt_steam_client(&proxy_config, &api_key, request).await {
-        Ok(r) => r,
-        Err(e) => {
-            error!("获取流式响应时出错：{}", e);
-            return Err(e.to_string());
-        }
-    };
+        let chat_message = AssistantMessage::new(
+            choice.message.content.text(),
+            response.created,
+            user_message_id,
+            MessageStatus::from_finish_reason(choice.finish_reason.as_deref()),
+            None,
+        )
+        .with_tool_calls(choice.message.calls())
+        .with_citations(citations.clone());
 
-    // let mut stream = response.bytes_stream();
+        let conn = pool.get()?;
+        chat_message.insert(&conn)?;
 
-    let mut message_parts = Vec::new();
+        Ok(response)
+    }
+    .await;
 
-    let abort_flag = Arc::new(AtomicBool::new(false));
-    let id = window.listen("abort-stream", {
-        let abort_flag = Arc::clone(&abort_flag);
-        move |_| {
-            info!("中断流式消息");
-            abort_flag.store(true, Ordering::Relaxed);
-        }
-    });
+    if let Err(e) = &result {
+        error!("获取普通响应时出错：{}", e);
+
+        save_error_reply(pool, user_message_id, e, citations)?;
+    }
+
+    result
+}
+
+/// 流式响应中已收到的回复
+#[derive(Default)]
+struct StreamReply {
+    parts: Vec<String>,
+    tool_calls: ToolCallMerger,
+    created: u64,
+    finish_reason: Option<String>,
+    /// 收到了 `[DONE]`
+    done: bool,
+}
+
+/// 执行工具后发送给前端的事件
+#[derive(Clone, Serialize)]
+struct ToolEvent<'a> {
+    name: &'a str,
+    output: &'a str,
+}
+
+/// 接收事件流直到结束或被中断，chunk 通过 `stream://{id}` 事件发送
+///
+/// `started` 之后 `first_token` 内未收到第一个 chunk，或两个 chunk 间隔超过 `chunk`
+/// 时中断并返回超时错误。
+async fn receive_stream(
+    stream: &ChatStream<'_>,
+    es: &mut EventSource,
+    reply: &mut StreamReply,
+    timeouts: &Timeouts,
+    started: Instant,
+) -> Result<()> {
+    loop {
+        let wait = if reply.created == 0 {
+            timeouts.first_token.saturating_sub(started.elapsed())
+        } else {
+            timeouts.chunk
+        };
+
+        let event = tokio::select! {
+            event = es.next() => event,
+            _ = stream.aborted() => {
+                trace!("中断时的消息: {:?}", reply.parts);
+                es.close();
+                return Ok(());
+            }
+            _ = tokio::time::sleep(wait) => {
+                es.close();
+
+                let message = if reply.created == 0 {
+                    format!("{:?} 内未收到第一个 chunk", timeouts.first_token)
+                } else {
+                    format!("{:?} 内未收到新的 chunk", timeouts.chunk)
+                };
 
-    let mut done_flag = true;
-    let mut response_time = 0u64;
+                warn!("流式响应超时：{}", message);
+                return Err(AppError::Timeout(message));
+            }
+        };
+
+        let event = match event {
+            Some(e) => e,
+            None => return Ok(()),
+        };
 
-    // TODO: 超过一定时间(默认 5 秒)后未能继续获得 chunk 则自动中断，意味着响应失败
-    while let Some(event) = es.next().await {
         match event {
             Ok(Event::Open) => trace!("Connection Open!"),
             Ok(Event::Message(message)) => {
@@ -260,72 +825,427 @@ async fn chat_gpt_stream(
                 let data = &message.data;
 
                 if data == "[DONE]" {
-                    window.emit("stream", "done").map_err(|e| e.to_string())?;
-                    break;
+                    reply.done = true;
+                    es.close();
+                    return Ok(());
                 }
 
                 let chunk_message: MessageChunk = match serde_json::from_str(data) {
                     Ok(r) => r,
                     Err(e) => {
                         error!("反序列化 chunk str 时出错：{}", e);
-                        return Err(e.to_string());
+                        es.close();
+                        return Err(e.into());
                     }
                 };
 
-                if response_time == 0 {
-                    response_time = chunk_message.created;
+                // Azure 的第一个 chunk 只有 prompt_filter_results，只包含用量的 chunk
+                // 同样没有 choices
+                let choice = match chunk_message.choices.first() {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                if reply.created == 0 {
+                    reply.created = chunk_message.created;
                 }
 
-                if let Some(part) = &chunk_message.choices[0].delta.content {
-                    message_parts.push(part.to_string());
+                if let Some(part) = &choice.delta.content {
+                    reply.parts.push(part.to_string());
                 }
 
-                window
-                    .emit("stream", chunk_message)
-                    .map_err(|e| e.to_string())?;
+                reply.tool_calls.push(&choice.delta);
+
+                if choice.finish_reason.is_some() {
+                    reply.finish_reason = choice.finish_reason.clone();
+                }
+
+                stream.emit(chunk_message)?;
             }
             Err(err) => {
+                es.close();
+
                 match err {
                     reqwest_eventsource::Error::StreamEnded => {
-                        trace!("Connection Done!")
+                        trace!("Connection Done!");
+                        return Ok(());
                     }
                     _ => {
                         error!("解析流式响应时出错：{}", err);
-                        return Err(err.to_string());
+                        return Err(err.into());
                     }
                 }
-                es.close();
             }
         }
+    }
+}
+
+/// 发送流式请求并保存对话，返回用户消息的 id
+///
+/// 用户消息在请求前保存；之后中断或出错时，已收到的部分回复以
+/// `aborted`、`timeout` 或 `error` 状态保存。模型调用主题启用的工具时，
+/// 执行工具并发送 [`ToolEvent`] 后继续请求。
+#[allow(clippy::too_many_arguments)]
+async fn send_chat_stream(
+    pool: &SQLitePool,
+    stream: &ChatStream<'_>,
+    client: &HttpClient,
+    keys: &KeyStore,
+    tools: &ToolRegistry,
+    mcp: &McpManager,
+    proxy_config: &ProxyConfig,
+    topic_id: u32,
+    mut request: ChatGPTRequest,
+    created_at: u64,
+) -> Result<u32> {
+    // 本地图片编码后再保存，读取失败时不保存这次的用户消息
+    vision::inline_images(&mut request)?;
+
+    let model = request.model.clone();
+
+    let user_message_id = save_user_message(pool, &request, created_at, topic_id)?;
+
+    // 最后一次请求收到的回复，出错时同样保存
+    let mut reply = StreamReply::default();
+    let mut message = String::new();
+    let mut tool_calls = Vec::new();
+    let mut citations = Vec::new();
+
+    let result = async {
+        let mut session = ToolSession::new(tools, mcp, topic_id).await?;
+        session.prepare(&mut request);
+
+        // 拒绝发送时返回错误，不需要检索知识库；命中需要提示的类别时通知前端，仍然发送
+        let moderation = moderation::check(client, keys, proxy_config, topic_id, &request).await?;
+        if let Some(outcome) = save_moderation(pool, user_message_id, moderation)? {
+            if outcome.warned() {
+                stream.emit(&outcome.record)?;
+            }
+        }
+
+        citations =
+            knowledge::augment_request(pool, client, keys, proxy_config, topic_id, &mut request)
+                .await;
+
+        debug!("使用的代理：{:?}", proxy_config);
+        debug!("发送的消息：{:?}", request);
+
+        let provider = get_topic_provider(topic_id)?;
+        let provider = provider.as_ref();
+        let candidates = keys.topic_candidates(topic_id)?;
+
+        let timeouts = Timeouts::load()?;
+
+        loop {
+            // 流式响应不返回用量，使用本地分词器估算
+            let prompt_tokens = count_request(&request);
+            debug!("prompt token 数：{}", prompt_tokens);
+
+            let started = Instant::now();
+            reply = StreamReply::default();
+
+            // 只在连接建立前换 key，连接建立后的错误不再重试
+            let request_ref = &request;
+            let result = match keys
+                .run(candidates.clone(), |key| async move {
+                    chat_gpt_steam_client(client, provider, proxy_config, &key.key, request_ref)
+                        .await
+                })
+                .await
+            {
+                Ok(mut es) => {
+                    stream.set_status(StreamStatus::Streaming);
+                    receive_stream(stream, &mut es, &mut reply, &timeouts, started).await
+                }
+                Err(e) => Err(e),
+            };
+
+            message = reply.parts.join("");
+            trace!("chunk message: {:?}", message);
+
+            tool_calls = std::mem::take(&mut reply.tool_calls).finish();
+
+            // 中断前已生成的部分同样计费
+            if !message.is_empty() || !tool_calls.is_empty() {
+                let completion_tokens = count_text(&model, &message)
+                    + tool_calls
+                        .iter()
+                        .map(|c| count_text(&model, &c.function.arguments))
+                        .sum::<usize>();
+
+                let usage = UsageRecord::new(
+                    topic_id,
+                    &model,
+                    prompt_tokens as u32,
+                    completion_tokens as u32,
+                    true,
+                    now()?,
+                );
+
+                let conn = pool.get()?;
+                usage.insert(&conn)?;
+            }
+
+            if result.is_err() || stream.is_aborted() || !session.should_run(&tool_calls) {
+                return result;
+            }
 
-        if abort_flag.load(Ordering::Relaxed) {
-            trace!("中断时的消息: {:?}", message_parts);
-            done_flag = false;
-            es.close();
-            break;
+            let calls = std::mem::take(&mut tool_calls);
+            let results = session
+                .run(pool, user_message_id, &mut request, &message, calls)
+                .await?;
+
+            for result in &results {
+                stream.emit(ToolEvent {
+                    name: result.name.as_deref().unwrap_or_default(),
+                    output: &result.content.text(),
+                })?;
+            }
         }
     }
+    .await;
 
-    let message = message_parts.join("");
-    trace!("chunk message: {:?}", message);
+    match &result {
+        Err(e) => {
+            error!("获取流式响应时出错：{}", e);
 
-    let mut user_message_id = 0u32;
+            if matches!(e, AppError::Timeout(_)) {
+                if let Err(e) = stream.emit("timeout") {
+                    error!("发送超时事件时出错：{}", e);
+                }
+            }
+        }
+        Ok(_) if reply.done => {
+            if let Err(e) = stream.emit("done") {
+                error!("发送完成事件时出错：{}", e);
+            }
+        }
+        _ => {}
+    }
 
-    if done_flag {
-        let user_message = UserMessage::new(user_message_content, created_at, topic_id);
+    let conn = pool.get()?;
 
-        let conn = pool.get().map_err(|e| e.to_string())?;
-        user_message.insert(&conn).map_err(|e| e.to_string())?;
+    let (status, error) = match &result {
+        Err(e @ AppError::Timeout(_)) => (MessageStatus::Timeout, Some(e.to_string())),
+        Err(e) => (MessageStatus::Error, Some(e.to_string())),
+        Ok(_) if stream.is_aborted() => (MessageStatus::Aborted, None),
+        Ok(_) => (
+            MessageStatus::from_finish_reason(reply.finish_reason.as_deref()),
+            None,
+        ),
+    };
 
-        user_message_id = conn.last_insert_rowid() as u32;
+    let response_time = if reply.created == 0 {
+        now()?
+    } else {
+        reply.created
+    };
 
-        let chat_message = AssistantMessage::new(message, response_time, user_message_id);
+    let chat_message =
+        AssistantMessage::new(message, response_time, user_message_id, status, error)
+            .with_tool_calls(tool_calls)
+            .with_citations(citations);
 
-        chat_message.insert(&conn).map_err(|e| e.to_string())?;
-    }
+    chat_message.insert(&conn)?;
 
-    window.unlisten(id);
-    Ok(user_message_id)
+    result.map(|_| user_message_id)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn chat_gpt(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    tools: tauri::State<'_, ToolRegistry>,
+    mcp: tauri::State<'_, McpManager>,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    request: ChatGPTRequest,
+    created_at: u64,
+) -> Result<ChatGPTResponse> {
+    let client = clients.get(&proxy_config)?;
+
+    send_chat(
+        &pool,
+        &client,
+        &keys,
+        &tools,
+        &mcp,
+        &proxy_config,
+        topic_id,
+        request,
+        created_at,
+    )
+    .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn chat_gpt_stream(
+    pool: tauri::State<'_, SQLitePool>,
+    streams: tauri::State<'_, StreamRegistry>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    tools: tauri::State<'_, ToolRegistry>,
+    mcp: tauri::State<'_, McpManager>,
+    window: tauri::Window,
+    stream_id: String,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    request: ChatGPTRequest,
+    created_at: u64,
+) -> Result<u32> {
+    let client = clients.get(&proxy_config)?;
+    let stream = ChatStream::new(&window, &streams, &stream_id, topic_id)?;
+
+    let result = send_chat_stream(
+        &pool,
+        &stream,
+        &client,
+        &keys,
+        &tools,
+        &mcp,
+        &proxy_config,
+        topic_id,
+        request,
+        created_at,
+    )
+    .await;
+
+    stream.finish(&result);
+
+    result
+}
+
+/// 前端选择的本地图片，发送前由 `vision::inline_images` 编码
+fn images_from_paths(paths: Option<Vec<String>>) -> Vec<ImageUrl> {
+    paths
+        .unwrap_or_default()
+        .into_iter()
+        .map(|url| ImageUrl { url, detail: None })
+        .collect()
+}
+
+/// 根据主题配置和历史消息在后端组装请求
+fn build_topic_request(
+    pool: &SQLitePool,
+    topic_id: u32,
+    content: &str,
+    images: Option<Vec<String>>,
+    stream: bool,
+) -> Result<ChatGPTRequest> {
+    let config = config::read_config()?;
+
+    let conn = pool.get()?;
+    let mut history = get_messages(&conn, topic_id)?;
+    // 失败的回复不作为上下文
+    history.retain(|c| {
+        !matches!(
+            c.assistant.status,
+            MessageStatus::Error | MessageStatus::Timeout
+        )
+    });
+
+    Ok(build_request(
+        config.as_ref(),
+        topic_id,
+        &history,
+        content,
+        images_from_paths(images),
+        stream,
+    ))
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn topic_chat_gpt(
+    pool: tauri::State<'_, SQLitePool>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    tools: tauri::State<'_, ToolRegistry>,
+    mcp: tauri::State<'_, McpManager>,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    content: String,
+    images: Option<Vec<String>>,
+    created_at: u64,
+) -> Result<ChatGPTResponse> {
+    let request = build_topic_request(&pool, topic_id, &content, images, false)?;
+
+    let client = clients.get(&proxy_config)?;
+
+    send_chat(
+        &pool,
+        &client,
+        &keys,
+        &tools,
+        &mcp,
+        &proxy_config,
+        topic_id,
+        request,
+        created_at,
+    )
+    .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn topic_chat_gpt_stream(
+    pool: tauri::State<'_, SQLitePool>,
+    streams: tauri::State<'_, StreamRegistry>,
+    clients: tauri::State<'_, SharedClient>,
+    keys: tauri::State<'_, KeyStore>,
+    tools: tauri::State<'_, ToolRegistry>,
+    mcp: tauri::State<'_, McpManager>,
+    window: tauri::Window,
+    stream_id: String,
+    proxy_config: ProxyConfig,
+    topic_id: u32,
+    content: String,
+    images: Option<Vec<String>>,
+    created_at: u64,
+) -> Result<u32> {
+    let request = build_topic_request(&pool, topic_id, &content, images, true)?;
+
+    let client = clients.get(&proxy_config)?;
+    let stream = ChatStream::new(&window, &streams, &stream_id, topic_id)?;
+
+    let result = send_chat_stream(
+        &pool,
+        &stream,
+        &client,
+        &keys,
+        &tools,
+        &mcp,
+        &proxy_config,
+        topic_id,
+        request,
+        created_at,
+    )
+    .await;
+
+    stream.finish(&result);
+
+    result
+}
+
+#[tauri::command]
+fn list_streams(streams: tauri::State<'_, StreamRegistry>) -> Vec<StreamInfo> {
+    streams.list()
+}
+
+#[tauri::command]
+fn cancel_stream(streams: tauri::State<'_, StreamRegistry>, stream_id: String) -> bool {
+    streams.cancel(&stream_id)
+}
+
+#[tauri::command]
+fn get_stream_status(
+    streams: tauri::State<'_, StreamRegistry>,
+    stream_id: String,
+) -> Option<StreamInfo> {
+    streams.get(&stream_id)
 }
 
 #[tauri::command]
@@ -333,8 +1253,14 @@ fn get_messages_by_topic_id(
     pool: tauri::State<'_, SQLitePool>,
     topic_id: u32,
 ) -> Result<Vec<Conversation>> {
-    let conn = pool.get().map_err(|e| e.to_string())?;
-    get_messages(&conn, topic_id).map_err(|e| e.to_string())
+    let conn = pool.get()?;
+    get_messages(&conn, topic_id).map_err(AppError::from)
+}
+
+/// 读取消息附带的本地图片用于显示
+#[tauri::command]
+fn read_image(path: String) -> Result<String> {
+    vision::data_url(&path)
 }
 
 #[tauri::command]
@@ -345,7 +1271,7 @@ async fn get_topics(pool: tauri::State<'_, SQLitePool>) -> Result<Vec<Topic>> {
         Ok(c) => c,
         Err(e) => {
             error!("从连接池中获取连接时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     };
 
@@ -353,7 +1279,7 @@ async fn get_topics(pool: tauri::State<'_, SQLitePool>) -> Result<Vec<Topic>> {
         Ok(c) => c,
         Err(e) => {
             error!("获取全部主题时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     };
 
@@ -376,8 +1302,8 @@ async fn update_topic(
         new_description
     );
 
-    let conn = pool.get().map_err(|e| e.to_string())?;
-    update_topic_by_id(&conn, topid_id, &new_name, &new_description).map_err(|e| e.to_string())?;
+    let conn = pool.get()?;
+    update_topic_by_id(&conn, topid_id, &new_name, &new_description)?;
 
     debug!(
         "已更新主题名：id={}, name={}, description={}",
@@ -395,7 +1321,23 @@ async fn delete_message_by_time(pool: tauri::State<'_, SQLitePool>, create_at: u
         Ok(c) => c,
         Err(e) => {
             error!("从连接池中获取连接时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
+        }
+    };
+
+    let attachments = match get_attachment_paths_by_time(&conn, create_at) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("查询消息附件时出错：{}", e);
+            return Err(e.into());
+        }
+    };
+
+    let replies = match get_assistant_message_ids_by_time(&conn, create_at) {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("查询消息回复时出错：{}", e);
+            return Err(e.into());
         }
     };
 
@@ -403,10 +1345,13 @@ async fn delete_message_by_time(pool: tauri::State<'_, SQLitePool>, create_at: u
         Ok(c) => c,
         Err(e) => {
             error!("删除消息时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     };
 
+    vision::remove_attachments(&attachments);
+    tts::remove_cache(&replies);
+
     debug!("已删除消息：create_at={}", create_at);
 
     Ok(())
@@ -425,7 +1370,7 @@ async fn new_topic(
         Ok(t) => t,
         Err(e) => {
             error!("创建新主题时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     };
 
@@ -433,7 +1378,7 @@ async fn new_topic(
         Ok(c) => c,
         Err(e) => {
             error!("从连接池中获取连接时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     };
 
@@ -441,7 +1386,7 @@ async fn new_topic(
         Ok(()) => (),
         Err(e) => {
             error!("插入新主题时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     };
 
@@ -458,7 +1403,23 @@ async fn clear_topic(pool: tauri::State<'_, SQLitePool>, topic_id: u32) -> Resul
         Ok(c) => c,
         Err(e) => {
             error!("从连接池中获取连接时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
+        }
+    };
+
+    let attachments = match get_topic_attachment_paths(&conn, topic_id) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("查询主题附件时出错：{}", e);
+            return Err(e.into());
+        }
+    };
+
+    let replies = match get_topic_assistant_message_ids(&conn, topic_id) {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("查询主题回复时出错：{}", e);
+            return Err(e.into());
         }
     };
 
@@ -468,20 +1429,34 @@ async fn clear_topic(pool: tauri::State<'_, SQLitePool>, topic_id: u32) -> Resul
         DELETE FROM assistant_message WHERE user_message_id IN (
             SELECT id FROM user_message WHERE topic_id = {}
         );
+        DELETE FROM tool_message WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
+        DELETE FROM attachment WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
+        DELETE FROM message_embedding WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
+        DELETE FROM moderation WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
         DELETE FROM user_message WHERE topic_id = {};
         COMMIT; 
         "#,
-        topic_id, topic_id
+        topic_id, topic_id, topic_id, topic_id, topic_id, topic_id
     );
 
     match conn.execute_batch(&sql) {
         Ok(()) => {
+            vision::remove_attachments(&attachments);
+            tts::remove_cache(&replies);
             debug!("已清空主题消息：{}", topic_id);
             return Ok(());
         }
         Err(e) => {
             error!("清空主题时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     }
 }
@@ -494,7 +1469,31 @@ async fn delete_topic(pool: tauri::State<'_, SQLitePool>, topic_id: u32) -> Resu
         Ok(c) => c,
         Err(e) => {
             error!("从连接池中获取连接时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
+        }
+    };
+
+    let attachments = match get_topic_attachment_paths(&conn, topic_id) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("查询主题附件时出错：{}", e);
+            return Err(e.into());
+        }
+    };
+
+    let replies = match get_topic_assistant_message_ids(&conn, topic_id) {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("查询主题回复时出错：{}", e);
+            return Err(e.into());
+        }
+    };
+
+    let images = match get_images(&conn, topic_id) {
+        Ok(i) => i,
+        Err(e) => {
+            error!("查询主题图片时出错：{}", e);
+            return Err(e.into());
         }
     };
 
@@ -504,25 +1503,96 @@ async fn delete_topic(pool: tauri::State<'_, SQLitePool>, topic_id: u32) -> Resu
         DELETE FROM assistant_message WHERE user_message_id IN (
             SELECT id FROM user_message WHERE topic_id = {}
         );
+        DELETE FROM tool_message WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
+        DELETE FROM attachment WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
+        DELETE FROM message_embedding WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
+        DELETE FROM moderation WHERE user_message_id IN (
+            SELECT id FROM user_message WHERE topic_id = {}
+        );
         DELETE FROM user_message WHERE topic_id = {};
+        DELETE FROM image WHERE topic_id = {};
+        DELETE FROM knowledge_chunk WHERE topic_id = {};
+        DELETE FROM knowledge_document WHERE topic_id = {};
         DELETE FROM topic WHERE id = {};
         COMMIT;
         "#,
-        topic_id, topic_id, topic_id
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id,
+        topic_id
     );
 
     match conn.execute_batch(&sql) {
         Ok(()) => {
+            vision::remove_attachments(&attachments);
+            tts::remove_cache(&replies);
+            gallery::remove_images(&images);
             debug!("已删除主题：{}", topic_id);
             return Ok(());
         }
         Err(e) => {
             error!("删除主题时出错：{}", e);
-            return Err(e.to_string());
+            return Err(e.into());
         }
     }
 }
 
+fn usage_summary(
+    pool: &SQLitePool,
+    group: UsageGroup,
+    since: Option<u64>,
+    until: Option<u64>,
+) -> Result<Vec<UsageSummary>> {
+    let config = config::read_config()?;
+    let pricing = config::pricing_table(config.as_ref());
+
+    let conn = pool.get()?;
+
+    summarize_usage(&conn, group, since, until, &pricing).map_err(|e| {
+        error!("统计用量时出错：{}", e);
+        e.into()
+    })
+}
+
+#[tauri::command]
+async fn get_usage_by_day(
+    pool: tauri::State<'_, SQLitePool>,
+    since: Option<u64>,
+    until: Option<u64>,
+) -> Result<Vec<UsageSummary>> {
+    usage_summary(&pool, UsageGroup::Day, since, until)
+}
+
+#[tauri::command]
+async fn get_usage_by_model(
+    pool: tauri::State<'_, SQLitePool>,
+    since: Option<u64>,
+    until: Option<u64>,
+) -> Result<Vec<UsageSummary>> {
+    usage_summary(&pool, UsageGroup::Model, since, until)
+}
+
+#[tauri::command]
+async fn get_usage_by_topic(
+    pool: tauri::State<'_, SQLitePool>,
+    since: Option<u64>,
+    until: Option<u64>,
+) -> Result<Vec<UsageSummary>> {
+    usage_summary(&pool, UsageGroup::Topic, since, until)
+}
+
 #[tauri::command]
 async fn switch_top_status(window: tauri::Window, current: bool) -> Result<()> {
     match window.set_always_on_top(!current) {
@@ -533,7 +1603,7 @@ async fn switch_top_status(window: tauri::Window, current: bool) -> Result<()> {
         }
         Err(e) => {
             error!("切换窗口置顶状态时出错：{}", e);
-            Err(e.to_string())
+            Err(e.into())
         }
     }
 }
@@ -543,6 +1613,12 @@ fn init_database(pool: &SQLitePool) -> anyhow::Result<()> {
 
     init_topic(&conn)?;
     init_messages(&conn)?;
+    init_usage(&conn)?;
+    init_attachment(&conn)?;
+    init_image(&conn)?;
+    init_embedding(&conn)?;
+    init_knowledge(&conn)?;
+    init_moderation(&conn)?;
 
     Ok(())
 }
@@ -574,33 +1650,79 @@ async fn main() -> anyhow::Result<()> {
 
     init_database(&pool)?;
 
+    let keys = KeyStore::default();
+    // 使用系统密钥服务时不需要解锁，启动时直接迁移明文 key；加密文件在解锁后迁移
+    let backend = config::read_config()?.and_then(|c| c.secret_backend);
+    if backend == Some(SecretBackend::Keyring) {
+        if let Err(e) = keys.secrets().migrate_config() {
+            warn!("迁移明文 API key 失败：{}", e);
+        }
+    }
+
     tauri::Builder::default()
-        // .setup(|app| {
-        //     // let window = app.get_window("main").unwrap();
-        //     Ok(())
-        // })
+        .setup(|app| {
+            // let window = app.get_window("main").unwrap();
+            tauri::async_runtime::spawn(semantic::run_background(app.app_handle()));
+            Ok(())
+        })
         .manage(pool)
+        .manage(StreamRegistry::default())
+        .manage(SharedClient::default())
+        .manage(keys)
+        .manage(ToolRegistry::default())
+        .manage(McpManager::default())
+        .manage(EmbeddingIndexer::default())
         .invoke_handler(tauri::generate_handler![
             chat_gpt,
             chat_gpt_stream,
+            topic_chat_gpt,
+            topic_chat_gpt_stream,
+            list_streams,
+            cancel_stream,
+            get_stream_status,
             get_topics,
             get_models,
             get_model,
+            transcribe_audio,
+            speak_message,
+            export_topic_speech,
+            generate_images,
+            get_images_by_topic_id,
+            delete_image,
+            semantic_search,
+            import_knowledge,
+            get_knowledge_documents,
+            delete_knowledge_document,
             export_to_file,
             export_to_markdown,
             read_config,
             write_config,
             get_messages_by_topic_id,
+            read_image,
             new_topic,
             update_topic,
             clear_topic,
             delete_topic,
             delete_message_by_time,
             switch_top_status,
+            get_usage_by_day,
+            get_usage_by_model,
+            get_usage_by_topic,
+            get_secrets_status,
+            unlock_secrets,
+            lock_secrets,
+            list_mcp_servers,
+            stop_mcp_server,
             restore_is_on_top
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            // 退出时结束 MCP 服务进程
+            if let tauri::RunEvent::Exit = event {
+                app.state::<McpManager>().kill_all();
+            }
+        });
 
     Ok(())
 }
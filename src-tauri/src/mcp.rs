@@ -0,0 +1,555 @@
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::{oneshot, Mutex},
+};
+
+use crate::{
+    api::chat::{FunctionDefinition, Tool as ToolDefinition},
+    config::{Config, McpServerConfig},
+    error::{AppError, Result},
+};
+
+/// 客户端支持的 MCP 协议版本
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// OpenAI 函数名的最大长度
+const MAX_FUNCTION_NAME_LEN: usize = 64;
+
+type Pending = Arc<StdMutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+
+/// MCP 服务提供的工具
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct McpTool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "inputSchema", default)]
+    pub input_schema: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct McpServerStatus {
+    pub name: String,
+    pub running: bool,
+    pub tools: Vec<String>,
+}
+
+/// 一个已完成握手的 MCP 服务进程，通过标准输入输出收发以换行分隔的 JSON-RPC 消息
+pub struct McpServer {
+    name: String,
+    config: McpServerConfig,
+    child: Mutex<Child>,
+    stdin: Arc<Mutex<ChildStdin>>,
+    pending: Pending,
+    next_id: AtomicU64,
+    tools: Vec<McpTool>,
+}
+
+async fn write_message(stdin: &Mutex<ChildStdin>, message: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(&line).await?;
+    stdin.flush().await?;
+
+    Ok(())
+}
+
+fn rpc_error(error: &Value) -> AppError {
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("未知错误");
+
+    AppError::Other(format!("MCP 请求失败：{}", message))
+}
+
+/// 处理服务发来的一条消息：响应交给等待的请求，请求只支持 ping
+async fn dispatch(name: &str, line: &str, pending: &Pending, stdin: &Mutex<ChildStdin>) {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("MCP 服务 {} 输出了无法解析的消息：{}，{}", name, e, line);
+            return;
+        }
+    };
+
+    let id = message.get("id").cloned();
+    let method = message.get("method").and_then(Value::as_str);
+
+    match (id, method) {
+        // 服务发来的请求
+        (Some(id), Some(method)) => {
+            let response = if method == "ping" {
+                json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+            } else {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("不支持的方法：{}", method) }
+                })
+            };
+
+            if let Err(e) = write_message(stdin, &response).await {
+                error!("回复 MCP 服务 {} 的请求时出错：{}", name, e);
+            }
+        }
+        // 请求的响应
+        (Some(id), None) => {
+            let sender = id
+                .as_u64()
+                .and_then(|id| pending.lock().unwrap().remove(&id));
+
+            let sender = match sender {
+                Some(s) => s,
+                None => {
+                    warn!("MCP 服务 {} 返回了未知请求的响应：{}", name, id);
+                    return;
+                }
+            };
+
+            let result = match message.get("error") {
+                Some(error) => Err(rpc_error(error)),
+                None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+            };
+
+            let _ = sender.send(result);
+        }
+        (None, Some(method)) => trace!("MCP 服务 {} 的通知：{}", name, method),
+        (None, None) => warn!("MCP 服务 {} 输出了无效的消息：{}", name, line),
+    }
+}
+
+impl McpServer {
+    /// 启动服务进程并完成初始化握手，获取工具列表
+    async fn start(name: &str, config: &McpServerConfig) -> Result<Arc<Self>> {
+        info!(
+            "启动 MCP 服务：{}，{} {:?}",
+            name, config.command, config.args
+        );
+
+        let mut command = Command::new(&config.command);
+        command
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        if let Some(cwd) = &config.cwd {
+            command.current_dir(cwd);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| AppError::Config(format!("无法启动 MCP 服务 {}：{}", name, e)))?;
+
+        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        let pending: Pending = Arc::default();
+
+        {
+            let name = name.to_string();
+            let pending = Arc::clone(&pending);
+            let stdin = Arc::clone(&stdin);
+
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stdout).lines();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if !line.trim().is_empty() {
+                        dispatch(&name, &line, &pending, &stdin).await;
+                    }
+                }
+
+                warn!("MCP 服务 {} 已退出", name);
+
+                // 进程退出后，未完成的请求不会再有响应
+                for (_, sender) in pending.lock().unwrap().drain() {
+                    let _ = sender.send(Err(AppError::Other(format!("MCP 服务 {} 已退出", name))));
+                }
+            });
+        }
+
+        {
+            let name = name.to_string();
+
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!("[mcp:{}] {}", name, line);
+                }
+            });
+        }
+
+        let mut server = McpServer {
+            name: name.to_string(),
+            config: config.clone(),
+            child: Mutex::new(child),
+            stdin,
+            pending,
+            next_id: AtomicU64::new(1),
+            tools: Vec::new(),
+        };
+
+        let result = server
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "chatgpt-client",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            )
+            .await?;
+
+        debug!("MCP 服务 {} 初始化完成：{}", name, result);
+
+        server
+            .notify("notifications/initialized", json!({}))
+            .await?;
+
+        server.tools = server.list_tools().await?;
+
+        info!(
+            "MCP 服务 {} 提供的工具：{:?}",
+            name,
+            server.tools.iter().map(|t| &t.name).collect::<Vec<_>>()
+        );
+
+        Ok(Arc::new(server))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+
+        trace!("MCP 请求：{} {}", self.name, message);
+
+        if let Err(e) = write_message(&self.stdin, &message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(AppError::Other(format!("MCP 服务 {} 已退出", self.name))),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(AppError::Timeout(format!(
+                    "MCP 服务 {} 在 {:?} 内未响应 {}",
+                    self.name, timeout, method
+                )))
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        write_message(
+            &self.stdin,
+            &json!({ "jsonrpc": "2.0", "method": method, "params": params }),
+        )
+        .await
+    }
+
+    async fn list_tools(&self) -> Result<Vec<McpTool>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let params = match &cursor {
+                Some(c) => json!({ "cursor": c }),
+                None => json!({}),
+            };
+
+            let result = self.request("tools/list", params).await?;
+
+            let page: Vec<McpTool> =
+                serde_json::from_value(result.get("tools").cloned().unwrap_or(json!([])))?;
+            tools.extend(page);
+
+            cursor = result
+                .get("nextCursor")
+                .and_then(Value::as_str)
+                .map(String::from);
+
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tools(&self) -> &[McpTool] {
+        &self.tools
+    }
+
+    /// 调用工具，文本内容按顺序拼接，其他类型的内容以 JSON 返回
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+
+        let output = result
+            .get("content")
+            .and_then(Value::as_array)
+            .map(|content| {
+                content
+                    .iter()
+                    .map(|item| match item.get("text").and_then(Value::as_str) {
+                        Some(text) => text.to_string(),
+                        None => item.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+
+        if result.get("isError").and_then(Value::as_bool) == Some(true) {
+            return Err(AppError::Other(output));
+        }
+
+        Ok(output)
+    }
+
+    async fn is_running(&self) -> bool {
+        matches!(self.child.lock().await.try_wait(), Ok(None))
+    }
+
+    async fn stop(&self) {
+        info!("停止 MCP 服务：{}", self.name);
+
+        if let Err(e) = self.child.lock().await.kill().await {
+            warn!("停止 MCP 服务 {} 时出错：{}", self.name, e);
+        }
+    }
+}
+
+/// 工具在请求中的函数名：`服务名__工具名`，只保留 OpenAI 允许的字符
+pub fn function_name(server: &str, tool: &str) -> String {
+    format!("{}__{}", server, tool)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_FUNCTION_NAME_LEN)
+        .collect()
+}
+
+pub fn tool_definition(server: &str, tool: &McpTool) -> ToolDefinition {
+    ToolDefinition {
+        kind: "function".to_string(),
+        function: FunctionDefinition {
+            name: function_name(server, &tool.name),
+            description: tool.description.clone(),
+            parameters: Some(if tool.input_schema.is_null() {
+                json!({ "type": "object", "properties": {} })
+            } else {
+                tool.input_schema.clone()
+            }),
+        },
+    }
+}
+
+fn server_configs(config: Option<&Config>) -> HashMap<String, McpServerConfig> {
+    config
+        .and_then(|c| c.mcp_servers.clone())
+        .unwrap_or_default()
+}
+
+/// 已启动的 MCP 服务，首次使用时启动，配置改变或进程退出后重新启动
+#[derive(Default)]
+pub struct McpManager {
+    servers: Mutex<HashMap<String, Arc<McpServer>>>,
+}
+
+impl McpManager {
+    /// 已启动且配置未改变的服务
+    async fn running(&self, name: &str, config: &McpServerConfig) -> Option<Arc<McpServer>> {
+        let mut servers = self.servers.lock().await;
+        let server = Arc::clone(servers.get(name)?);
+
+        if server.config == *config && server.is_running().await {
+            return Some(server);
+        }
+
+        servers.remove(name);
+        drop(servers);
+        server.stop().await;
+
+        None
+    }
+
+    /// 启动和初始化服务时不持有锁，一个服务启动缓慢不会阻塞其他主题
+    async fn get(&self, name: &str, config: &McpServerConfig) -> Result<Arc<McpServer>> {
+        if let Some(server) = self.running(name, config).await {
+            return Ok(server);
+        }
+
+        let server = McpServer::start(name, config).await?;
+
+        // 其他任务可能同时启动了同一个服务，保留先插入的服务
+        let mut servers = self.servers.lock().await;
+        if let Some(existing) = servers.get(name) {
+            if existing.config == *config && existing.is_running().await {
+                let existing = Arc::clone(existing);
+                drop(servers);
+                server.stop().await;
+                return Ok(existing);
+            }
+        }
+
+        let old = servers.insert(name.to_string(), Arc::clone(&server));
+        drop(servers);
+        if let Some(old) = old {
+            old.stop().await;
+        }
+
+        Ok(server)
+    }
+
+    /// 主题启用的 MCP 服务，无法启动的服务只记录错误
+    pub async fn topic_servers(
+        &self,
+        config: Option<&Config>,
+        topic_id: u32,
+    ) -> Vec<Arc<McpServer>> {
+        let names = config
+            .and_then(|c| c.topics.as_ref())
+            .and_then(|t| t.get(&topic_id.to_string()))
+            .and_then(|t| t.mcp_servers.clone())
+            .unwrap_or_default();
+
+        let configs = server_configs(config);
+        let mut servers = Vec::new();
+
+        for name in names {
+            let server_config = match configs.get(&name) {
+                Some(c) => c,
+                None => {
+                    warn!("未配置 MCP 服务：{}", name);
+                    continue;
+                }
+            };
+
+            match self.get(&name, server_config).await {
+                Ok(server) => servers.push(server),
+                Err(e) => error!("启动 MCP 服务 {} 失败：{}", name, e),
+            }
+        }
+
+        servers
+    }
+
+    /// 启动配置中的服务，返回其状态
+    pub async fn status(&self, config: Option<&Config>) -> Vec<McpServerStatus> {
+        let mut status = Vec::new();
+
+        for (name, server_config) in server_configs(config) {
+            let tools = match self.get(&name, &server_config).await {
+                Ok(server) => Some(server.tools().iter().map(|t| t.name.clone()).collect()),
+                Err(e) => {
+                    error!("启动 MCP 服务 {} 失败：{}", name, e);
+                    None
+                }
+            };
+
+            status.push(McpServerStatus {
+                name,
+                running: tools.is_some(),
+                tools: tools.unwrap_or_default(),
+            });
+        }
+
+        status.sort_by(|a, b| a.name.cmp(&b.name));
+
+        status
+    }
+
+    pub async fn stop(&self, name: &str) -> bool {
+        let server = self.servers.lock().await.remove(name);
+
+        match server {
+            Some(server) => {
+                server.stop().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 停止已从配置中删除或配置已改变的服务
+    pub async fn sync_config(&self, config: Option<&Config>) {
+        let configs = server_configs(config);
+
+        // 在锁内取出需要停止的服务，等待进程退出时不阻塞其他主题
+        let stale: Vec<Arc<McpServer>> = {
+            let mut servers = self.servers.lock().await;
+            let names: Vec<String> = servers
+                .iter()
+                .filter(|(name, server)| configs.get(*name) != Some(&server.config))
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            names
+                .iter()
+                .filter_map(|name| servers.remove(name))
+                .collect()
+        };
+
+        for server in stale {
+            server.stop().await;
+        }
+    }
+
+    /// 退出程序时结束所有服务进程，不等待进程退出
+    ///
+    /// 在主线程的事件循环中调用，不在异步运行时内，可以阻塞等待锁。服务表的锁只在
+    /// 短暂的操作中持有，进程的锁最多持有到正在停止的进程退出。
+    pub fn kill_all(&self) {
+        let servers = self.servers.blocking_lock();
+
+        for server in servers.values() {
+            if let Err(e) = server.child.blocking_lock().start_kill() {
+                warn!("结束 MCP 服务 {} 时出错：{}", server.name, e);
+            }
+        }
+    }
+}
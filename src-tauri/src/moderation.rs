@@ -0,0 +1,198 @@
+//! 发送前的内容审核
+//!
+//! 主题配置了审核策略时，发送前用 `/moderations` 检查最后一条用户消息，按命中类别
+//! 配置的处理只记录、提示或拒绝发送。审核结果随用户消息一起保存在 `moderation` 表。
+
+use std::collections::BTreeMap;
+
+use rusqlite::Connection;
+
+use crate::{
+    api::{
+        chat::ChatGPTRequest,
+        client::HttpClient,
+        moderations::{moderations_client, ModerationResult, DEFAULT_MODEL},
+        provider::get_topic_provider,
+    },
+    config::{read_config, ModerationAction, ModerationPolicy, ProxyConfig},
+    db::moderation::ModerationRecord,
+    error::{AppError, Result},
+    keys::KeyStore,
+    time::now,
+};
+
+/// 一次审核的结果和需要采取的处理
+pub struct ModerationOutcome {
+    /// 保存前 `user_message_id` 为 0
+    pub record: ModerationRecord,
+    /// 命中类别中最严格的处理
+    pub action: Option<ModerationAction>,
+}
+
+impl ModerationOutcome {
+    pub fn blocked(&self) -> bool {
+        self.action == Some(ModerationAction::Block)
+    }
+
+    pub fn warned(&self) -> bool {
+        self.action == Some(ModerationAction::Warn)
+    }
+
+    pub fn save(&mut self, conn: &Connection, user_message_id: u32) -> Result<()> {
+        self.record.user_message_id = user_message_id;
+        self.record.insert(conn)?;
+
+        Ok(())
+    }
+
+    /// 拒绝发送时返回给前端的错误
+    pub fn error(&self) -> AppError {
+        match &self.record.error {
+            Some(e) => AppError::Moderation(format!("内容审核请求失败，已拒绝发送：{}", e)),
+            None => AppError::Moderation(format!(
+                "内容审核未通过：{}",
+                self.record
+                    .categories
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("、")
+            )),
+        }
+    }
+}
+
+fn topic_policy(topic_id: u32) -> Result<Option<ModerationPolicy>> {
+    let config = read_config()?;
+
+    Ok(config
+        .as_ref()
+        .and_then(|c| c.topics.as_ref())
+        .and_then(|t| t.get(&topic_id.to_string()))
+        .and_then(|t| t.moderation.clone()))
+}
+
+/// 按策略判断命中的类别，接口标记的类别或分数达到阈值的类别视为命中
+fn evaluate(
+    policy: &ModerationPolicy,
+    model: &str,
+    result: ModerationResult,
+    created_at: u64,
+) -> ModerationOutcome {
+    let mut hits = BTreeMap::new();
+
+    for (category, flagged) in &result.categories {
+        let score = result
+            .category_scores
+            .get(category)
+            .copied()
+            .unwrap_or_default();
+
+        if *flagged || policy.threshold.is_some_and(|t| score >= t) {
+            hits.insert(category.clone(), policy.action_for(category));
+        }
+    }
+
+    let action = hits.values().max().copied();
+
+    ModerationOutcome {
+        record: ModerationRecord {
+            id: 0,
+            user_message_id: 0,
+            model: model.to_string(),
+            flagged: result.flagged,
+            action: action.map(|a| a.as_str().to_string()),
+            categories: hits
+                .into_iter()
+                .map(|(c, a)| (c, a.as_str().to_string()))
+                .collect(),
+            scores: result.category_scores,
+            error: None,
+            created_at,
+        },
+        action,
+    }
+}
+
+/// 审核请求中最后一条用户消息，主题没有配置审核策略时返回 `None`
+///
+/// 审核请求失败时，`fail_closed` 的策略拒绝发送，否则只记录错误。
+pub async fn check(
+    client: &HttpClient,
+    keys: &KeyStore,
+    proxy_config: &ProxyConfig,
+    topic_id: u32,
+    request: &ChatGPTRequest,
+) -> Result<Option<ModerationOutcome>> {
+    let policy = match topic_policy(topic_id)? {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+
+    let input = request
+        .messages
+        .iter()
+        .rfind(|m| m.role == "user")
+        .map(|m| m.content.text())
+        .unwrap_or_default();
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let model = policy.model.as_deref().unwrap_or(DEFAULT_MODEL);
+    let provider = get_topic_provider(topic_id)?;
+    let provider = provider.as_ref();
+    let candidates = keys.topic_candidates(topic_id)?;
+    let input = &input;
+
+    let response = keys
+        .run(candidates, |key| async move {
+            moderations_client(client, provider, proxy_config, &key.key, model, input).await
+        })
+        .await;
+
+    let created_at = now()?;
+
+    let result = response.and_then(|r| {
+        r.results
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::Parse("审核响应中没有结果".to_string()))
+    });
+
+    let outcome = match result {
+        Ok(result) => evaluate(&policy, model, result, created_at),
+        Err(e) => {
+            warn!("内容审核请求失败：{}", e);
+
+            ModerationOutcome {
+                record: ModerationRecord {
+                    id: 0,
+                    user_message_id: 0,
+                    model: model.to_string(),
+                    flagged: false,
+                    action: policy
+                        .fail_closed
+                        .then(|| ModerationAction::Block.as_str().to_string()),
+                    categories: BTreeMap::new(),
+                    scores: BTreeMap::new(),
+                    error: Some(e.to_string()),
+                    created_at,
+                },
+                action: policy.fail_closed.then_some(ModerationAction::Block),
+            }
+        }
+    };
+
+    match outcome.action {
+        Some(action) => info!(
+            "内容审核命中：topic_id={} action={} categories={:?}",
+            topic_id,
+            action.as_str(),
+            outcome.record.categories
+        ),
+        None => debug!("内容审核通过：topic_id={}", topic_id),
+    }
+
+    Ok(Some(outcome))
+}
@@ -0,0 +1,537 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lazy_static::lazy_static;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Config, SecretBackend, APP_CONFIG_DIR},
+    error::{AppError, Result},
+    keys::DEFAULT_KEY_NAME,
+};
+
+lazy_static! {
+    static ref SECRETS_FILE: PathBuf = APP_CONFIG_DIR.join("secrets.json");
+}
+
+/// 配置中引用密钥的前缀，如 `secret://openai/default`
+pub const SECRET_PREFIX: &str = "secret://";
+
+/// 系统密钥服务中使用的服务名
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "chatgpt-client";
+
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+/// AES-GCM 的 nonce 长度
+const NONCE_LEN: usize = 12;
+
+/// 加密后的密钥文件
+#[derive(Deserialize, Serialize)]
+struct SecretsFile {
+    version: u32,
+    /// Argon2id 参数
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    /// AES-256-GCM 加密的 `{名字: 密钥}`
+    ciphertext: String,
+}
+
+/// 解锁后的密钥
+struct Vault {
+    key: [u8; KEY_LEN],
+    salt: Vec<u8>,
+    params: Params,
+    secrets: HashMap<String, String>,
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| AppError::Config(format!("密钥文件的 {} 无效：{}", field, e)))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
+
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| AppError::Other(format!("派生密钥失败：{}", e)))?;
+
+    Ok(key)
+}
+
+impl Vault {
+    fn create(passphrase: &str) -> Result<Self> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        let params = Params::default();
+        let key = derive_key(passphrase, &salt, params.clone())?;
+
+        Ok(Vault {
+            key,
+            salt,
+            params,
+            secrets: HashMap::new(),
+        })
+    }
+
+    fn open(passphrase: &str, file: &SecretsFile) -> Result<Self> {
+        // 创建时使用默认参数，更大的值只能来自损坏的文件，会让 Argon2 分配大量内存
+        if file.m_cost > Params::DEFAULT_M_COST
+            || file.t_cost > Params::DEFAULT_T_COST
+            || file.p_cost > Params::DEFAULT_P_COST
+        {
+            return Err(AppError::Config(format!(
+                "密钥文件的参数无效：m_cost={}, t_cost={}, p_cost={}",
+                file.m_cost, file.t_cost, file.p_cost
+            )));
+        }
+
+        let params = Params::new(file.m_cost, file.t_cost, file.p_cost, Some(KEY_LEN))
+            .map_err(|e| AppError::Config(format!("密钥文件的参数无效：{}", e)))?;
+
+        let salt = decode("salt", &file.salt)?;
+        let nonce = decode("nonce", &file.nonce)?;
+        let ciphertext = decode("ciphertext", &file.ciphertext)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(AppError::Config(format!(
+                "密钥文件的 nonce 长度无效：{}",
+                nonce.len()
+            )));
+        }
+
+        let key = derive_key(passphrase, &salt, params.clone())?;
+
+        let cipher = Aes256Gcm::new_from_slice(&key).unwrap();
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| AppError::Config("密码错误或密钥文件已损坏".to_string()))?;
+
+        let secrets = serde_json::from_slice(&plaintext)?;
+
+        Ok(Vault {
+            key,
+            salt,
+            params,
+            secrets,
+        })
+    }
+
+    /// 每次保存使用新的 nonce 重新加密
+    ///
+    /// 先写入临时文件再替换，写入中途失败不会损坏原来的密钥文件。
+    fn save(&self, path: &Path) -> Result<()> {
+        let cipher = Aes256Gcm::new_from_slice(&self.key).unwrap();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let plaintext = serde_json::to_vec(&self.secrets)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|e| AppError::Other(format!("加密密钥失败：{}", e)))?;
+
+        let file = SecretsFile {
+            version: 1,
+            m_cost: self.params.m_cost(),
+            t_cost: self.params.t_cost(),
+            p_cost: self.params.p_cost(),
+            salt: STANDARD.encode(&self.salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&file)?)?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SecretsStatus {
+    pub backend: SecretBackend,
+    /// 是否已创建密钥文件
+    pub exists: bool,
+    pub unlocked: bool,
+}
+
+/// 保存 API key 等密钥，配置文件中只保存 `secret://名字` 形式的引用
+///
+/// 默认保存在用密码加密的 `secrets.json` 中（Argon2id 派生密钥，AES-256-GCM 加密），
+/// 启动后需要输入密码解锁；启用 `keyring` 特性后也可以使用系统的密钥服务。
+pub struct SecretStore {
+    vault: Mutex<Option<Vault>>,
+    file: PathBuf,
+}
+
+impl Default for SecretStore {
+    fn default() -> Self {
+        SecretStore {
+            vault: Mutex::new(None),
+            file: SECRETS_FILE.clone(),
+        }
+    }
+}
+
+fn backend(config: Option<&Config>) -> SecretBackend {
+    config.and_then(|c| c.secret_backend).unwrap_or_default()
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(name: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .map_err(|e| AppError::Config(format!("无法访问系统密钥服务：{}", e)))
+}
+
+impl SecretStore {
+    pub fn status(&self) -> Result<SecretsStatus> {
+        let config = config::read_config()?;
+
+        Ok(SecretsStatus {
+            backend: backend(config.as_ref()),
+            exists: self.file.exists(),
+            unlocked: self.vault.lock().unwrap().is_some(),
+        })
+    }
+
+    /// 是否应将配置中的明文密钥移入密钥库
+    fn enabled(&self, config: Option<&Config>) -> bool {
+        match backend(config) {
+            SecretBackend::File => self.file.exists(),
+            SecretBackend::Keyring => true,
+        }
+    }
+
+    /// 用密码解锁密钥文件，文件不存在时以此密码创建
+    pub fn unlock(&self, passphrase: &str) -> Result<()> {
+        if passphrase.is_empty() {
+            return Err(AppError::Config("密码不能为空".to_string()));
+        }
+
+        let vault = if self.file.exists() {
+            let content = fs::read(&self.file)?;
+            let file: SecretsFile = serde_json::from_slice(&content)?;
+
+            Vault::open(passphrase, &file)?
+        } else {
+            let vault = Vault::create(passphrase)?;
+            vault.save(&self.file)?;
+
+            info!("已创建密钥文件：{}", self.file.display());
+
+            vault
+        };
+
+        *self.vault.lock().unwrap() = Some(vault);
+
+        info!("已解锁密钥文件");
+
+        Ok(())
+    }
+
+    pub fn lock(&self) {
+        *self.vault.lock().unwrap() = None;
+
+        info!("已锁定密钥文件");
+    }
+
+    fn get(&self, config: Option<&Config>, name: &str) -> Result<String> {
+        match backend(config) {
+            SecretBackend::File => {
+                let vault = self.vault.lock().unwrap();
+                let vault = vault
+                    .as_ref()
+                    .ok_or_else(|| AppError::Config("密钥文件未解锁".to_string()))?;
+
+                vault
+                    .secrets
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| AppError::Config(format!("密钥不存在：{}", name)))
+            }
+            #[cfg(feature = "keyring")]
+            SecretBackend::Keyring => keyring_entry(name)?
+                .get_password()
+                .map_err(|e| AppError::Config(format!("读取密钥 {} 失败：{}", name, e))),
+            #[cfg(not(feature = "keyring"))]
+            SecretBackend::Keyring => Err(keyring_unsupported()),
+        }
+    }
+
+    fn set(&self, config: Option<&Config>, name: &str, secret: &str) -> Result<()> {
+        match backend(config) {
+            SecretBackend::File => {
+                let mut vault = self.vault.lock().unwrap();
+                let vault = vault
+                    .as_mut()
+                    .ok_or_else(|| AppError::Config("密钥文件未解锁".to_string()))?;
+
+                vault.secrets.insert(name.to_string(), secret.to_string());
+                vault.save(&self.file)
+            }
+            #[cfg(feature = "keyring")]
+            SecretBackend::Keyring => keyring_entry(name)?
+                .set_password(secret)
+                .map_err(|e| AppError::Config(format!("保存密钥 {} 失败：{}", name, e))),
+            #[cfg(not(feature = "keyring"))]
+            SecretBackend::Keyring => Err(keyring_unsupported()),
+        }
+    }
+
+    /// 解析配置中的值，`secret://名字` 从密钥库中读取，其他值原样返回
+    pub fn resolve(&self, config: Option<&Config>, value: &str) -> Result<String> {
+        match value.strip_prefix(SECRET_PREFIX) {
+            Some(name) => self.get(config, name),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// 将配置中的明文 key 移入密钥库并替换为引用，返回是否有改动
+    ///
+    /// 与 `old` 中相同的明文 key 不处理，密钥库锁定时也能保存其他配置，这些 key 在解锁时迁移。
+    /// 未启用密钥库（未创建密钥文件且未使用系统密钥服务）时不做处理。
+    pub fn protect(&self, config: &mut Config, old: Option<&Config>) -> Result<bool> {
+        if !self.enabled(Some(config)) {
+            return Ok(false);
+        }
+
+        let is_plaintext = |key: &str| !key.is_empty() && !key.starts_with(SECRET_PREFIX);
+
+        // (在 api_keys 中的位置，open_api_key 为 None；密钥名；key)
+        let mut plaintext = Vec::new();
+
+        if is_plaintext(&config.open_api_key)
+            && old.map(|c| c.open_api_key.as_str()) != Some(config.open_api_key.as_str())
+        {
+            plaintext.push((
+                None,
+                secret_name(None, DEFAULT_KEY_NAME),
+                config.open_api_key.clone(),
+            ));
+        }
+
+        let old_keys = old.and_then(|c| c.api_keys.as_ref());
+
+        for (index, key) in config.api_keys.iter().flatten().enumerate() {
+            let unchanged = old_keys.is_some_and(|keys| keys.contains(key));
+
+            if is_plaintext(&key.key) && !unchanged {
+                plaintext.push((
+                    Some(index),
+                    secret_name(key.provider.as_deref(), &key.name),
+                    key.key.clone(),
+                ));
+            }
+        }
+
+        if plaintext.is_empty() {
+            return Ok(false);
+        }
+
+        for (_, name, secret) in &plaintext {
+            self.set(Some(config), name, secret)?;
+        }
+
+        let reference = |name: &str| format!("{}{}", SECRET_PREFIX, name);
+
+        for (index, name, _) in &plaintext {
+            match index {
+                None => config.open_api_key = reference(name),
+                Some(i) => {
+                    if let Some(key) = config.api_keys.as_mut().and_then(|k| k.get_mut(*i)) {
+                        key.key = reference(name);
+                    }
+                }
+            }
+        }
+
+        info!("已将 {} 个明文 key 移入密钥库", plaintext.len());
+
+        Ok(true)
+    }
+
+    /// 迁移配置文件中已有的明文 key
+    pub fn migrate_config(&self) -> Result<()> {
+        let mut config = match config::read_config()? {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        if self.protect(&mut config, None)? {
+            config::write_config(&config)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// 密钥库中的名字，以服务提供商区分，不同服务提供商的同名 key 不会互相覆盖
+fn secret_name(provider: Option<&str>, name: &str) -> String {
+    format!("{}/{}", provider.unwrap_or("openai"), name)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_unsupported() -> AppError {
+    AppError::Config("当前版本未启用系统密钥服务，请使用加密文件保存密钥".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> SecretStore {
+        let file = std::env::temp_dir().join(format!(
+            "chatgpt-client-secrets-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&file);
+
+        SecretStore {
+            vault: Mutex::new(None),
+            file,
+        }
+    }
+
+    fn read_file(store: &SecretStore) -> SecretsFile {
+        serde_json::from_slice(&fs::read(&store.file).unwrap()).unwrap()
+    }
+
+    fn write_file(store: &SecretStore, file: &SecretsFile) {
+        fs::write(&store.file, serde_json::to_vec(file).unwrap()).unwrap();
+    }
+
+    fn config(open_api_key: &str, api_key: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            open_api_key = "{}"
+            image_scale = 1
+
+            [proxy]
+            method = "none"
+
+            [[api_keys]]
+            name = "work"
+            key = "{}"
+            provider = "azure"
+            "#,
+            open_api_key, api_key
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn unlock_roundtrip() {
+        let store = store("roundtrip");
+        store.unlock("passphrase").unwrap();
+        store.set(None, "openai/default", "sk-test").unwrap();
+        store.lock();
+
+        assert!(store.get(None, "openai/default").is_err());
+
+        store.unlock("passphrase").unwrap();
+        assert_eq!(store.get(None, "openai/default").unwrap(), "sk-test");
+        assert!(!store.file.with_extension("json.tmp").exists());
+
+        fs::remove_file(&store.file).unwrap();
+    }
+
+    #[test]
+    fn unlock_rejects_wrong_passphrase() {
+        let store = store("passphrase");
+        store.unlock("passphrase").unwrap();
+        store.lock();
+
+        assert!(matches!(store.unlock("wrong"), Err(AppError::Config(_))));
+        assert!(!store.status().unwrap().unlocked);
+
+        fs::remove_file(&store.file).unwrap();
+    }
+
+    #[test]
+    fn unlock_rejects_corrupt_file() {
+        let store = store("corrupt");
+        store.unlock("passphrase").unwrap();
+        store.set(None, "openai/default", "sk-test").unwrap();
+        store.lock();
+        let original = read_file(&store);
+
+        let mut file = read_file(&store);
+        file.nonce = STANDARD.encode([0u8; 5]);
+        write_file(&store, &file);
+        assert!(matches!(
+            store.unlock("passphrase"),
+            Err(AppError::Config(_))
+        ));
+
+        let mut file = read_file(&store);
+        file.nonce = original.nonce.clone();
+        let mut ciphertext = STANDARD.decode(&original.ciphertext).unwrap();
+        ciphertext[0] ^= 0xFF;
+        file.ciphertext = STANDARD.encode(ciphertext);
+        write_file(&store, &file);
+        assert!(matches!(
+            store.unlock("passphrase"),
+            Err(AppError::Config(_))
+        ));
+
+        let mut file = read_file(&store);
+        file.ciphertext = original.ciphertext.clone();
+        file.m_cost = u32::MAX;
+        write_file(&store, &file);
+        assert!(matches!(
+            store.unlock("passphrase"),
+            Err(AppError::Config(_))
+        ));
+
+        write_file(&store, &original);
+        store.unlock("passphrase").unwrap();
+        assert_eq!(store.get(None, "openai/default").unwrap(), "sk-test");
+
+        fs::remove_file(&store.file).unwrap();
+    }
+
+    #[test]
+    fn protect_keeps_unchanged_keys() {
+        let store = store("protect");
+        store.unlock("passphrase").unwrap();
+
+        let old = config("sk-old", "sk-work");
+        let mut config = config("sk-old", "sk-work");
+        assert!(!store.protect(&mut config, Some(&old)).unwrap());
+        assert_eq!(config.open_api_key, "sk-old");
+
+        config.open_api_key = "sk-new".to_string();
+        assert!(store.protect(&mut config, Some(&old)).unwrap());
+        assert_eq!(config.open_api_key, "secret://openai/default");
+        assert_eq!(config.api_keys.as_ref().unwrap()[0].key, "sk-work");
+        assert_eq!(
+            store.resolve(Some(&config), &config.open_api_key).unwrap(),
+            "sk-new"
+        );
+
+        assert!(store.protect(&mut config, None).unwrap());
+        assert_eq!(
+            config.api_keys.as_ref().unwrap()[0].key,
+            "secret://azure/work"
+        );
+
+        fs::remove_file(&store.file).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! 历史对话的语义搜索
+//!
+//! 每组对话（用户消息和最终回复）的向量保存在 `message_embedding` 表，按模型区分。
+//! 新的对话由后台任务定期建立索引，搜索前也会先补全索引；相似度在本地计算。
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::{
+    api::{
+        client::{HttpClient, SharedClient},
+        embeddings::{embeddings_client, EmbeddingRequest},
+        provider::{get_provider, Provider},
+    },
+    config::{read_config, EmbeddingConfig, ProxyConfig},
+    db::{
+        embedding::{get_embeddings, get_pending_conversations, insert_embedding},
+        message::{get_messages, Conversation},
+    },
+    error::{AppError, Result},
+    keys::{ApiKey, KeyStore},
+    time::now,
+    SQLitePool,
+};
+
+/// 每组对话参与计算向量的最大字符数，超过的部分截断
+const MAX_INPUT_CHARS: usize = 2000;
+
+pub const DEFAULT_TOP_K: usize = 10;
+
+/// 搜索前最多建立索引的批次数，其余的由后台索引，避免第一次搜索等待全部历史对话
+pub const SEARCH_INDEX_BATCHES: usize = 1;
+
+/// 计算向量需要的客户端、提供商和 key
+pub struct Embedder<'a> {
+    client: HttpClient,
+    provider: Box<dyn Provider>,
+    proxy_config: ProxyConfig,
+    keys: &'a KeyStore,
+    candidates: Vec<ApiKey>,
+    pub model: String,
+}
+
+impl<'a> Embedder<'a> {
+    pub fn new(
+        client: &HttpClient,
+        keys: &'a KeyStore,
+        proxy_config: &ProxyConfig,
+        config: &EmbeddingConfig,
+    ) -> Result<Self> {
+        let provider = config.provider.as_deref();
+
+        Ok(Embedder {
+            client: client.clone(),
+            provider: get_provider(provider)?,
+            proxy_config: proxy_config.clone(),
+            keys,
+            candidates: keys.provider_candidates(provider)?,
+            model: config.model.clone(),
+        })
+    }
+
+    /// 按输入的顺序返回向量
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = EmbeddingRequest {
+            model: &self.model,
+            input: inputs,
+        };
+        let request = &request;
+
+        let vectors = self
+            .keys
+            .run(self.candidates.clone(), |key| async move {
+                embeddings_client(
+                    &self.client,
+                    self.provider.as_ref(),
+                    &self.proxy_config,
+                    &key.key,
+                    request,
+                )
+                .await
+            })
+            .await?
+            .into_vectors();
+
+        if vectors.len() != inputs.len() {
+            return Err(AppError::Parse(format!(
+                "向量数量与输入不一致：{} != {}",
+                vectors.len(),
+                inputs.len()
+            )));
+        }
+
+        Ok(vectors)
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    // 不同维度的向量来自不同的模型，没有可比性
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+fn conversation_text(user: &str, assistant: &str) -> String {
+    format!("用户：{}\n\n助手：{}", user, assistant)
+        .chars()
+        .take(MAX_INPUT_CHARS)
+        .collect()
+}
+
+/// 为还没有向量的对话建立索引，同一时间只有一次索引在进行
+#[derive(Default)]
+pub struct EmbeddingIndexer {
+    running: Mutex<()>,
+}
+
+impl EmbeddingIndexer {
+    /// 按批次处理待索引的对话，返回新建立索引的数量
+    ///
+    /// `max_batches` 为空时处理全部。每批处理完释放锁，搜索不需要等待后台处理完所有对话。
+    pub async fn index_pending(
+        &self,
+        pool: &SQLitePool,
+        embedder: &Embedder<'_>,
+        batch_size: usize,
+        max_batches: Option<usize>,
+    ) -> Result<usize> {
+        let batch_size = batch_size.max(1);
+        let mut total = 0;
+
+        for _ in 0..max_batches.unwrap_or(usize::MAX) {
+            let _running = self.running.lock().await;
+
+            let pending = get_pending_conversations(&*pool.get()?, &embedder.model, batch_size)?;
+            if pending.is_empty() {
+                break;
+            }
+
+            let inputs: Vec<String> = pending
+                .iter()
+                .map(|p| conversation_text(&p.user, &p.assistant))
+                .collect();
+            let vectors = embedder.embed(&inputs).await?;
+
+            let conn = pool.get()?;
+            let created_at = now()?;
+            for (conversation, vector) in pending.iter().zip(&vectors) {
+                insert_embedding(
+                    &conn,
+                    conversation.user_message_id,
+                    &embedder.model,
+                    vector,
+                    created_at,
+                )?;
+            }
+
+            total += pending.len();
+            if pending.len() < batch_size {
+                break;
+            }
+        }
+
+        if total > 0 {
+            info!("已为 {} 组对话建立索引：model={}", total, embedder.model);
+        }
+
+        Ok(total)
+    }
+}
+
+async fn index_once(handle: &AppHandle) -> Result<usize> {
+    let config = EmbeddingConfig::load()?;
+    if !config.background {
+        return Ok(0);
+    }
+
+    let proxy_config = match read_config()? {
+        Some(c) => c.proxy,
+        None => return Ok(0),
+    };
+
+    let pool = handle.state::<SQLitePool>();
+    let clients = handle.state::<SharedClient>();
+    let keys = handle.state::<KeyStore>();
+    let indexer = handle.state::<EmbeddingIndexer>();
+
+    let client = clients.get(&proxy_config)?;
+    let embedder = Embedder::new(&client, &keys, &proxy_config, &config)?;
+    indexer
+        .index_pending(&pool, &embedder, config.batch_size, None)
+        .await
+}
+
+/// 后台定期建立索引，配置中关闭时只检查配置
+pub async fn run_background(handle: AppHandle) {
+    loop {
+        if let Err(e) = index_once(&handle).await {
+            warn!("后台建立索引失败：{}", e);
+        }
+
+        let interval = EmbeddingConfig::load()
+            .map(|c| c.interval_secs)
+            .unwrap_or_default()
+            .max(10);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// 语义搜索的结果
+#[derive(Debug, Serialize)]
+pub struct SemanticMatch {
+    pub score: f32,
+    pub topic_id: u32,
+    pub conversation: Conversation,
+}
+
+/// 按相似度从高到低返回最多 `top_k` 组对话，`topic_id` 为空时搜索所有主题
+pub async fn search(
+    pool: &SQLitePool,
+    embedder: &Embedder<'_>,
+    query: &str,
+    top_k: usize,
+    topic_id: Option<u32>,
+) -> Result<Vec<SemanticMatch>> {
+    let query_vector = embedder
+        .embed(&[query.to_string()])
+        .await?
+        .pop()
+        .unwrap_or_default();
+
+    let conn = pool.get()?;
+
+    let mut scored: Vec<_> = get_embeddings(&conn, &embedder.model, topic_id)?
+        .into_iter()
+        .map(|e| (cosine_similarity(&query_vector, &e.embedding), e))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(top_k);
+
+    let mut topics: HashMap<u32, Vec<Conversation>> = HashMap::new();
+    for (_, embedding) in scored.iter() {
+        if let Entry::Vacant(entry) = topics.entry(embedding.topic_id) {
+            entry.insert(get_messages(&conn, embedding.topic_id)?);
+        }
+    }
+
+    let mut matches = Vec::with_capacity(scored.len());
+    for (score, embedding) in scored {
+        let conversations = topics.get_mut(&embedding.topic_id).unwrap();
+        let index = conversations
+            .iter()
+            .position(|c| c.user.id() == embedding.user_message_id);
+
+        if let Some(index) = index {
+            matches.push(SemanticMatch {
+                score,
+                topic_id: embedding.topic_id,
+                conversation: conversations.swap_remove(index),
+            });
+        }
+    }
+
+    debug!("语义搜索：query={} matches={}", query, matches.len());
+
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_similarity_values() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn cosine_similarity_incomparable() {
+        assert_eq!(cosine_similarity(&[1.0, 2.0], &[1.0, 2.0, 3.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+        assert_eq!(cosine_similarity(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn conversation_text_truncates() {
+        let text = conversation_text(&"问".repeat(MAX_INPUT_CHARS), "答");
+        assert_eq!(text.chars().count(), MAX_INPUT_CHARS);
+        assert!(text.starts_with("用户："));
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::{
+    error::{AppError, Result},
+    time::now,
+};
+
+/// 已结束的流式响应保留的时间（秒），用于查询状态
+const FINISHED_RETENTION: u64 = 10 * 60;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamStatus {
+    Connecting,
+    Streaming,
+    Done,
+    Aborted,
+    Timeout,
+    Error,
+}
+
+impl StreamStatus {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            StreamStatus::Done
+                | StreamStatus::Aborted
+                | StreamStatus::Timeout
+                | StreamStatus::Error
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamInfo {
+    pub id: String,
+    pub topic_id: u32,
+    pub status: StreamStatus,
+    pub started_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+/// 用于中断某个流式响应
+#[derive(Debug, Default)]
+pub struct StreamHandle {
+    aborted: AtomicBool,
+    notify: Notify,
+}
+
+impl StreamHandle {
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::Relaxed);
+        // notify_one 会保留通知，中断发生在等待之前也不会丢失
+        self.notify.notify_one();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// 等待中断
+    pub async fn aborted(&self) {
+        if self.is_aborted() {
+            return;
+        }
+
+        self.notify.notified().await
+    }
+}
+
+struct StreamEntry {
+    info: StreamInfo,
+    handle: Arc<StreamHandle>,
+}
+
+/// 正在进行的流式响应，每个流以前端生成的 id 区分，
+/// chunk 通过 `stream://{id}` 事件发送。
+#[derive(Default)]
+pub struct StreamRegistry {
+    streams: Mutex<HashMap<String, StreamEntry>>,
+}
+
+pub fn event_name(id: &str) -> String {
+    format!("stream://{}", id)
+}
+
+/// tauri 的事件名只能包含字母、数字、`-`、`/`、`:` 和 `_`
+fn validate_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '/' | ':' | '_'));
+
+    if !valid {
+        return Err(AppError::Other(format!("流式响应 id 无效：{}", id)));
+    }
+
+    Ok(())
+}
+
+impl StreamRegistry {
+    pub fn register(&self, id: &str, topic_id: u32) -> Result<Arc<StreamHandle>> {
+        validate_id(id)?;
+
+        let now = now()?;
+
+        let mut streams = self.streams.lock().unwrap();
+
+        streams.retain(|_, s| match s.info.finished_at {
+            Some(t) => now.saturating_sub(t) < FINISHED_RETENTION,
+            None => true,
+        });
+
+        if streams
+            .get(id)
+            .is_some_and(|s| !s.info.status.is_finished())
+        {
+            return Err(AppError::Other(format!("流式响应已存在：{}", id)));
+        }
+
+        let handle = Arc::new(StreamHandle::default());
+
+        streams.insert(
+            id.to_string(),
+            StreamEntry {
+                info: StreamInfo {
+                    id: id.to_string(),
+                    topic_id,
+                    status: StreamStatus::Connecting,
+                    started_at: now,
+                    finished_at: None,
+                },
+                handle: Arc::clone(&handle),
+            },
+        );
+
+        debug!("注册流式响应：id={}, topic_id={}", id, topic_id);
+
+        Ok(handle)
+    }
+
+    pub fn set_status(&self, id: &str, status: StreamStatus) {
+        let mut streams = self.streams.lock().unwrap();
+
+        if let Some(stream) = streams.get_mut(id) {
+            stream.info.status = status;
+
+            if status.is_finished() {
+                stream.info.finished_at = now().ok();
+            }
+        }
+
+        trace!("流式响应状态：id={}, status={:?}", id, status);
+    }
+
+    /// 中断流式响应，流不存在或已结束时返回 false
+    pub fn cancel(&self, id: &str) -> bool {
+        let streams = self.streams.lock().unwrap();
+
+        match streams.get(id) {
+            Some(stream) if !stream.info.status.is_finished() => {
+                info!("中断流式消息：{}", id);
+                stream.handle.abort();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<StreamInfo> {
+        let streams = self.streams.lock().unwrap();
+
+        let mut list: Vec<StreamInfo> = streams.values().map(|s| s.info.clone()).collect();
+        list.sort_by_key(|s| s.started_at);
+
+        list
+    }
+
+    pub fn get(&self, id: &str) -> Option<StreamInfo> {
+        let streams = self.streams.lock().unwrap();
+
+        streams.get(id).map(|s| s.info.clone())
+    }
+}
+
+/// 一次流式响应，负责发送事件和更新状态
+pub struct ChatStream<'a> {
+    window: &'a tauri::Window,
+    registry: &'a StreamRegistry,
+    id: String,
+    handle: Arc<StreamHandle>,
+}
+
+impl<'a> ChatStream<'a> {
+    pub fn new(
+        window: &'a tauri::Window,
+        registry: &'a StreamRegistry,
+        id: &str,
+        topic_id: u32,
+    ) -> Result<Self> {
+        let handle = registry.register(id, topic_id)?;
+
+        Ok(ChatStream {
+            window,
+            registry,
+            id: id.to_string(),
+            handle,
+        })
+    }
+
+    pub fn emit<S: Serialize + Clone>(&self, payload: S) -> Result<()> {
+        self.window.emit(&event_name(&self.id), payload)?;
+
+        Ok(())
+    }
+
+    pub fn set_status(&self, status: StreamStatus) {
+        self.registry.set_status(&self.id, status);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.handle.is_aborted()
+    }
+
+    pub async fn aborted(&self) {
+        self.handle.aborted().await
+    }
+
+    /// 根据结果设置最终状态
+    pub fn finish<T>(&self, result: &Result<T>) {
+        let status = match result {
+            Err(AppError::Timeout(_)) => StreamStatus::Timeout,
+            Err(_) => StreamStatus::Error,
+            Ok(_) if self.is_aborted() => StreamStatus::Aborted,
+            Ok(_) => StreamStatus::Done,
+        };
+
+        self.set_status(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_validates_id() {
+        let registry = StreamRegistry::default();
+
+        assert!(registry.register("3f2a-9c1b_0", 1).is_ok());
+        assert!(registry.register("", 1).is_err());
+        assert!(registry.register("a b", 1).is_err());
+        assert!(registry.register("a.b", 1).is_err());
+        assert!(registry.register("流", 1).is_err());
+    }
+
+    #[test]
+    fn register_rejects_running_stream() {
+        let registry = StreamRegistry::default();
+
+        assert!(registry.register("a", 1).is_ok());
+        assert!(registry.register("a", 1).is_err());
+
+        registry.set_status("a", StreamStatus::Done);
+        assert!(registry.register("a", 1).is_ok());
+    }
+}
@@ -0,0 +1,238 @@
+use lazy_static::lazy_static;
+use tiktoken_rs::{
+    cl100k_base, model::get_context_size, o200k_base, p50k_base, tokenizer::get_tokenizer,
+    tokenizer::Tokenizer, CoreBPE,
+};
+
+use crate::{
+    api::chat::{ChatGPTRequest, Message},
+    db::message::Conversation,
+};
+
+// 词表随 tiktoken-rs 一起编译进程序，离线可用
+lazy_static! {
+    static ref CL100K_BASE: CoreBPE = cl100k_base().unwrap();
+    static ref O200K_BASE: CoreBPE = o200k_base().unwrap();
+    static ref P50K_BASE: CoreBPE = p50k_base().unwrap();
+}
+
+/// 未设置 `max_tokens` 时为回复预留的 token 数
+const DEFAULT_COMPLETION_RESERVE: usize = 1024;
+
+/// 每条消息的格式开销：<|start|>{role}\n{content}<|end|>\n
+const TOKENS_PER_MESSAGE: usize = 3;
+/// 每个回复以 <|start|>assistant<|message|> 开头
+const TOKENS_PER_REPLY: usize = 3;
+/// 每张图片的估算值，按 high detail 下 1024x1024 的图片（4 块）计算
+const TOKENS_PER_IMAGE: usize = 765;
+
+/// 按模型选择编码，无法识别的模型（如兼容接口的本地模型）使用 cl100k_base
+fn bpe(model: &str) -> &'static CoreBPE {
+    match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => &O200K_BASE,
+        Some(Tokenizer::P50kBase | Tokenizer::P50kEdit | Tokenizer::R50kBase | Tokenizer::Gpt2) => {
+            &P50K_BASE
+        }
+        _ => &CL100K_BASE,
+    }
+}
+
+pub fn count_text(model: &str, text: &str) -> usize {
+    bpe(model).encode_with_special_tokens(text).len()
+}
+
+fn count_message(model: &str, message: &Message) -> usize {
+    // 工具调用按函数名和参数估算
+    let calls = message
+        .calls()
+        .iter()
+        .map(|c| count_text(model, &c.function.name) + count_text(model, &c.function.arguments))
+        .sum::<usize>();
+
+    TOKENS_PER_MESSAGE
+        + count_text(model, &message.role)
+        + count_text(model, &message.content.text())
+        + message.content.images().len() * TOKENS_PER_IMAGE
+        + calls
+}
+
+pub fn count_messages(model: &str, messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|m| count_message(model, m))
+        .sum::<usize>()
+        + TOKENS_PER_REPLY
+}
+
+/// 计算请求的 prompt token 数
+pub fn count_request(request: &ChatGPTRequest) -> usize {
+    count_messages(&request.model, &request.messages)
+}
+
+/// 模型可用于 prompt 的 token 数
+pub fn prompt_budget(model: &str, max_tokens: Option<u64>) -> usize {
+    let reserve = max_tokens
+        .map(|t| t as usize)
+        .unwrap_or(DEFAULT_COMPLETION_RESERVE);
+
+    get_context_size(model).saturating_sub(reserve)
+}
+
+fn count_conversation(model: &str, conversation: &Conversation) -> usize {
+    conversation
+        .messages()
+        .iter()
+        .map(|m| count_message(model, m))
+        .sum()
+}
+
+/// 从最早的对话开始删除，直到 prompt 不超过预算
+///
+/// `fixed` 为必须保留的消息（系统消息、提示词和新的用户消息），
+/// `keep_first` 为真时始终保留第一组对话。
+pub fn trim_conversations(
+    model: &str,
+    budget: usize,
+    fixed: &[Message],
+    conversations: &mut Vec<&Conversation>,
+    keep_first: bool,
+) {
+    let mut total = count_messages(model, fixed)
+        + conversations
+            .iter()
+            .map(|c| count_conversation(model, c))
+            .sum::<usize>();
+
+    let first = if keep_first { 1 } else { 0 };
+
+    while total > budget && conversations.len() > first {
+        let removed = conversations.remove(first);
+        total -= count_conversation(model, removed);
+    }
+
+    if total > budget {
+        warn!("删除历史对话后 prompt 仍超出预算：{} > {}", total, budget);
+    } else {
+        trace!("prompt token 数：{}，预算：{}", total, budget);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::message::{AssistantMessage, MessageStatus, UserMessage};
+
+    const MODEL: &str = "gpt-3.5-turbo";
+
+    fn conversation(question: &str, answer: &str) -> Conversation {
+        Conversation {
+            user: UserMessage::new(question, 0, 3),
+            attachments: Vec::new(),
+            moderation: None,
+            tools: Vec::new(),
+            assistant: AssistantMessage::new(
+                answer.to_string(),
+                0,
+                0,
+                MessageStatus::Complete,
+                None,
+            ),
+        }
+    }
+
+    fn total(fixed: &[Message], conversations: &[&Conversation]) -> usize {
+        count_messages(MODEL, fixed)
+            + conversations
+                .iter()
+                .map(|c| count_conversation(MODEL, c))
+                .sum::<usize>()
+    }
+
+    #[test]
+    fn trim_removes_oldest_when_over_budget() {
+        let history = [
+            conversation("第一个问题", "第一个回答"),
+            conversation("第二个问题", "第二个回答"),
+            conversation("第三个问题", "第三个回答"),
+        ];
+        let fixed = [Message::new("user", "新问题")];
+
+        let mut conversations: Vec<&Conversation> = history.iter().collect();
+        let budget = total(&fixed, &conversations) - 1;
+
+        trim_conversations(MODEL, budget, &fixed, &mut conversations, false);
+
+        assert_eq!(conversations.len(), 2);
+        assert_eq!(conversations[0].user.message, "第二个问题");
+        assert!(total(&fixed, &conversations) <= budget);
+    }
+
+    #[test]
+    fn trim_keeps_first_when_over_budget() {
+        let history = [
+            conversation("第一个问题", "第一个回答"),
+            conversation("第二个问题", "第二个回答"),
+            conversation("第三个问题", "第三个回答"),
+        ];
+        let fixed = [Message::new("user", "新问题")];
+
+        let mut conversations: Vec<&Conversation> = history.iter().collect();
+        let budget = total(&fixed, &conversations) - 1;
+
+        trim_conversations(MODEL, budget, &fixed, &mut conversations, true);
+
+        assert_eq!(conversations.len(), 2);
+        assert_eq!(conversations[0].user.message, "第一个问题");
+        assert_eq!(conversations[1].user.message, "第三个问题");
+    }
+
+    #[test]
+    fn trim_keeps_all_at_budget() {
+        let history = [
+            conversation("第一个问题", "第一个回答"),
+            conversation("第二个问题", "第二个回答"),
+        ];
+        let fixed = [
+            Message::new("system", "你是翻译"),
+            Message::new("user", "新问题"),
+        ];
+
+        let mut conversations: Vec<&Conversation> = history.iter().collect();
+        let budget = total(&fixed, &conversations);
+
+        trim_conversations(MODEL, budget, &fixed, &mut conversations, false);
+
+        assert_eq!(conversations.len(), 2);
+    }
+
+    #[test]
+    fn trim_single_message_larger_than_budget() {
+        let history = [conversation("问题", "回答")];
+        let fixed = [Message::new("user", &"很长的问题".repeat(100))];
+
+        let mut conversations: Vec<&Conversation> = history.iter().collect();
+        let budget = count_messages(MODEL, &fixed) / 2;
+
+        // 新的用户消息不能删除，删除所有对话后仍超出预算
+        trim_conversations(MODEL, budget, &fixed, &mut conversations, false);
+
+        assert!(conversations.is_empty());
+    }
+
+    #[test]
+    fn trim_keeps_first_even_if_over_budget() {
+        let history = [
+            conversation(&"很长的问题".repeat(100), "回答"),
+            conversation("第二个问题", "第二个回答"),
+        ];
+        let fixed = [Message::new("user", "新问题")];
+
+        let mut conversations: Vec<&Conversation> = history.iter().collect();
+        let budget = count_messages(MODEL, &fixed);
+
+        trim_conversations(MODEL, budget, &fixed, &mut conversations, true);
+
+        assert_eq!(conversations.len(), 1);
+        assert!(total(&fixed, &conversations) > budget);
+    }
+}
@@ -0,0 +1,238 @@
+use serde_json::{json, Value};
+
+use super::{string_arg, Tool, ToolContext};
+use crate::error::{AppError, Result};
+
+/// 四则运算，支持 `+ - * / % ^`、括号和负号
+pub struct Calculator;
+
+/// 括号、负号和乘方的最大嵌套层数，避免过深的递归导致栈溢出
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    depth: usize,
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::Parse(format!("无效的表达式：{}", message))
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &'a str) -> Self {
+        Parser {
+            chars: expression.chars().peekable(),
+            depth: 0,
+        }
+    }
+
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<f64>) -> Result<f64> {
+        if self.depth >= MAX_DEPTH {
+            return Err(invalid("嵌套层数过多"));
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// expr = term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// term = unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.unary()?;
+
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err(invalid("除数为 0"));
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err(invalid("除数为 0"));
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// unary = ('-' | '+') unary | power，负号的优先级低于乘方，`-2 ^ 2` 为 -4
+    fn unary(&mut self) -> Result<f64> {
+        if self.eat('-') {
+            return Ok(-self.nested(Self::unary)?);
+        }
+
+        if self.eat('+') {
+            return self.nested(Self::unary);
+        }
+
+        self.power()
+    }
+
+    /// power = primary ('^' unary)?，乘方为右结合，指数可以带负号
+    fn power(&mut self) -> Result<f64> {
+        let base = self.primary()?;
+
+        if self.eat('^') {
+            return Ok(base.powf(self.nested(Self::unary)?));
+        }
+
+        Ok(base)
+    }
+
+    /// primary = '(' expr ')' | number
+    fn primary(&mut self) -> Result<f64> {
+        if self.eat('(') {
+            let value = self.nested(Self::expr)?;
+            if !self.eat(')') {
+                return Err(invalid("缺少右括号"));
+            }
+            return Ok(value);
+        }
+
+        self.number()
+    }
+
+    fn number(&mut self) -> Result<f64> {
+        self.peek();
+
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+            number.push(c);
+        }
+
+        if number.is_empty() {
+            return Err(match self.chars.peek() {
+                Some(c) => invalid(&format!("意外的字符 {}", c)),
+                None => invalid("表达式不完整"),
+            });
+        }
+
+        number
+            .parse()
+            .map_err(|_| invalid(&format!("无效的数字 {}", number)))
+    }
+}
+
+fn evaluate(expression: &str) -> Result<f64> {
+    let mut parser = Parser::new(expression);
+    let value = parser.expr()?;
+
+    if let Some(c) = parser.peek() {
+        return Err(invalid(&format!("意外的字符 {}", c)));
+    }
+
+    if !value.is_finite() {
+        return Err(invalid("结果溢出"));
+    }
+
+    Ok(value)
+}
+
+impl Tool for Calculator {
+    fn name(&self) -> &'static str {
+        "calculator"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluate an arithmetic expression. Supports + - * / % ^ and parentheses."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "The expression to evaluate, e.g. (1 + 2) * 3 ^ 2"
+                }
+            },
+            "required": ["expression"]
+        })
+    }
+
+    fn call(&self, _ctx: &ToolContext, arguments: Value) -> Result<String> {
+        let expression = string_arg(&arguments, "expression")?;
+
+        let value = evaluate(expression)?;
+
+        Ok(json!({ "expression": expression, "result": value }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3").unwrap(), 3.0);
+        assert_eq!(evaluate("7 % 4 * 2").unwrap(), 6.0);
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(evaluate("-2 ^ 2").unwrap(), -4.0);
+        assert_eq!(evaluate("(-2) ^ 2").unwrap(), 4.0);
+        assert_eq!(evaluate("2 ^ -1").unwrap(), 0.5);
+        assert_eq!(evaluate("2 * -3").unwrap(), -6.0);
+        assert_eq!(evaluate("- -1.5 + +2").unwrap(), 3.5);
+    }
+
+    #[test]
+    fn evaluate_invalid() {
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("1 % 0").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("x").is_err());
+        assert!(evaluate("10 ^ 1000").is_err());
+    }
+
+    #[test]
+    fn evaluate_limits_nesting() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert_eq!(evaluate(&nested(MAX_DEPTH)).unwrap(), 1.0);
+        assert!(evaluate(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(evaluate(&nested(100_000)).is_err());
+        assert!(evaluate(&format!("{}1", "-".repeat(100_000))).is_err());
+        assert!(evaluate(&"2 ^ ".repeat(100_000)).is_err());
+    }
+}
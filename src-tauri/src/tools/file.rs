@@ -0,0 +1,109 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use super::{string_arg, Tool, ToolContext};
+use crate::{
+    config::ToolsConfig,
+    error::{AppError, Result},
+};
+
+/// 读取用户允许的目录中的文本文件
+pub struct ReadFile;
+
+/// 解析符号链接和 `..` 后判断文件是否在允许的目录中
+fn resolve(allowed_dirs: &[String], path: &str) -> Result<PathBuf> {
+    let path = Path::new(path).canonicalize()?;
+
+    let allowed = allowed_dirs
+        .iter()
+        .filter_map(|dir| Path::new(dir).canonicalize().ok())
+        .any(|dir| path.starts_with(dir));
+
+    if !allowed {
+        return Err(AppError::Other(format!(
+            "不允许读取此文件：{}",
+            path.display()
+        )));
+    }
+
+    if !path.is_file() {
+        return Err(AppError::Other(format!("不是文件：{}", path.display())));
+    }
+
+    Ok(path)
+}
+
+impl Tool for ReadFile {
+    fn name(&self) -> &'static str {
+        "read_file"
+    }
+
+    fn description(&self) -> &'static str {
+        "Read a text file from one of the directories the user has approved. \
+         Large files are truncated; use offset to read further."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Absolute path of the file"
+                },
+                "offset": {
+                    "type": "integer",
+                    "description": "Byte offset to start reading from, default 0"
+                }
+            },
+            "required": ["path"]
+        })
+    }
+
+    fn call(&self, ctx: &ToolContext, arguments: Value) -> Result<String> {
+        let default = ToolsConfig::default();
+        let tools = ctx
+            .config
+            .and_then(|c| c.tools.as_ref())
+            .unwrap_or(&default);
+
+        if tools.allowed_dirs.is_empty() {
+            return Err(AppError::Config("未配置允许读取的目录".to_string()));
+        }
+
+        let path = resolve(&tools.allowed_dirs, string_arg(&arguments, "path")?)?;
+        let offset = arguments.get("offset").and_then(Value::as_u64).unwrap_or(0);
+
+        let mut file = File::open(&path)?;
+        let size = file.metadata()?.len();
+
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut buf = Vec::new();
+        file.take(tools.max_file_bytes).read_to_end(&mut buf)?;
+
+        let end = offset + buf.len() as u64;
+
+        debug!(
+            "读取文件：{}，{}..{} / {}",
+            path.display(),
+            offset,
+            end,
+            size
+        );
+
+        Ok(json!({
+            "path": path.display().to_string(),
+            "size": size,
+            "offset": offset,
+            "truncated": end < size,
+            "content": String::from_utf8_lossy(&buf),
+        })
+        .to_string())
+    }
+}
@@ -0,0 +1,84 @@
+use serde_json::{json, Value};
+
+use super::{string_arg, Tool, ToolContext};
+use crate::{db::message::search_messages, error::Result};
+
+/// 返回给模型的每条消息最多保留的字符数
+const MAX_SNIPPET_CHARS: usize = 500;
+
+const DEFAULT_LIMIT: u64 = 5;
+const MAX_LIMIT: u64 = 20;
+
+/// 搜索本应用保存的历史对话
+pub struct SearchHistory;
+
+fn snippet(text: &str) -> String {
+    match text.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+impl Tool for SearchHistory {
+    fn name(&self) -> &'static str {
+        "search_history"
+    }
+
+    fn description(&self) -> &'static str {
+        "Search earlier conversations saved in this app by keyword. \
+         Returns the most recent matching question and answer pairs."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Keyword to search for"
+                },
+                "current_topic_only": {
+                    "type": "boolean",
+                    "description": "Only search the current topic, default false"
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of results, default 5"
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    fn call(&self, ctx: &ToolContext, arguments: Value) -> Result<String> {
+        let query = string_arg(&arguments, "query")?;
+
+        let topic_id = arguments
+            .get("current_topic_only")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+            .then_some(ctx.topic_id);
+
+        let limit = arguments
+            .get("limit")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_LIMIT)
+            .min(MAX_LIMIT);
+
+        let matches = search_messages(ctx.conn, query, topic_id, limit as u32)?;
+
+        let results: Vec<Value> = matches
+            .iter()
+            .map(|m| {
+                json!({
+                    "topic_id": m.topic_id,
+                    "created_at": m.created_at,
+                    "user": snippet(&m.user),
+                    "assistant": snippet(&m.assistant),
+                })
+            })
+            .collect();
+
+        Ok(json!({ "results": results }).to_string())
+    }
+}
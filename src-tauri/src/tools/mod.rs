@@ -0,0 +1,334 @@
+use std::sync::Arc;
+
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+use crate::{
+    api::chat::{ChatGPTRequest, FunctionDefinition, Message, Tool as ToolDefinition, ToolCall},
+    config::{self, Config, ToolsConfig},
+    db::message::ToolMessage,
+    error::{AppError, Result},
+    mcp::{self, McpManager, McpServer, McpTool},
+    time::now,
+    SQLitePool,
+};
+
+mod calculator;
+mod file;
+mod history;
+mod time;
+
+/// 执行工具时可用的上下文
+pub struct ToolContext<'a> {
+    pub conn: &'a Connection,
+    pub config: Option<&'a Config>,
+    pub topic_id: u32,
+}
+
+/// 模型可以调用的本地工具
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// JSON Schema 描述的参数
+    fn parameters(&self) -> Value;
+
+    /// 执行工具，返回发送给模型的结果
+    fn call(&self, ctx: &ToolContext, arguments: Value) -> Result<String>;
+}
+
+/// 已注册的工具，主题只能使用 `TopicConfig.tools` 中列出的工具
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        let mut registry = ToolRegistry { tools: Vec::new() };
+
+        registry.register(Box::new(calculator::Calculator));
+        registry.register(Box::new(time::CurrentTime));
+        registry.register(Box::new(file::ReadFile));
+        registry.register(Box::new(history::SearchHistory));
+
+        registry
+    }
+}
+
+/// 主题可以调用的工具名
+fn topic_tools(config: Option<&Config>, topic_id: u32) -> Vec<String> {
+    config
+        .and_then(|c| c.topics.as_ref())
+        .and_then(|t| t.get(&topic_id.to_string()))
+        .and_then(|t| t.tools.clone())
+        .unwrap_or_default()
+}
+
+impl ToolRegistry {
+    pub fn register(&mut self, tool: Box<dyn Tool>) {
+        self.tools.push(tool);
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn Tool> {
+        self.tools
+            .iter()
+            .find(|t| t.name() == name)
+            .map(|t| t.as_ref())
+    }
+
+    /// `names` 中已注册的工具的定义，未注册的工具名会被忽略
+    fn definitions(&self, names: &[String]) -> Vec<ToolDefinition> {
+        names
+            .iter()
+            .filter_map(|name| match self.get(name) {
+                Some(tool) => Some(ToolDefinition {
+                    kind: "function".to_string(),
+                    function: FunctionDefinition {
+                        name: tool.name().to_string(),
+                        description: Some(tool.description().to_string()),
+                        parameters: Some(tool.parameters()),
+                    },
+                }),
+                None => {
+                    warn!("未知的工具：{}", name);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// 工具是否已注册并在主题中启用
+    fn can_execute(&self, names: &[String], name: &str) -> bool {
+        names.iter().any(|n| n == name) && self.get(name).is_some()
+    }
+
+    /// 执行一次调用，出错时把错误信息作为结果返回给模型
+    fn execute(&self, ctx: &ToolContext, call: &ToolCall) -> String {
+        let name = &call.function.name;
+
+        debug!("调用工具：{}，参数：{}", name, call.function.arguments);
+
+        let result = self
+            .get(name)
+            .ok_or_else(|| AppError::Other(format!("未知的工具：{}", name)))
+            .and_then(|tool| {
+                let arguments = parse_arguments(&call.function.arguments)?;
+
+                tool.call(ctx, arguments)
+            });
+
+        match result {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("工具 {} 执行失败：{}", name, e);
+                json!({ "error": e.to_string() }).to_string()
+            }
+        }
+    }
+}
+
+/// 没有参数的函数可能返回空字符串
+fn parse_arguments(arguments: &str) -> Result<Value> {
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+
+    Ok(serde_json::from_str(arguments)?)
+}
+
+/// 读取字符串参数
+fn string_arg<'a>(arguments: &'a Value, name: &str) -> Result<&'a str> {
+    arguments
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| AppError::Parse(format!("缺少参数：{}", name)))
+}
+
+/// 路由到 MCP 服务的工具
+struct McpRoute {
+    /// 请求中的函数名
+    function: String,
+    server: Arc<McpServer>,
+    tool: McpTool,
+}
+
+/// 一次回复中自动执行工具的过程
+///
+/// 模型请求调用的工具都已在主题中启用（本地工具或 MCP 服务的工具）时执行这些工具，
+/// 把调用过程保存到数据库，并将执行结果添加到请求中再次发送，
+/// 直到模型给出最终回复或达到 `max_rounds`。
+pub struct ToolSession<'a> {
+    registry: &'a ToolRegistry,
+    config: Option<Config>,
+    topic_id: u32,
+    names: Vec<String>,
+    mcp: Vec<McpRoute>,
+    rounds: u32,
+}
+
+impl<'a> ToolSession<'a> {
+    /// 读取主题启用的工具，按需启动主题使用的 MCP 服务
+    pub async fn new(
+        registry: &'a ToolRegistry,
+        mcp: &McpManager,
+        topic_id: u32,
+    ) -> Result<ToolSession<'a>> {
+        let config = config::read_config()?;
+        let names = topic_tools(config.as_ref(), topic_id);
+
+        let routes = mcp
+            .topic_servers(config.as_ref(), topic_id)
+            .await
+            .into_iter()
+            .flat_map(|server| {
+                server
+                    .tools()
+                    .iter()
+                    .map(|tool| McpRoute {
+                        function: mcp::function_name(server.name(), &tool.name),
+                        server: Arc::clone(&server),
+                        tool: tool.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        Ok(ToolSession {
+            registry,
+            config,
+            topic_id,
+            names,
+            mcp: routes,
+            rounds: 0,
+        })
+    }
+
+    fn route(&self, function: &str) -> Option<&McpRoute> {
+        self.mcp.iter().find(|r| r.function == function)
+    }
+
+    /// 请求中没有工具定义时添加主题启用的工具
+    pub fn prepare(&self, request: &mut ChatGPTRequest) {
+        if request.tools.is_some() {
+            return;
+        }
+
+        let mut definitions = self.registry.definitions(&self.names);
+        definitions.extend(
+            self.mcp
+                .iter()
+                .map(|r| mcp::tool_definition(r.server.name(), &r.tool)),
+        );
+
+        if !definitions.is_empty() {
+            request.tools = Some(definitions);
+        }
+    }
+
+    fn max_rounds(&self) -> u32 {
+        self.config
+            .as_ref()
+            .and_then(|c| c.tools.as_ref())
+            .map_or(ToolsConfig::default().max_rounds, |t| t.max_rounds)
+    }
+
+    /// 是否自动执行回复中的调用，模型调用了未启用的工具时交由用户处理
+    pub fn should_run(&self, calls: &[ToolCall]) -> bool {
+        let executable = !calls.is_empty()
+            && calls.iter().all(|c| {
+                self.registry.can_execute(&self.names, &c.function.name)
+                    || self.route(&c.function.name).is_some()
+            });
+
+        if !executable {
+            return false;
+        }
+
+        if self.rounds >= self.max_rounds() {
+            warn!("调用工具的轮数已达上限：{}", self.rounds);
+            return false;
+        }
+
+        true
+    }
+
+    async fn execute(&self, pool: &SQLitePool, call: &ToolCall) -> Result<String> {
+        let route = match self.route(&call.function.name) {
+            Some(r) => r,
+            None => {
+                let conn = pool.get()?;
+                let ctx = ToolContext {
+                    conn: &conn,
+                    config: self.config.as_ref(),
+                    topic_id: self.topic_id,
+                };
+
+                return Ok(self.registry.execute(&ctx, call));
+            }
+        };
+
+        debug!(
+            "调用 MCP 工具：{} {}，参数：{}",
+            route.server.name(),
+            route.tool.name,
+            call.function.arguments
+        );
+
+        let result = match parse_arguments(&call.function.arguments) {
+            Ok(arguments) => route.server.call_tool(&route.tool.name, arguments).await,
+            Err(e) => Err(e),
+        };
+
+        Ok(result.unwrap_or_else(|e| {
+            warn!("MCP 工具 {} 执行失败：{}", call.function.name, e);
+            json!({ "error": e.to_string() }).to_string()
+        }))
+    }
+
+    /// 执行调用，返回工具的执行结果
+    ///
+    /// 请求调用工具的回复和执行结果都保存为 `user_message_id` 的工具消息，并添加到请求中。
+    pub async fn run(
+        &mut self,
+        pool: &SQLitePool,
+        user_message_id: u32,
+        request: &mut ChatGPTRequest,
+        content: &str,
+        calls: Vec<ToolCall>,
+    ) -> Result<Vec<Message>> {
+        self.rounds += 1;
+
+        // 旧版 functions 接口的调用没有 id，结果以 function 消息返回
+        let legacy = calls.iter().all(|c| c.id.is_empty());
+
+        let mut assistant = Message::new("assistant", content);
+        if legacy {
+            assistant.function_call = calls.first().map(|c| c.function.clone());
+        } else {
+            assistant.tool_calls = Some(calls.clone());
+        }
+
+        ToolMessage::new(user_message_id, &assistant, now()?).insert(&*pool.get()?)?;
+        request.messages.push(assistant);
+
+        let mut results = Vec::new();
+
+        for call in &calls {
+            let output = self.execute(pool, call).await?;
+
+            let mut result = Message::new(if legacy { "function" } else { "tool" }, &output);
+            result.name = Some(call.function.name.clone());
+            if !legacy {
+                result.tool_call_id = Some(call.id.clone());
+            }
+
+            ToolMessage::new(user_message_id, &result, now()?).insert(&*pool.get()?)?;
+            request.messages.push(result.clone());
+
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+}
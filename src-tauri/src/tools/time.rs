@@ -0,0 +1,44 @@
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::{Tool, ToolContext};
+use crate::error::{AppError, Result};
+
+/// 当前的本地时间
+pub struct CurrentTime;
+
+impl Tool for CurrentTime {
+    fn name(&self) -> &'static str {
+        "current_time"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the current date and time in the user's local time zone."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn call(&self, _ctx: &ToolContext, _arguments: Value) -> Result<String> {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|e| {
+            error!("获取本地时区失败：{}", e);
+            OffsetDateTime::now_utc()
+        });
+
+        let formatted = now
+            .format(&Rfc3339)
+            .map_err(|e| AppError::Other(format!("格式化时间失败：{}", e)))?;
+
+        Ok(json!({
+            "datetime": formatted,
+            "weekday": now.weekday().to_string(),
+            "utc_offset": now.offset().to_string(),
+            "timestamp": now.unix_timestamp(),
+        })
+        .to_string())
+    }
+}
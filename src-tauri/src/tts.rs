@@ -0,0 +1,176 @@
+//! 朗读回复
+//!
+//! 回复按句子分段合成后拼接，缓存在应用目录下的 `speech` 中，文件名以回复的 id 开头。
+
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    api::{
+        client::HttpClient,
+        provider::Provider,
+        speech::{speech_client, split_chunks, SpeechOptions, SpeechRequest, MAX_CHUNK_CHARS},
+    },
+    config::{ProxyConfig, APP_CONFIG_DIR},
+    db::message::{AssistantMessage, Conversation, MessageStatus},
+    error::{AppError, Result},
+    keys::{ApiKey, KeyStore},
+};
+
+lazy_static! {
+    static ref SPEECH_DIR: PathBuf = APP_CONFIG_DIR.join("speech");
+}
+
+/// 合成语音需要的客户端、提供商和 key
+pub struct Speaker<'a> {
+    pub client: &'a HttpClient,
+    pub provider: &'a dyn Provider,
+    pub proxy_config: &'a ProxyConfig,
+    pub keys: &'a KeyStore,
+    pub candidates: Vec<ApiKey>,
+    pub options: &'a SpeechOptions,
+}
+
+/// 模型名和声音可能包含文件名中不允许的字符
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn cache_path(message_id: u32, options: &SpeechOptions) -> PathBuf {
+    let speed = options.speed.map(|s| format!("-{}", s)).unwrap_or_default();
+
+    SPEECH_DIR.join(format!(
+        "{}-{}-{}{}.{}",
+        message_id,
+        sanitize(&options.model),
+        sanitize(&options.voice),
+        speed,
+        options.response_format.extension()
+    ))
+}
+
+/// 删除回复后删除它们的语音缓存，每条回复可能有不同模型、声音和语速的多个文件
+pub fn remove_cache(message_ids: &[u32]) {
+    if message_ids.is_empty() {
+        return;
+    }
+
+    let entries = match std::fs::read_dir(&*SPEECH_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("读取语音缓存目录时出错：{}", e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let id = name
+            .to_str()
+            .and_then(|n| n.split_once('-'))
+            .and_then(|(id, _)| id.parse::<u32>().ok());
+        if !id.is_some_and(|id| message_ids.contains(&id)) {
+            continue;
+        }
+
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => debug!("已删除语音缓存：{}", entry.path().display()),
+            Err(e) => warn!("删除语音缓存 {} 时出错：{}", entry.path().display(), e),
+        }
+    }
+}
+
+impl Speaker<'_> {
+    async fn synthesize(&self, input: &str) -> Result<Vec<u8>> {
+        let request = SpeechRequest::new(self.options, input);
+        let request = &request;
+
+        self.keys
+            .run(self.candidates.clone(), |key| async move {
+                speech_client(
+                    self.client,
+                    self.provider,
+                    self.proxy_config,
+                    &key.key,
+                    request,
+                )
+                .await
+            })
+            .await
+    }
+
+    /// 回复的语音文件，没有缓存时分段合成
+    pub async fn message_audio(&self, message: &AssistantMessage) -> Result<PathBuf> {
+        let path = cache_path(message.id(), self.options);
+        if path.exists() {
+            debug!("使用缓存的语音：{}", path.display());
+            return Ok(path);
+        }
+
+        let chunks = split_chunks(&message.message, MAX_CHUNK_CHARS);
+        if chunks.is_empty() {
+            return Err(AppError::Other("回复中没有可朗读的文本".to_string()));
+        }
+        if chunks.len() > 1 && !self.options.response_format.concatenable() {
+            return Err(AppError::Other(format!(
+                "回复较长需要分段合成，{} 格式无法拼接，请使用 mp3、opus、aac 或 pcm",
+                self.options.response_format.extension()
+            )));
+        }
+
+        let mut audio = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            debug!("合成第 {}/{} 段语音", i + 1, chunks.len());
+            audio.extend(self.synthesize(chunk).await?);
+        }
+
+        // 全部合成后再写入，避免中途失败留下不完整的缓存
+        tokio::fs::create_dir_all(&*SPEECH_DIR).await?;
+        tokio::fs::write(&path, audio).await?;
+
+        Ok(path)
+    }
+
+    /// 将主题中所有回复的语音按顺序拼接到 `filepath`，失败和没有文本的回复跳过
+    pub async fn export_topic(
+        &self,
+        conversations: &[Conversation],
+        filepath: &Path,
+    ) -> Result<()> {
+        if !self.options.response_format.concatenable() {
+            return Err(AppError::Other(format!(
+                "{} 格式无法拼接，请使用 mp3、opus、aac 或 pcm",
+                self.options.response_format.extension()
+            )));
+        }
+
+        let mut file = tokio::fs::File::create(filepath).await?;
+        let mut count = 0;
+
+        for conversation in conversations {
+            let message = &conversation.assistant;
+            if matches!(
+                message.status,
+                MessageStatus::Error | MessageStatus::Timeout
+            ) || split_chunks(&message.message, MAX_CHUNK_CHARS).is_empty()
+            {
+                continue;
+            }
+
+            let path = self.message_audio(message).await?;
+            file.write_all(&tokio::fs::read(path).await?).await?;
+            count += 1;
+        }
+
+        file.flush().await?;
+
+        info!("已导出 {} 条回复的语音：{}", count, filepath.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! 用户消息中的图片
+//!
+//! 前端传入本地图片路径，发送前读取文件并编码为 `data:` URL；
+//! 保存消息时图片写入应用目录下的 `attachments`，历史对话再次发送时从这里读取。
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lazy_static::lazy_static;
+use rusqlite::Connection;
+
+use crate::{
+    api::chat::{ChatGPTRequest, ImageUrl},
+    config::APP_CONFIG_DIR,
+    db::attachment::Attachment,
+    error::{AppError, Result},
+};
+
+lazy_static! {
+    static ref ATTACHMENT_DIR: PathBuf = APP_CONFIG_DIR.join("attachments");
+}
+
+/// 接口允许的单张图片大小上限
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+fn is_inline(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("data:")
+}
+
+pub fn image_mime(path: &Path) -> Result<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "png" => Ok("image/png"),
+        "jpg" | "jpeg" => Ok("image/jpeg"),
+        "gif" => Ok("image/gif"),
+        "webp" => Ok("image/webp"),
+        _ => Err(AppError::Other(format!(
+            "不支持的图片格式：{}",
+            path.display()
+        ))),
+    }
+}
+
+pub fn mime_extension(mime: &str) -> &'static str {
+    match mime {
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "png",
+    }
+}
+
+/// 读取本地图片并编码为 `data:` URL，路径可以带 `file://` 前缀
+pub fn data_url(path: &str) -> Result<String> {
+    let path = Path::new(path.strip_prefix("file://").unwrap_or(path));
+    let mime = image_mime(path)?;
+
+    let size = fs::metadata(path)
+        .map_err(|e| AppError::Io(format!("无法读取图片 {}：{}", path.display(), e)))?
+        .len();
+    if size > MAX_IMAGE_BYTES {
+        return Err(AppError::Other(format!(
+            "图片超过 {} MB：{}",
+            MAX_IMAGE_BYTES / 1024 / 1024,
+            path.display()
+        )));
+    }
+
+    let data = fs::read(path)?;
+
+    Ok(format!("data:{};base64,{}", mime, STANDARD.encode(data)))
+}
+
+/// 把请求中所有本地图片替换为 `data:` URL，远程链接和 `data:` URL 保持不变
+pub fn inline_images(request: &mut ChatGPTRequest) -> Result<()> {
+    for message in request.messages.iter_mut() {
+        for image in message.content.images_mut() {
+            if !is_inline(&image.url) {
+                image.url = data_url(&image.url)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_data_url(url: &str) -> Result<(&str, Vec<u8>)> {
+    let (header, data) = url
+        .strip_prefix("data:")
+        .and_then(|u| u.split_once(','))
+        .ok_or_else(|| AppError::Parse("无效的 data URL".to_string()))?;
+    let mime = header
+        .strip_suffix(";base64")
+        .ok_or_else(|| AppError::Parse("data URL 不是 base64 编码".to_string()))?;
+    let data = STANDARD
+        .decode(data)
+        .map_err(|e| AppError::Parse(format!("图片 base64 解码失败：{}", e)))?;
+
+    Ok((mime, data))
+}
+
+/// 保存用户消息的图片
+///
+/// 需要在 `inline_images` 之后调用：`data:` URL 解码后写入附件目录，远程链接直接记录。
+pub fn save_images(
+    conn: &Connection,
+    user_message_id: u32,
+    images: &[&ImageUrl],
+    created_at: u64,
+) -> Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+
+    for (index, image) in images.iter().enumerate() {
+        let attachment = if image.url.starts_with("data:") {
+            let (mime, data) = parse_data_url(&image.url)?;
+
+            fs::create_dir_all(&*ATTACHMENT_DIR)?;
+            let path = ATTACHMENT_DIR.join(format!(
+                "{}-{}.{}",
+                user_message_id,
+                index,
+                mime_extension(mime)
+            ));
+            fs::write(&path, data)?;
+
+            Attachment::image(
+                user_message_id,
+                Some(mime),
+                &path.to_string_lossy(),
+                image.detail.as_deref(),
+                created_at,
+            )
+        } else {
+            Attachment::image(
+                user_message_id,
+                None,
+                &image.url,
+                image.detail.as_deref(),
+                created_at,
+            )
+        };
+
+        attachment.insert(conn)?;
+        attachments.push(attachment);
+    }
+
+    Ok(attachments)
+}
+
+/// 删除消息后删除复制到应用目录中的图片，远程图片的链接和目录外的路径不处理
+pub fn remove_attachments(paths: &[String]) {
+    for path in paths {
+        let path = Path::new(path);
+        if !path.starts_with(&*ATTACHMENT_DIR) {
+            continue;
+        }
+
+        match fs::remove_file(path) {
+            Ok(()) => debug!("已删除附件：{}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => warn!("删除附件 {} 时出错：{}", path.display(), e),
+        }
+    }
+}
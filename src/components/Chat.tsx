@@ -8,13 +8,18 @@ import React, {
 } from 'react'
 import { FloatButton, message } from 'antd'
 import {
+  BookOutlined,
   SaveOutlined,
+  SoundOutlined,
+  PictureOutlined,
   FileImageOutlined,
   FileMarkdownOutlined,
   FilePdfOutlined,
   FileTextOutlined,
 } from '@ant-design/icons'
 import domtoimage from 'dom-to-image-more'
+import { invoke } from '@tauri-apps/api'
+import { useParams } from 'react-router-dom'
 import {
   type SaveDialogOptions,
   save,
@@ -24,12 +29,14 @@ import {
 import '~/styles/Chat.scss'
 import { UserMessageMode, saveFile, saveMarkdown } from '~/lib/fs'
 import Progress from '~/components/Progress'
-import { now } from '~/lib'
+import { errorMessage, now, toProxyStruct } from '~/lib'
 import { type TypeOpen } from 'antd/es/message/interface'
 
 const MessageList = lazy(async () => await import('~/components/message/List'))
+const Gallery = lazy(async () => await import('~/components/Gallery'))
+const Knowledge = lazy(async () => await import('~/components/Knowledge'))
 
-type ChatProps = Omit<MessageListProps, 'showLineNumbers'> & {
+type ChatProps = Omit<MessageListProps, 'showLineNumbers' | 'onSpeak'> & {
   config: Config
   topicName: string
 }
@@ -52,6 +59,15 @@ const MARKDOWN_FILTER_OPTION: SaveDialogOptions = {
   ] as DialogFilter[],
 } as const
 
+const AUDIO_FILTER_OPTION: SaveDialogOptions = {
+  filters: [
+    {
+      name: '音频',
+      extensions: ['mp3', 'opus', 'aac', 'pcm'],
+    },
+  ] as DialogFilter[],
+} as const
+
 const handleSaveMessage = (
   errorMsg: string,
   setSaving: React.Dispatch<React.SetStateAction<Saving>>,
@@ -139,6 +155,60 @@ const Chat = memo(
     const contentsRef = useRef<HTMLDivElement>(null)
     const [saving, setSaving] = useState<Saving>({ status: false, name: '' })
     const [progress, setProgress] = useState(0)
+    const { topicID } = useParams<'topicID'>()
+    const topicId = parseInt(topicID ?? '0')
+    const [openGallery, setOpenGallery] = useState(false)
+    const [openKnowledge, setOpenKnowledge] = useState(false)
+
+    // 语音选项使用配置文件中的 speech
+    const handleSpeak = useCallback(
+      async (time: number): Promise<void> => {
+        try {
+          const audio = await invoke<SpeechAudio>('speak_message', {
+            proxyConfig: toProxyStruct(config.proxy),
+            topicId,
+            createdAt: time,
+          })
+
+          await new Audio(`data:${audio.mime};base64,${audio.data}`).play()
+        } catch (e) {
+          void message.error(errorMessage(e))
+        }
+      },
+      [config, topicId]
+    )
+
+    const handleSaveAudio = useCallback(async () => {
+      if (messages.length === 0) {
+        await message.warning('当前消息列表为空')
+
+        return
+      }
+
+      const extension = config.speech?.response_format ?? 'mp3'
+      const filepath = await save({
+        ...AUDIO_FILTER_OPTION,
+        defaultPath: `${topicName}-${now()}.${extension}`,
+      })
+
+      if (filepath === null) return
+
+      setSaving({ status: true, name: '音频' })
+
+      try {
+        await invoke('export_topic_speech', {
+          proxyConfig: toProxyStruct(config.proxy),
+          topicId,
+          filepath,
+        })
+
+        void message.success('音频已保存到：' + filepath)
+      } catch (e) {
+        void message.error(errorMessage(e))
+      } finally {
+        setSaving((pre) => ({ status: false, name: pre.name }))
+      }
+    }, [messages, config, topicId, topicName])
 
     const handleSaveMarkdown = useCallback(async () => {
       if (messages.length === 0) {
@@ -240,11 +310,49 @@ const Chat = memo(
                 messages={messages}
                 showTopicList={showTopicList}
                 showLineNumbers={config.showLineNumbers}
+                onSpeak={handleSpeak}
               />
             </React.Suspense>
           </div>
         </div>
 
+        <React.Suspense fallback={null}>
+          <Gallery
+            open={openGallery}
+            config={config}
+            topicId={topicId}
+            onClose={() => {
+              setOpenGallery(false)
+            }}
+          />
+          <Knowledge
+            open={openKnowledge}
+            config={config}
+            topicId={topicId}
+            onClose={() => {
+              setOpenKnowledge(false)
+            }}
+          />
+        </React.Suspense>
+
+        <FloatButton
+          tooltip="知识库"
+          icon={<BookOutlined />}
+          style={{ right: 8, bottom: 374, marginBottom: 16 }}
+          onClick={() => {
+            setOpenKnowledge(true)
+          }}
+        />
+
+        <FloatButton
+          tooltip="图片"
+          icon={<PictureOutlined />}
+          style={{ right: 8, bottom: 318, marginBottom: 16 }}
+          onClick={() => {
+            setOpenGallery(true)
+          }}
+        />
+
         <FloatButton.Group
           trigger="hover"
           style={{ right: 8, bottom: 262, marginBottom: 16 }}
@@ -275,6 +383,13 @@ const Chat = memo(
             tooltip="保存为图片"
             icon={<FileImageOutlined />}
           />
+
+          <FloatButton
+            key="save-audio"
+            onClick={handleSaveAudio}
+            tooltip="导出回复的语音"
+            icon={<SoundOutlined />}
+          />
         </FloatButton.Group>
       </>
     )
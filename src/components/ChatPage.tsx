@@ -4,12 +4,21 @@ import React, {
   useEffect,
   useCallback,
   useLayoutEffect,
+  useRef,
 } from 'react'
-import { Layout, Spin, message, Modal } from 'antd'
+import { Layout, Spin, message, Modal, Input } from 'antd'
 import { ExclamationCircleFilled } from '@ant-design/icons'
 import { invoke } from '@tauri-apps/api'
 import { type Event } from '@tauri-apps/api/event'
-import { isEqual, newChatRequest, now, readConfig, saveConfig } from '~/lib'
+import {
+  errorMessage,
+  isEqual,
+  newChatRequest,
+  now,
+  readConfig,
+  saveConfig,
+  toProxyStruct,
+} from '~/lib'
 import { useParams, useSearchParams } from 'react-router-dom'
 import {
   PROMPT_ASSISTANT_RESPONSE,
@@ -18,6 +27,7 @@ import {
   PROMPT_ROLE_MESSAGE_IN_CHINESE,
   addNewLine,
   deleteMessage,
+  messageToChatMessage,
 } from '~/lib/message'
 import { appWindow } from '@tauri-apps/api/window'
 import { TauriEvent } from '@tauri-apps/api/event'
@@ -38,7 +48,7 @@ const MessageInput = lazy(
 const { Content, Sider } = Layout
 
 const handleStreamResponse = async (
-  e: Event<string | MessageChunk>,
+  e: Event<string | MessageChunk | ToolEvent | ModerationRecord>,
   setMessages: React.Dispatch<React.SetStateAction<Message[]>>
 ): Promise<void> => {
   const payload = e.payload
@@ -46,6 +56,8 @@ const handleStreamResponse = async (
   if (typeof payload === 'string') {
     if (payload === 'done') {
       await message.success('文字流接收完成')
+    } else if (payload === 'timeout') {
+      void message.warning('等待文字流超时，已中断')
     } else {
       // 应该不存在其他文字的可能性
     }
@@ -53,21 +65,43 @@ const handleStreamResponse = async (
     return
   }
 
+  if ('output' in payload) {
+    void message.info(`已调用工具：${payload.name}`)
+
+    return
+  }
+
+  if ('flagged' in payload) {
+    void message.warning(
+      `内容审核命中：${Object.keys(payload.categories).join('、')}`
+    )
+
+    return
+  }
+
   const choice = payload.choices[0]
 
   if (choice.delta.role != null) {
     void message.success('开始接收文字流')
 
-    // 消息列表里添加一条消息
-    setMessages((prevMessages) => [
-      ...prevMessages,
-      {
-        content: '',
-        // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
-        role: choice.delta.role!,
-        time: payload.created * 1000,
-      },
-    ])
+    // 消息列表里添加一条消息，调用工具后继续生成的回复沿用调用前的空消息
+    setMessages((prevMessages) => {
+      const last = prevMessages[prevMessages.length - 1]
+
+      if (last?.role === 'assistant' && last.content === '') {
+        return prevMessages
+      }
+
+      return [
+        ...prevMessages,
+        {
+          content: '',
+          // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+          role: choice.delta.role!,
+          time: payload.created * 1000,
+        },
+      ]
+    })
 
     console.log('流消息', '添加一条消息')
 
@@ -80,16 +114,20 @@ const handleStreamResponse = async (
       {
         ...prevMessages[prevMessages.length - 1],
         content:
-          // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
-          prevMessages[prevMessages.length - 1].content + choice.delta.content!,
+          prevMessages[prevMessages.length - 1].content +
+          (choice.delta.content ?? ''),
       },
     ])
 
     return
   }
 
-  if (choice.finish_reason === 'stop') {
-    // 结束
+  if (
+    choice.finish_reason === 'stop' ||
+    choice.finish_reason === 'tool_calls' ||
+    choice.finish_reason === 'function_call'
+  ) {
+    // 结束，调用工具时后端执行后继续请求
   } else {
     // 其他异常:https://platform.openai.com/docs/guides/chat/response-format
     await message.error('网络异常，消息未接收完整')
@@ -112,11 +150,14 @@ const conversationsToMessages = (conversations: Conversation[]): Message[] => {
   for (const c of conversations) {
     const userMessage: Message = {
       content: c.user.message,
+      images: c.attachments.map((a) => a.path),
+      moderation: c.moderation ?? undefined,
       time: c.user.created_at,
       role: 'user',
     }
     const assistantMessage: Message = {
       content: c.assistant.message,
+      citations: c.assistant.citations ?? undefined,
       time: c.assistant.created_at * 1000,
       role: 'assistant',
     }
@@ -148,7 +189,7 @@ const addPromptMessages = (
 
 const addConversationMessages = (
   sendedMessages: ChatMessage[],
-  messages: Message[],
+  messages: ChatMessage[],
   useFirstConversation: boolean,
   useConversationCount: number
 ): void => {
@@ -199,15 +240,18 @@ const fillMessages = (
   topicConfig?: TopicConfig,
   inChinese?: boolean
 ): void => {
+  // 历史消息中的图片随对话一起发送
+  const history = messages.map(messageToChatMessage)
+
   if (inChinese !== undefined) {
-    sendedMessages.unshift(...messages)
+    sendedMessages.unshift(...history)
     addPromptMessages(sendedMessages, inChinese)
   }
 
   if (topicConfig?.use_context) {
     addConversationMessages(
       sendedMessages,
-      messages,
+      history,
       topicConfig.use_first_conversation,
       topicConfig.conversation_count
     )
@@ -230,6 +274,11 @@ const ChatPage: React.FC = () => {
   const [searchParams] = useSearchParams()
 
   const [retry, setRetry] = useState(false)
+  // 密钥文件已创建但未解锁时要求输入密码
+  const [openUnlock, setOpenUnlock] = useState(false)
+  const [passphrase, setPassphrase] = useState('')
+  // 当前流式响应的 id，用于中断
+  const streamID = useRef<string | null>(null)
   // const [abort, setAbort] = useState(false)
 
   const topicIDNumber = parseInt(topicID)
@@ -247,6 +296,11 @@ const ChatPage: React.FC = () => {
         setOpenSetting(true)
       }
 
+      const secrets = await invoke<SecretsStatus>('get_secrets_status')
+      if (secrets.backend === 'file' && secrets.exists && !secrets.unlocked) {
+        setOpenUnlock(true)
+      }
+
       void appWindow.once(TauriEvent.WINDOW_CLOSE_REQUESTED, async () => {
         await invoke('restore_is_on_top')
         await appWindow.close()
@@ -260,6 +314,20 @@ const ChatPage: React.FC = () => {
     void getMessagesByTopic(topicID)
   }, [topicID])
 
+  const handleUnlock = async (): Promise<void> => {
+    try {
+      await invoke('unlock_secrets', { passphrase })
+
+      setOpenUnlock(false)
+      setPassphrase('')
+
+      // 解锁后会迁移配置文件中的明文 key
+      setConfig(await readConfig())
+    } catch (e) {
+      void message.error(errorMessage(e))
+    }
+  }
+
   const getMessagesByTopic = useCallback(
     async (topicID: string): Promise<void> => {
       try {
@@ -279,7 +347,7 @@ const ChatPage: React.FC = () => {
 
         setMessages(ms)
       } catch (e) {
-        void message.error(String(e))
+        void message.error(errorMessage(e))
       }
     },
     [topicID]
@@ -305,55 +373,74 @@ const ChatPage: React.FC = () => {
 
           setMessages([])
         } catch (e) {
-          void message.error(e as string)
+          void message.error(errorMessage(e))
         }
       },
     })
   }
 
   const handleAbortStream = async (): Promise<void> => {
-    await appWindow.emit('abort-stream')
+    if (!streamID.current) return
+
+    await invoke('cancel_stream', { streamId: streamID.current })
     void message.info('已中断流式响应')
   }
 
-  const newMessage = (content: string): number => {
+  const newMessage = (content: string, images?: string[]): number => {
     const createdAt = now()
 
     setMessages((prevMessages) => [
       ...prevMessages,
-      { content, role: 'user', time: createdAt },
+      { content, images, role: 'user', time: createdAt },
     ])
 
     return createdAt
   }
 
+  // 失败的回复已保存到数据库，未收到任何内容时显示错误信息，以便重试时删除
+  const addFailedReply = (e: unknown): void => {
+    setMessages((prevMessages) =>
+      prevMessages[prevMessages.length - 1]?.role === 'assistant'
+        ? prevMessages
+        : [
+            ...prevMessages,
+            { content: errorMessage(e), role: 'assistant', time: now() },
+          ]
+    )
+  }
+
   const sendStreamRequest = async (args: ChatRequestArgs): Promise<void> => {
     console.log('使用的代理配置', config?.proxy)
 
+    const streamId = crypto.randomUUID()
+    streamID.current = streamId
+
     try {
-      const unlisten = await appWindow.listen<string>('stream', async (e) => {
-        await handleStreamResponse(e, setMessages)
-      })
+      const unlisten = await appWindow.listen<string>(
+        `stream://${streamId}`,
+        async (e) => {
+          await handleStreamResponse(e, setMessages)
+        }
+      )
 
       try {
-        const messageID = await invoke<number>('chat_gpt_stream', { ...args })
-
-        if (messageID === 0) {
-          setMessages((pre) => [...pre.slice(0, pre.length - 2)])
-        }
+        // 中断时已收到的部分回复会保存，不再从列表中移除
+        await invoke<number>('chat_gpt_stream', {
+          ...args,
+          streamId,
+        })
       } catch (e) {
-        void message.error(e as string)
+        void message.error(errorMessage(e))
 
         setRetry(true)
 
-        setMessages((prevMessages) => [
-          ...prevMessages.slice(0, prevMessages.length - 1),
-        ])
+        addFailedReply(e)
       }
 
       unlisten()
+      streamID.current = null
     } catch (e) {
-      void message.error(e as string)
+      void message.error(errorMessage(e))
 
       setRetry(true)
 
@@ -370,7 +457,8 @@ const ChatPage: React.FC = () => {
     setMessages((prevMessages) => [
       ...prevMessages,
       {
-        content: addNewLine(resp.choices[0].message.content),
+        // 回复的内容总是文本
+        content: addNewLine(resp.choices[0].message.content as string),
         role: resp.choices[0].message.role,
         time: resp.created * 1000,
       },
@@ -381,26 +469,23 @@ const ChatPage: React.FC = () => {
     ChatRequestArgs,
     'request' | 'createdAt'
   > => ({
-    proxyConfig: {
-      ...config?.proxy,
-      reverse_proxy: config?.proxy?.reverseProxy,
-    },
-    apiKey: config?.openApiKey,
+    proxyConfig: toProxyStruct(config?.proxy),
     topicId: topicIDNumber,
   })
 
   const handleSendMessage = useCallback(
-    async (content: string, stream = true): Promise<void> => {
-      const createdAt = newMessage(content)
+    async (
+      content: string,
+      stream = true,
+      images?: string[]
+    ): Promise<void> => {
+      const createdAt = newMessage(content, images)
 
       setWaiting(true)
       setRetry(false)
 
       const sendedMessages: ChatMessage[] = [
-        {
-          role: 'user',
-          content,
-        },
+        messageToChatMessage({ role: 'user', content, images, time: createdAt }),
       ]
 
       fillMessages(
@@ -433,9 +518,11 @@ const ChatPage: React.FC = () => {
         setRetry(true)
         setWaiting(false)
 
+        addFailedReply(e)
+
         console.error(e)
 
-        await message.error(String(e))
+        await message.error(errorMessage(e))
       } finally {
         setWaiting(false)
       }
@@ -518,6 +605,26 @@ const ChatPage: React.FC = () => {
         />
       </React.Suspense>
 
+      <Modal
+        title="解锁密钥文件"
+        open={openUnlock}
+        okText="解锁"
+        cancelText="取消"
+        onOk={handleUnlock}
+        onCancel={() => {
+          setOpenUnlock(false)
+        }}
+      >
+        <Input.Password
+          placeholder="输入保护 API key 的密码"
+          value={passphrase}
+          onChange={(e) => {
+            setPassphrase(e.target.value)
+          }}
+          onPressEnter={handleUnlock}
+        />
+      </Modal>
+
       <Layout className="layout">
         {showTopicList ? (
           <Sider>
@@ -0,0 +1,221 @@
+import React, { memo, useCallback, useEffect, useState } from 'react'
+import {
+  Button,
+  Empty,
+  Image,
+  Input,
+  InputNumber,
+  Modal,
+  Popconfirm,
+  Select,
+  Space,
+  Tooltip,
+  message,
+} from 'antd'
+import { DeleteOutlined, PictureOutlined } from '@ant-design/icons'
+import { invoke } from '@tauri-apps/api'
+import { open } from '@tauri-apps/api/dialog'
+import { errorMessage, toProxyStruct } from '~/lib'
+
+const SIZES = ['256x256', '512x512', '1024x1024', '1792x1024', '1024x1792']
+const QUALITIES = ['standard', 'hd']
+
+interface GalleryProps {
+  open: boolean
+  config: Config
+  topicId: number
+  onClose: () => void
+}
+
+// 本地图片由后端读取为 data URL
+const GalleryImage: React.FC<{ image: ImageRecord; onDelete: () => void }> = ({
+  image,
+  onDelete,
+}) => {
+  const [src, setSrc] = useState('')
+
+  useEffect(() => {
+    invoke<string>('read_image', { path: image.path })
+      .then(setSrc)
+      .catch(() => {
+        setSrc('')
+      })
+  }, [image.path])
+
+  return (
+    <Space direction="vertical" size={4} style={{ width: 160 }}>
+      <Image src={src} width={160} />
+      <Tooltip title={image.revised_prompt ?? image.prompt}>
+        <div className="gallery-prompt">
+          {image.prompt ?? '变体'} · {image.model}
+        </div>
+      </Tooltip>
+      <Popconfirm title="删除这张图片？" onConfirm={onDelete}>
+        <Button size="small" danger icon={<DeleteOutlined />} />
+      </Popconfirm>
+    </Space>
+  )
+}
+
+const Gallery = memo(({ open: visible, config, topicId, onClose }: GalleryProps) => {
+  const [images, setImages] = useState<ImageRecord[]>([])
+  const [prompt, setPrompt] = useState('')
+  const [source, setSource] = useState<string | null>(null)
+  const [options, setOptions] = useState<ImageOptions>({
+    n: 1,
+    size: '1024x1024',
+  })
+  const [generating, setGenerating] = useState(false)
+
+  const loadImages = useCallback(async () => {
+    try {
+      setImages(await invoke<ImageRecord[]>('get_images_by_topic_id', { topicId }))
+    } catch (e) {
+      void message.error(errorMessage(e))
+    }
+  }, [topicId])
+
+  useEffect(() => {
+    if (visible) void loadImages()
+  }, [visible, loadImages])
+
+  const selectSource = async (): Promise<void> => {
+    const selected = await open({
+      filters: [{ name: '图片', extensions: ['png', 'jpg', 'jpeg', 'webp'] }],
+    })
+
+    setSource(typeof selected === 'string' ? selected : null)
+  }
+
+  // 选择了原图时，有提示为编辑，没有提示为生成变体
+  const generate = async (): Promise<void> => {
+    const task: ImageTask =
+      source === null
+        ? { kind: 'generation', prompt }
+        : prompt.trim()
+          ? { kind: 'edit', image: source, prompt }
+          : { kind: 'variation', image: source }
+
+    setGenerating(true)
+
+    try {
+      const created = await invoke<ImageRecord[]>('generate_images', {
+        proxyConfig: toProxyStruct(config.proxy),
+        topicId,
+        task,
+        options,
+      })
+
+      setImages((pre) => [...pre, ...created])
+      setPrompt('')
+    } catch (e) {
+      void message.error(errorMessage(e))
+    } finally {
+      setGenerating(false)
+    }
+  }
+
+  const deleteImage = async (id: number): Promise<void> => {
+    try {
+      await invoke('delete_image', { id })
+
+      setImages((pre) => pre.filter((i) => i.id !== id))
+    } catch (e) {
+      void message.error(errorMessage(e))
+    }
+  }
+
+  return (
+    <Modal
+      title="图片"
+      open={visible}
+      width={760}
+      footer={null}
+      onCancel={onClose}
+    >
+      <Space direction="vertical" style={{ width: '100%' }}>
+        <Input.TextArea
+          value={prompt}
+          placeholder="描述要生成的图片"
+          autoSize={{ minRows: 2, maxRows: 6 }}
+          onChange={(e) => {
+            setPrompt(e.target.value)
+          }}
+        />
+
+        <Space wrap>
+          <Select
+            value={options.size}
+            style={{ width: 130 }}
+            options={SIZES.map((s) => ({ value: s, label: s }))}
+            onChange={(size) => {
+              setOptions((pre) => ({ ...pre, size }))
+            }}
+          />
+          <Select
+            value={options.quality}
+            placeholder="质量"
+            allowClear
+            style={{ width: 110 }}
+            options={QUALITIES.map((q) => ({ value: q, label: q }))}
+            onChange={(quality) => {
+              setOptions((pre) => ({ ...pre, quality }))
+            }}
+          />
+          <InputNumber
+            min={1}
+            max={10}
+            value={options.n}
+            addonBefore="数量"
+            style={{ width: 120 }}
+            onChange={(n) => {
+              setOptions((pre) => ({ ...pre, n: n ?? 1 }))
+            }}
+          />
+          <Tooltip title={source ?? '选择原图后编辑或生成变体'}>
+            <Button
+              icon={<PictureOutlined />}
+              onClick={selectSource}
+              onContextMenu={(e) => {
+                e.preventDefault()
+                setSource(null)
+              }}
+            >
+              {source === null ? '原图' : '已选择原图'}
+            </Button>
+          </Tooltip>
+          <Button
+            type="primary"
+            loading={generating}
+            disabled={source === null && prompt.trim() === ''}
+            onClick={generate}
+          >
+            生成
+          </Button>
+        </Space>
+
+        {images.length === 0 ? (
+          <Empty description="当前主题还没有图片" />
+        ) : (
+          <Image.PreviewGroup>
+            <Space wrap align="start">
+              {images.map((image) => (
+                <GalleryImage
+                  key={image.id}
+                  image={image}
+                  onDelete={() => {
+                    void deleteImage(image.id)
+                  }}
+                />
+              ))}
+            </Space>
+          </Image.PreviewGroup>
+        )}
+      </Space>
+    </Modal>
+  )
+})
+
+Gallery.displayName = 'Gallery'
+
+export default Gallery
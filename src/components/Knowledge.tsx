@@ -0,0 +1,133 @@
+import React, { memo, useCallback, useEffect, useState } from 'react'
+import { Button, Empty, List, Modal, Popconfirm, Tag, message } from 'antd'
+import { DeleteOutlined, FileAddOutlined } from '@ant-design/icons'
+import { invoke } from '@tauri-apps/api'
+import { open } from '@tauri-apps/api/dialog'
+import { errorMessage, toProxyStruct } from '~/lib'
+
+const FILTERS = [
+  { name: '文档', extensions: ['md', 'markdown', 'txt', 'rst', 'pdf'] },
+  {
+    name: '源代码',
+    extensions: ['rs', 'py', 'js', 'ts', 'tsx', 'go', 'java', 'c', 'cpp', 'h'],
+  },
+]
+
+interface KnowledgeProps {
+  open: boolean
+  config: Config
+  topicId: number
+  onClose: () => void
+}
+
+const Knowledge = memo(
+  ({ open: visible, config, topicId, onClose }: KnowledgeProps) => {
+    const [documents, setDocuments] = useState<KnowledgeDocument[]>([])
+    const [importing, setImporting] = useState(false)
+
+    const loadDocuments = useCallback(async () => {
+      try {
+        setDocuments(
+          await invoke<KnowledgeDocument[]>('get_knowledge_documents', {
+            topicId,
+          })
+        )
+      } catch (e) {
+        void message.error(errorMessage(e))
+      }
+    }, [topicId])
+
+    useEffect(() => {
+      if (visible) void loadDocuments()
+    }, [visible, loadDocuments])
+
+    // 同一路径再次导入时后端替换旧的分段，导入后重新读取列表
+    const importDocuments = async (): Promise<void> => {
+      const selected = await open({ multiple: true, filters: FILTERS })
+      if (selected === null) return
+
+      const paths = Array.isArray(selected) ? selected : [selected]
+
+      setImporting(true)
+
+      try {
+        await invoke<KnowledgeDocument[]>('import_knowledge', {
+          proxyConfig: toProxyStruct(config.proxy),
+          topicId,
+          paths,
+        })
+      } catch (e) {
+        void message.error(errorMessage(e))
+      } finally {
+        setImporting(false)
+        void loadDocuments()
+      }
+    }
+
+    const deleteDocument = async (id: number): Promise<void> => {
+      try {
+        await invoke('delete_knowledge_document', { id })
+
+        setDocuments((pre) => pre.filter((d) => d.id !== id))
+      } catch (e) {
+        void message.error(errorMessage(e))
+      }
+    }
+
+    return (
+      <Modal
+        title="知识库"
+        open={visible}
+        width={640}
+        footer={
+          <Button
+            type="primary"
+            icon={<FileAddOutlined />}
+            loading={importing}
+            onClick={importDocuments}
+          >
+            导入文件
+          </Button>
+        }
+        onCancel={onClose}
+      >
+        {documents.length === 0 ? (
+          <Empty description="当前主题还没有导入文件" />
+        ) : (
+          <List
+            size="small"
+            dataSource={documents}
+            renderItem={(d) => (
+              <List.Item
+                actions={[
+                  <Popconfirm
+                    key="delete"
+                    title="从知识库中移除这个文件？"
+                    onConfirm={() => {
+                      void deleteDocument(d.id)
+                    }}
+                  >
+                    <Button size="small" danger icon={<DeleteOutlined />} />
+                  </Popconfirm>,
+                ]}
+              >
+                <List.Item.Meta
+                  title={
+                    <>
+                      {d.name} <Tag>{d.kind}</Tag>
+                    </>
+                  }
+                  description={`${d.path} · ${d.chunks} 段 · ${d.model}`}
+                />
+              </List.Item>
+            )}
+          />
+        )}
+      </Modal>
+    )
+  }
+)
+
+Knowledge.displayName = 'Knowledge'
+
+export default Knowledge
@@ -154,7 +154,8 @@ const ChatMenu = memo(({
       ...config,
       topics: {
         ...config.topics,
-        [topicID]: topicConfig
+        // 保留设置界面中没有的字段，如模型、服务提供商和工具
+        [topicID]: { ...config.topics?.[topicID], ...topicConfig }
       }
     }
 
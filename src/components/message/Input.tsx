@@ -5,20 +5,33 @@ import {
   CloseCircleFilled,
   ClearOutlined,
   RedoOutlined,
+  PictureOutlined,
+  AudioOutlined,
 } from '@ant-design/icons'
 import {
   Affix,
+  Badge,
   Button,
   type ButtonProps,
+  Dropdown,
+  type MenuProps,
   Input,
   Space,
   Tooltip,
   message,
 } from 'antd'
 import { invoke } from '@tauri-apps/api'
+import { open } from '@tauri-apps/api/dialog'
+import { errorMessage, toProxyStruct } from '~/lib'
 
 const { TextArea } = Input
 
+const audioItems: MenuProps['items'] = [
+  { key: 'input', label: '转写到输入框' },
+  { key: 'send', label: '转写并发送' },
+  { key: 'translate', label: '翻译为英文并发送' },
+]
+
 const MessageInput = memo(
   ({
     onSendMessage,
@@ -33,6 +46,9 @@ const MessageInput = memo(
   }: MessageInputProps) => {
     const [chatMessage, setChatMessage] = useState('')
     const [lastInputMessage, setLastInputMessage] = useState(lastUserMessage)
+    const [images, setImages] = useState<string[]>([])
+    const [lastInputImages, setLastInputImages] = useState<string[]>([])
+    const [transcribing, setTranscribing] = useState(false)
 
     // console.log(lastUserMessage)
 
@@ -60,12 +76,68 @@ const MessageInput = memo(
         return
       }
 
-      void onSendMessage(message, config.useStream)
+      void onSendMessage(message, config.useStream, images)
 
       setLastInputMessage(message)
+      setLastInputImages(images)
 
       setChatMessage('')
-    }, [chatMessage, config.useStream, onSendMessage])
+      setImages([])
+    }, [chatMessage, images, config.useStream, onSendMessage])
+
+    // 选择的本地图片路径由后端读取并编码
+    const selectImages = async (): Promise<void> => {
+      const selected = await open({
+        multiple: true,
+        filters: [
+          { name: '图片', extensions: ['png', 'jpg', 'jpeg', 'gif', 'webp'] },
+        ],
+      })
+
+      if (selected === null) return
+
+      setImages(Array.isArray(selected) ? selected : [selected])
+    }
+
+    // 转写音频，发送时转写结果作为用户消息保存到当前主题
+    const handleAudio: MenuProps['onClick'] = async ({ key }) => {
+      const file = await open({
+        filters: [
+          {
+            name: '音频',
+            extensions: ['mp3', 'mp4', 'm4a', 'wav', 'webm', 'ogg', 'flac'],
+          },
+        ],
+      })
+
+      if (file === null || Array.isArray(file)) return
+
+      setTranscribing(true)
+
+      try {
+        const resp = await invoke<AudioResponse>('transcribe_audio', {
+          proxyConfig: toProxyStruct(config.proxy),
+          topicId: parseInt(topicID),
+          task: key === 'translate' ? 'translation' : 'transcription',
+          request: { file },
+        })
+
+        const text = resp.text.trim()
+
+        if (key === 'input') {
+          setChatMessage((pre) => (pre ? pre + '\n' : '') + text)
+        } else if (text) {
+          void onSendMessage(text, config.useStream)
+
+          setLastInputMessage(text)
+          setLastInputImages([])
+        }
+      } catch (e) {
+        void message.error(errorMessage(e))
+      } finally {
+        setTranscribing(false)
+      }
+    }
 
     const clearMessages = async (): Promise<void> => {
       try {
@@ -73,7 +145,7 @@ const MessageInput = memo(
 
         resetMessageList()
       } catch (e) {
-        void message.error(e as string)
+        void message.error(errorMessage(e))
       }
     }
 
@@ -87,7 +159,7 @@ const MessageInput = memo(
 
       if (!ok) return
 
-      void onSendMessage(lastInputMessage, config.useStream)
+      void onSendMessage(lastInputMessage, config.useStream, lastInputImages)
     }
 
     const statusButton = (): React.ReactNode => {
@@ -147,6 +219,36 @@ const MessageInput = memo(
               </Button>
             </Tooltip>
 
+            <Tooltip
+              title={
+                images.length > 0
+                  ? `已选择 ${images.length} 张图片，右键清除`
+                  : '附加图片'
+              }
+            >
+              <Badge count={images.length} size="small">
+                <Button
+                  disabled={waiting}
+                  onClick={selectImages}
+                  onContextMenu={(e) => {
+                    e.preventDefault()
+                    setImages([])
+                  }}
+                >
+                  <PictureOutlined />
+                </Button>
+              </Badge>
+            </Tooltip>
+
+            <Dropdown
+              menu={{ items: audioItems, onClick: handleAudio }}
+              disabled={waiting || transcribing}
+            >
+              <Button loading={transcribing}>
+                <AudioOutlined />
+              </Button>
+            </Dropdown>
+
             <TextArea
               value={chatMessage}
               placeholder={
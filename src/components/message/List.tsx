@@ -2,16 +2,24 @@ import React, { lazy, memo } from 'react'
 
 const Message = lazy(async () => await import('~/components/message/Message'))
 
-const MessageList = memo(({ messages, showTopicList, showLineNumbers }: MessageListProps) => (
+const MessageList = memo(({ messages, showTopicList, showLineNumbers, onSpeak }: MessageListProps) => (
   <ol className="list">
-    {messages.map(({ content, role, time }) => (
+    {messages.map(({ content, images, citations, moderation, role, time }, i) => (
       <React.Suspense fallback={null} key={time}>
         <Message
           content={content}
+          images={images}
+          citations={citations}
+          moderation={moderation}
           role={role}
           time={time}
           showTopicList={showTopicList}
           showLineNumbers={showLineNumbers}
+          onSpeak={
+            role === 'assistant' && i > 0 && onSpeak
+              ? async () => { await onSpeak(messages[i - 1].time) }
+              : undefined
+          }
         />
       </React.Suspense>
     ))}
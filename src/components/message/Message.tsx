@@ -1,12 +1,13 @@
-import React, { memo, useMemo, useState } from 'react'
+import React, { memo, useEffect, useMemo, useState } from 'react'
 import ReactMarkdown from 'react-markdown'
 import type { CodeProps } from 'react-markdown/lib/ast-to-react'
 import SyntaxHighlighter from 'react-syntax-highlighter/dist/esm/prism-async'
 import { dark as CodeStyle } from 'react-syntax-highlighter/dist/esm/styles/prism'
 import remarkMath from 'remark-math'
 import rehypeKatex from 'rehype-katex'
-import { Button, Tooltip } from 'antd'
-import { CheckOutlined, CopyOutlined } from '@ant-design/icons'
+import { Button, Image, Popover, Tag, Tooltip } from 'antd'
+import { invoke } from '@tauri-apps/api'
+import { CheckOutlined, CopyOutlined, SoundOutlined } from '@ant-design/icons'
 import { CopyToClipboard } from 'react-copy-to-clipboard'
 
 import 'katex/dist/katex.min.css'
@@ -74,19 +75,102 @@ const InlineCode: React.FC<CodeProps> = ({ className, children }) => {
   return <code className={className}>{children}</code>
 }
 
+// 本地图片由后端读取为 data URL，远程图片直接显示
+const Attachments: React.FC<{ images: string[] }> = ({ images }) => {
+  const [sources, setSources] = useState<string[]>([])
+
+  useEffect(() => {
+    void Promise.all(
+      images.map(async (path) =>
+        /^(https?:|data:)/.test(path)
+          ? path
+          : await invoke<string>('read_image', { path }).catch(() => '')
+      )
+    ).then(setSources)
+  }, [images])
+
+  return (
+    <Image.PreviewGroup>
+      {sources.map((src, i) => (
+        <Image key={i} src={src} width={160} />
+      ))}
+    </Image.PreviewGroup>
+  )
+}
+
+// 回复引用的知识库分段，悬停查看当时检索到的内容
+const Citations: React.FC<{ citations: Citation[] }> = ({ citations }) => (
+  <ol className="citations">
+    {citations.map((c) => (
+      <li key={c.index}>
+        <Popover
+          title={`${c.path}（第 ${c.start_line}-${c.end_line} 行）`}
+          content={<pre className="citation-content">{c.content}</pre>}
+        >
+          [{c.index}] {c.name}
+        </Popover>
+      </li>
+    ))}
+  </ol>
+)
+
+// 审核命中的类别，只记录的结果不显示
+const ModerationTag: React.FC<{ moderation: ModerationRecord }> = ({
+  moderation,
+}) => {
+  if (moderation.action === null || moderation.action === 'log') return null
+
+  return (
+    <Tooltip
+      title={moderation.error ?? Object.keys(moderation.categories).join('、')}
+    >
+      <Tag
+        className="moderation-tag"
+        color={moderation.action === 'block' ? 'error' : 'warning'}
+      >
+        {moderation.action === 'block' ? '审核未通过' : '审核提示'}
+      </Tag>
+    </Tooltip>
+  )
+}
+
 interface MessageProps extends Message {
   showTopicList: boolean
   showLineNumbers: boolean
+  onSpeak?: () => Promise<void>
 }
 
 const Message = memo(
-  ({ content, role, time, showTopicList, showLineNumbers }: MessageProps) => {
+  ({
+    content,
+    images,
+    citations,
+    moderation,
+    role,
+    time,
+    showTopicList,
+    showLineNumbers,
+    onSpeak,
+  }: MessageProps) => {
     const sent = role === 'user'
 
     const remarkPlugins = useMemo(() => [remarkMath], [])
     const rehypePlugins = useMemo(() => [rehypeKatex], [])
 
     const [copied, setCopied] = useState(false)
+    const [speaking, setSpeaking] = useState(false)
+
+    const handleSpeak = async (): Promise<void> => {
+      if (!onSpeak) return
+
+      setSpeaking(true)
+
+      try {
+        await onSpeak()
+      } finally {
+        setSpeaking(false)
+      }
+    }
 
     const renderCodeBlock = ({
       node,
@@ -143,6 +227,20 @@ const Message = memo(
           </Tooltip>
         </CopyToClipboard>
 
+        {onSpeak ? (
+          <Tooltip title="朗读" placement="left">
+            <Button
+              size="small"
+              shape="circle"
+              className="speak-button"
+              loading={speaking}
+              onClick={handleSpeak}
+            >
+              <SoundOutlined />
+            </Button>
+          </Tooltip>
+        ) : null}
+
         <ReactMarkdown
           components={{ code: renderCodeBlock }}
           remarkPlugins={remarkPlugins}
@@ -150,6 +248,12 @@ const Message = memo(
         >
           {content}
         </ReactMarkdown>
+
+        {images?.length ? <Attachments images={images} /> : null}
+
+        {citations?.length ? <Citations citations={citations} /> : null}
+
+        {moderation ? <ModerationTag moderation={moderation} /> : null}
       </li>
     )
   }
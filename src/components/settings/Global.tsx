@@ -17,7 +17,7 @@ import {
 import { UserMessageMode } from '~/lib/fs'
 import { SwapOutlined } from '@ant-design/icons'
 import { invoke } from '@tauri-apps/api'
-import { Models } from '~/lib'
+import { Models, toProxyStruct, errorMessage } from '~/lib'
 
 const PROTOCOLS: Array<SelectOption<string>> = [
   {
@@ -259,6 +259,7 @@ const Settings = memo(
         const newSettings: Config = {
           ...config,
           proxy: {
+            ...config.proxy,
             method: proxyMethod,
             reverseProxy,
             proxy: Object.keys(proxy).length === 0 ? undefined : proxy,
@@ -313,11 +314,7 @@ const Settings = memo(
     const onCheckConnect = async (): Promise<void> => {
       try {
         const res = await invoke<Model>('get_model', {
-          proxyConfig: {
-            ...config?.proxy,
-            reverse_proxy: config?.proxy?.reverseProxy,
-          },
-          apiKey: config?.openApiKey,
+          proxyConfig: toProxyStruct(config?.proxy),
           model: Models.GPT_3_5,
         })
 
@@ -327,6 +324,19 @@ const Settings = memo(
       }
     }
 
+    const onEncryptSecrets = async (passphrase: string): Promise<void> => {
+      try {
+        // 密钥文件不存在时以此密码创建，并迁移配置文件中的明文 key
+        await invoke('unlock_secrets', { passphrase })
+
+        form.setFieldValue('secrets-passphrase', '')
+
+        void message.success('API key 已加密保存')
+      } catch (e) {
+        void message.error(errorMessage(e))
+      }
+    }
+
     return (
       <Modal
         open={open}
@@ -369,9 +379,14 @@ const Settings = memo(
                 <Select.Option key="proxy" value="proxy">
                   代理
                 </Select.Option>
+
+                <Select.Option key="system" value="system">
+                  系统代理
+                </Select.Option>
               </Select>
 
-              {proxyMethod == null ? null : proxyMethod === 'proxy' ? (
+              {proxyMethod == null ||
+              proxyMethod === 'system' ? null : proxyMethod === 'proxy' ? (
                 <>
                   <Form.Item
                     name={['proxy', 'protocol']}
@@ -425,6 +440,36 @@ const Settings = memo(
                       }}
                     />
                   </Form.Item>
+
+                  <Form.Item
+                    name={['proxy', 'username']}
+                    initialValue={proxy.username}
+                    noStyle
+                  >
+                    <Input
+                      placeholder="用户名"
+                      value={proxy.username}
+                      style={{ width: 90 }}
+                      onChange={(e) => {
+                        onProxyInputChange('username', e.target.value)
+                      }}
+                    />
+                  </Form.Item>
+
+                  <Form.Item
+                    name={['proxy', 'password']}
+                    initialValue={proxy.password}
+                    noStyle
+                  >
+                    <Input.Password
+                      placeholder="密码"
+                      value={proxy.password}
+                      style={{ width: 100 }}
+                      onChange={(e) => {
+                        onProxyInputChange('password', e.target.value)
+                      }}
+                    />
+                  </Form.Item>
                 </>
               ) : (
                 <Form.Item
@@ -461,6 +506,19 @@ const Settings = memo(
             <Input.Password onChange={onInputOpenApiKey} />
           </Form.Item>
 
+          <Form.Item
+            name="secrets-passphrase"
+            label="加密保存"
+            tooltip="设置密码后 API key 将加密保存在 secrets.json 中，配置文件中只保存引用，每次启动需输入密码解锁"
+          >
+            <Input.Search
+              type="password"
+              placeholder="输入密码"
+              enterButton="加密"
+              onSearch={onEncryptSecrets}
+            />
+          </Form.Item>
+
           <Form.Item
             name="image-scale"
             label="图片缩放比例"
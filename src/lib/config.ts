@@ -9,6 +9,7 @@
 
 import { invoke } from '@tauri-apps/api'
 import { message } from 'antd'
+import { errorMessage } from './error'
 
 export const defaultConfig: Config = {
   proxy: undefined,
@@ -33,11 +34,7 @@ interface PromptStruct {
 }
 
 interface ConfigStruct {
-  proxy: {
-    method: ProxyMethod
-    proxy?: Proxy
-    reverse_proxy?: ReverseProxy
-  }
+  proxy: ProxyConfigStruct
   prompt: PromptStruct
   open_api_key: string
   image_scale: number
@@ -47,6 +44,20 @@ interface ConfigStruct {
   is_on_top: boolean
   topics?: Record<string, TopicConfig>
   export?: ExportConfig
+  providers?: Record<string, ProviderConfig>
+  pricing?: Record<string, ModelPrice>
+  retry?: RetryConfig
+  timeout?: TimeoutConfig
+  http?: HttpConfig
+  tls?: TlsConfig
+  api_keys?: ApiKeyConfig[]
+  key_rotation?: KeyRotation
+  secret_backend?: SecretBackend
+  tools?: ToolsConfig
+  mcp_servers?: Record<string, McpServerConfig>
+  speech?: SpeechOptions
+  embedding?: EmbeddingConfig
+  knowledge?: KnowledgeConfig
 }
 
 export const PROTOCOLS = [
@@ -70,6 +81,17 @@ export const PROTOCOLS = [
   ],
 ]
 
+/**
+ * 将配置中的代理转换为后端使用的 ProxyConfig
+ */
+export const toProxyStruct = (proxy?: Config['proxy']): ProxyConfigStruct => ({
+  method: proxy?.method,
+  proxy: proxy?.proxy,
+  reverse_proxy: proxy?.reverseProxy,
+  no_proxy: proxy?.noProxy,
+  headers: proxy?.headers,
+})
+
 export const readConfig = async (): Promise<Config> => {
   try {
     const config = await invoke<ConfigStruct>('read_config')
@@ -85,6 +107,8 @@ export const readConfig = async (): Promise<Config> => {
         method: config?.proxy?.method,
         proxy: config?.proxy?.proxy,
         reverseProxy: config?.proxy?.reverse_proxy,
+        noProxy: config?.proxy?.no_proxy,
+        headers: config?.proxy?.headers,
       },
       prompt: { inChinese: config?.prompt?.in_chinese ?? true },
       openApiKey: config?.open_api_key ?? '',
@@ -93,6 +117,20 @@ export const readConfig = async (): Promise<Config> => {
       useEnter: config?.use_enter ?? false,
       showLineNumbers: config?.show_line_numbers ?? false,
       topics: config?.topics,
+      providers: config?.providers,
+      pricing: config?.pricing,
+      retry: config?.retry,
+      timeout: config?.timeout,
+      http: config?.http,
+      tls: config?.tls,
+      apiKeys: config?.api_keys,
+      keyRotation: config?.key_rotation,
+      secretBackend: config?.secret_backend,
+      tools: config?.tools,
+      mcpServers: config?.mcp_servers,
+      speech: config?.speech,
+      embedding: config?.embedding,
+      knowledge: config?.knowledge,
       isOnTop: config?.is_on_top,
       export: {
         markdown: {
@@ -101,7 +139,7 @@ export const readConfig = async (): Promise<Config> => {
       },
     }
   } catch (e) {
-    void message.error(String(e))
+    void message.error(errorMessage(e))
 
     return defaultConfig
   }
@@ -110,11 +148,7 @@ export const readConfig = async (): Promise<Config> => {
 export const saveConfig = async (config: Config): Promise<void> => {
   try {
     const configStruct: ConfigStruct = {
-      proxy: {
-        // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
-        ...config.proxy!,
-        reverse_proxy: config.proxy?.reverseProxy,
-      },
+      proxy: toProxyStruct(config.proxy),
       prompt: { in_chinese: config.prompt.inChinese },
       open_api_key: config.openApiKey,
       image_scale: config.imageScale,
@@ -122,6 +156,20 @@ export const saveConfig = async (config: Config): Promise<void> => {
       use_enter: config.useEnter,
       show_line_numbers: config.showLineNumbers,
       topics: config.topics,
+      providers: config.providers,
+      pricing: config.pricing,
+      retry: config.retry,
+      timeout: config.timeout,
+      http: config.http,
+      tls: config.tls,
+      api_keys: config.apiKeys,
+      key_rotation: config.keyRotation,
+      secret_backend: config.secretBackend,
+      tools: config.tools,
+      mcp_servers: config.mcpServers,
+      speech: config.speech,
+      embedding: config.embedding,
+      knowledge: config.knowledge,
       export: config.export,
       is_on_top: config.isOnTop,
     }
@@ -130,7 +178,7 @@ export const saveConfig = async (config: Config): Promise<void> => {
 
     return
   } catch (e) {
-    void message.error(String(e))
+    void message.error(errorMessage(e))
   }
 }
 
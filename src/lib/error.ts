@@ -0,0 +1,15 @@
+const isAppError = (e: unknown): e is AppError =>
+  typeof e === 'object' && e !== null && 'kind' in e && 'message' in e
+
+/**
+ * 获取后端命令返回的错误信息
+ * @param e 捕获到的错误
+ * @returns 可展示给用户的错误信息
+ */
+export const errorMessage = (e: unknown): string => {
+  if (isAppError(e)) {
+    return e.http_status != null ? `${e.http_status}: ${e.message}` : e.message
+  }
+
+  return String(e)
+}
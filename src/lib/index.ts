@@ -12,6 +12,7 @@ export * from './time'
 export * from './message'
 export * from './config'
 export * from './request'
+export * from './error'
 
 /**
  * isEqual 函数用于比较两个对象是否相等
@@ -7,6 +7,7 @@
 
 import { invoke } from '@tauri-apps/api'
 import { message } from 'antd'
+import { errorMessage } from './error'
 
 export const addNewLine = (src: string): string => {
   return src.replace(/([^\n])\n([^\n])/g, '$1\n\n$2')
@@ -49,15 +50,28 @@ export const deleteMessage = async (createAt: number): Promise<boolean> => {
 
     return true
   } catch (e) {
-    void message.error('删除消息失败：' + (e as string))
+    void message.error('删除消息失败：' + errorMessage(e))
 
     return false
   }
 }
 
 export const messageToChatMessage = (message: Message): ChatMessage => {
+  if (!message.images?.length) {
+    return {
+      role: message.role,
+      content: message.content
+    }
+  }
+
   return {
     role: message.role,
-    content: message.content
+    content: [
+      { type: 'text', text: message.content },
+      ...message.images.map((url): ContentPart => ({
+        type: 'image_url',
+        image_url: { url }
+      }))
+    ]
   }
 }
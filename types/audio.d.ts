@@ -0,0 +1,40 @@
+/*
+ * file     audio.d.ts
+ */
+
+type AudioTask = 'transcription' | 'translation'
+
+type AudioResponseFormat = 'json' | 'text' | 'srt' | 'verbose_json' | 'vtt'
+
+declare interface AudioRequest {
+  /** 本地音频文件路径 */
+  file: string
+  model?: string
+  language?: string
+  prompt?: string
+  response_format?: AudioResponseFormat
+  temperature?: number
+}
+
+declare interface AudioResponse {
+  text: string
+  language: string | null
+  duration: number | null
+  raw: Record<string, unknown> | null
+}
+
+type SpeechFormat = 'mp3' | 'opus' | 'aac' | 'flac' | 'wav' | 'pcm'
+
+declare interface SpeechOptions {
+  model?: string
+  voice?: string
+  response_format?: SpeechFormat
+  speed?: number
+}
+
+declare interface SpeechAudio {
+  path: string
+  mime: string
+  /** base64 编码的音频 */
+  data: string
+}
@@ -7,16 +7,73 @@
  * Modified By: thepoy
  */
 
-type Role = 'user' | 'assistant' | 'system'
+type Role = 'user' | 'assistant' | 'system' | 'tool'
 
-type FinishReason = 'stop' | 'length' | 'content_filter' | null
+type FinishReason =
+  | 'stop'
+  | 'length'
+  | 'content_filter'
+  | 'tool_calls'
+  | 'function_call'
+  | null
+
+declare interface FunctionCall {
+  name: string
+  arguments: string
+}
+
+declare interface ToolCall {
+  id: string
+  type: 'function'
+  function: FunctionCall
+}
+
+declare interface FunctionDefinition {
+  name: string
+  description?: string
+  parameters?: Record<string, unknown>
+}
+
+declare interface Tool {
+  type: 'function'
+  function: FunctionDefinition
+}
+
+type ToolChoice =
+  | 'none'
+  | 'auto'
+  | 'required'
+  | { type: 'function'; function: { name: string } }
+
+declare interface ImageUrl {
+  /** http(s) 链接、data URL 或本地路径，本地路径由后端编码 */
+  url: string
+  detail?: 'low' | 'high' | 'auto'
+}
+
+type ContentPart =
+  | { type: 'text'; text: string }
+  | { type: 'image_url'; image_url: ImageUrl }
 
 declare interface ChatMessage {
   role: Role
-  content: string
+  content: string | ContentPart[]
+  name?: string
+  tool_calls?: ToolCall[]
+  tool_call_id?: string
+  function_call?: FunctionCall
 }
 
-declare type Message = ChatMessage & { time: number }
+declare type Message = Omit<ChatMessage, 'content'> & {
+  content: string
+  /** 用户消息附带的图片路径 */
+  images?: string[]
+  /** 回复引用的知识库分段 */
+  citations?: Citation[]
+  /** 用户消息发送前的审核结果 */
+  moderation?: ModerationRecord
+  time: number
+}
 
 declare interface ChatGPTRequest {
   model: string
@@ -30,6 +87,10 @@ declare interface ChatGPTRequest {
   presence_penalty?: number
   frequency_penalty?: number
   user?: string
+  tools?: Tool[]
+  tool_choice?: ToolChoice
+  functions?: FunctionDefinition[]
+  function_call?: 'none' | 'auto' | { name: string }
 }
 
 interface Choice {
@@ -38,9 +99,24 @@ interface Choice {
   finish_reason: FinishReason
 }
 
+interface ToolCallDelta {
+  index: number
+  id?: string
+  type?: 'function'
+  function?: Partial<FunctionCall>
+}
+
 interface StreamChoiceDelta {
   role?: Role
   content?: string
+  tool_calls?: ToolCallDelta[]
+  function_call?: Partial<FunctionCall>
+}
+
+/** 后端执行工具后发送的事件 */
+declare interface ToolEvent {
+  name: string
+  output: string
 }
 
 interface StreamChoice {
@@ -60,7 +136,7 @@ declare interface ChatGPTResponse<T = Choice | StreamChoice> {
   object: string
   created: number
   choices: T[]
-  usage: Usage
+  usage?: Usage
   model: string
 }
 
@@ -90,13 +166,7 @@ declare interface MessageChunk {
 type UpdateScrollbar = () => void
 
 interface ChatRequestArgs {
-  proxyConfig: {
-    reverse_proxy?: string
-    method?: ProxyMethod
-    proxy?: Proxy
-    reverseProxy?: string
-  }
-  apiKey?: string
+  proxyConfig: ProxyConfigStruct
   request: ChatGPTRequest
   topicId: number
   createdAt: number
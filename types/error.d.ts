@@ -0,0 +1,23 @@
+type ErrorKind =
+  | 'invalid_api_key'
+  | 'rate_limit'
+  | 'insufficient_quota'
+  | 'context_length_exceeded'
+  | 'api'
+  | 'network'
+  | 'proxy'
+  | 'timeout'
+  | 'database'
+  | 'config'
+  | 'io'
+  | 'parse'
+  | 'moderation'
+  | 'other'
+
+declare interface AppError {
+  kind: ErrorKind
+  message: string
+  retryable: boolean
+  http_status: number | null
+  provider_code: string | null
+}
@@ -0,0 +1,29 @@
+/*
+ * file     image.d.ts
+ */
+
+type ImageTask =
+  | { kind: 'generation'; prompt: string }
+  | { kind: 'edit'; image: string; mask?: string; prompt: string }
+  | { kind: 'variation'; image: string }
+
+declare interface ImageOptions {
+  model?: string
+  n?: number
+  size?: string
+  quality?: string
+  style?: string
+}
+
+declare interface ImageRecord {
+  id: number
+  topic_id: number
+  kind: ImageTask['kind']
+  prompt: string | null
+  revised_prompt: string | null
+  model: string
+  size: string | null
+  quality: string | null
+  path: string
+  created_at: number
+}
@@ -0,0 +1,29 @@
+/*
+ * file     knowledge.d.ts
+ */
+
+declare type KnowledgeKind = 'markdown' | 'text' | 'code' | 'pdf'
+
+declare interface KnowledgeDocument {
+  id: number
+  topic_id: number
+  name: string
+  path: string
+  kind: KnowledgeKind
+  model: string
+  chunks: number
+  created_at: number
+}
+
+/** 回复引用的知识库分段，`index` 对应回复中的 `[n]` */
+declare interface Citation {
+  index: number
+  document_id: number
+  chunk_id: number
+  name: string
+  path: string
+  start_line: number
+  end_line: number
+  score: number
+  content: string
+}
@@ -9,10 +9,16 @@ interface MessageListProps {
   messages: Message[]
   showTopicList: boolean
   showLineNumbers: boolean
+  /** 朗读回复，参数为对应用户消息的时间 */
+  onSpeak?: (time: number) => Promise<void>
 }
 
 interface MessageInputProps {
-  onSendMessage: (message: string, stream: boolean) => Promise<void>
+  onSendMessage: (
+    message: string,
+    stream: boolean,
+    images?: string[]
+  ) => Promise<void>
   onAbortStream: () => void
   resetMessageList: () => void
   redo: () => Promise<boolean>
@@ -37,14 +43,68 @@ declare interface UserMessage {
   topic_id: number
 }
 
+declare type MessageStatus =
+  | 'complete'
+  | 'aborted'
+  | 'error'
+  | 'timeout'
+  | 'length'
+
 declare interface AssistantMessage {
   id: number
   message: string
   created_at: number
   user_message_id: number
+  status: MessageStatus
+  error: string | null
+  tool_calls: ToolCall[] | null
+  citations: Citation[] | null
+}
+
+declare interface ToolMessage {
+  id: number
+  user_message_id: number
+  role: 'assistant' | 'tool'
+  message: string
+  tool_calls: ToolCall[] | null
+  tool_call_id: string | null
+  name: string | null
+  created_at: number
+}
+
+declare interface Attachment {
+  id: number
+  user_message_id: number
+  kind: 'image'
+  mime: string | null
+  path: string
+  detail: string | null
+  created_at: number
+}
+
+declare interface ModerationRecord {
+  id: number
+  user_message_id: number
+  model: string
+  flagged: boolean
+  action: ModerationAction | null
+  /** 命中的类别及对应的处理 */
+  categories: Record<string, ModerationAction>
+  scores: Record<string, number>
+  error: string | null
+  created_at: number
 }
 
 declare interface Conversation {
   user: UserMessage
+  attachments: Attachment[]
+  moderation: ModerationRecord | null
+  tools: ToolMessage[]
   assistant: AssistantMessage
 }
+
+declare interface SemanticMatch {
+  score: number
+  topic_id: number
+  conversation: Conversation
+}
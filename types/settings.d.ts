@@ -13,17 +13,21 @@ declare interface Proxy {
   protocol?: Protocol
   host?: string
   port?: number
+  username?: string
+  password?: string
 }
 
 declare type ReverseProxy = string
 
-type ProxyMethod = 'proxy' | 'reverse-proxy'
+type ProxyMethod = 'proxy' | 'reverse-proxy' | 'system'
 
 declare interface Config {
   proxy?: {
     method: ProxyMethod
     proxy?: Proxy
     reverseProxy?: ReverseProxy
+    noProxy?: string[]
+    headers?: Record<string, string>
   }
   prompt: PromptConfig
   export: ExportConfig
@@ -34,8 +38,126 @@ declare interface Config {
   showLineNumbers: boolean
   isOnTop: boolean
   topics?: Record<string, TopicConfig>
+  providers?: Record<string, ProviderConfig>
+  // 以下配置只能在配置文件中修改，保存时原样写回
+  pricing?: Record<string, ModelPrice>
+  retry?: RetryConfig
+  timeout?: TimeoutConfig
+  http?: HttpConfig
+  tls?: TlsConfig
+  apiKeys?: ApiKeyConfig[]
+  keyRotation?: KeyRotation
+  secretBackend?: SecretBackend
+  tools?: ToolsConfig
+  mcpServers?: Record<string, McpServerConfig>
+  speech?: SpeechOptions
+  embedding?: EmbeddingConfig
+  knowledge?: KnowledgeConfig
 }
 
+declare interface ApiKeyConfig {
+  name: string
+  key: string
+  provider?: string
+}
+
+declare type KeyRotation = 'failover' | 'round-robin'
+
+declare type SecretBackend = 'file' | 'keyring'
+
+declare interface McpServerConfig {
+  command: string
+  args?: string[]
+  env?: Record<string, string>
+  cwd?: string
+  timeout_ms?: number
+}
+
+declare interface McpServerStatus {
+  name: string
+  running: boolean
+  tools: string[]
+}
+
+declare interface ToolsConfig {
+  allowed_dirs?: string[]
+  max_file_bytes?: number
+  max_rounds?: number
+}
+
+declare interface EmbeddingConfig {
+  background?: boolean
+  model?: string
+  provider?: string
+  batch_size?: number
+  interval_secs?: number
+}
+
+declare interface KnowledgeConfig {
+  top_k?: number
+  chunk_chars?: number
+  overlap_chars?: number
+  min_score?: number
+  max_file_bytes?: number
+}
+
+declare interface SecretsStatus {
+  backend: SecretBackend
+  exists: boolean
+  unlocked: boolean
+}
+
+declare interface ModelPrice {
+  prompt: number
+  completion: number
+}
+
+declare interface RetryConfig {
+  max_attempts?: number
+  base_delay_ms?: number
+  max_delay_ms?: number
+}
+
+declare interface TimeoutConfig {
+  connect_ms?: number
+  request_ms?: number
+  model_ms?: number
+  first_token_ms?: number
+  chunk_ms?: number
+}
+
+declare interface TlsConfig {
+  ca_files?: string[]
+  reverse_proxy_cert?: string
+  accept_invalid_certs_hosts?: string[]
+}
+
+declare interface HttpConfig {
+  pool_max_idle_per_host?: number
+  pool_idle_timeout_ms?: number
+  tcp_keepalive_ms?: number
+  user_agent?: string
+}
+
+// 后端使用的代理配置
+declare interface ProxyConfigStruct {
+  method?: ProxyMethod
+  proxy?: Proxy
+  reverse_proxy?: ReverseProxy
+  no_proxy?: string[]
+  headers?: Record<string, string>
+}
+
+declare type ProviderConfig =
+  | { kind: 'openai' }
+  | {
+    kind: 'azure'
+    endpoint: string
+    api_version: string
+    deployments?: Record<string, string>
+  }
+  | { kind: 'compatible', base_url: string }
+
 declare interface ExportConfig {
   markdown: MarkdownConfig
 }
@@ -50,6 +172,28 @@ declare interface TopicConfig {
   use_first_conversation: boolean
   system_role: string
   temperature: number
+  provider?: string
+  // 固定使用的 API key 名
+  api_key?: string
+  // 可以调用的本地工具：calculator、current_time、read_file、search_history
+  tools?: string[]
+  // 启用的 MCP 服务名
+  mcp_servers?: string[]
+  // 发送前的内容审核策略，为空时不审核
+  moderation?: ModerationPolicy
+}
+
+declare type ModerationAction = 'log' | 'warn' | 'block'
+
+declare interface ModerationPolicy {
+  model?: string
+  // 没有单独配置的类别命中时的处理
+  action?: ModerationAction
+  // 按类别配置的处理，父类别同时作用于子类别
+  categories?: Record<string, ModerationAction>
+  threshold?: number
+  // 审核请求失败时拒绝发送
+  fail_closed?: boolean
 }
 
 declare interface PromptConfig {